@@ -0,0 +1,17 @@
+use std::process::Command;
+
+// capture the git commit at build time so the bot can report exactly what it's running
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|c| c.trim().to_string())
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=BOOT_GIT_COMMIT={}", commit);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
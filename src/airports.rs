@@ -0,0 +1,90 @@
+use crate::sqlite::{Address, Location};
+use failure::{err_msg, Error};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// EGLL or LHR as typed, lowercase is left to nominatim so "rome" is still rome
+pub fn is_code(loc: &str) -> bool {
+    matches!(loc.len(), 3 | 4)
+        && loc.starts_with(|c: char| c.is_ascii_uppercase())
+        && loc
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+// looks an ICAO (4 characters) or IATA (3 characters) code up in OurAirports'
+// airports.csv, see https://ourairports.com/data/, this reads the whole file
+// so it should only be called from a blocking task
+pub fn find(path: &str, code: &str) -> Result<Option<Location>, Error> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+
+    let header = split_csv(&lines.next().ok_or(err_msg("airports file is empty"))??);
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (Some(name), Some(lat), Some(lon), Some(country)) = (
+        column("name"),
+        column("latitude_deg"),
+        column("longitude_deg"),
+        column("iso_country"),
+    ) else {
+        return Err(err_msg(
+            "airports file is missing name/latitude_deg/longitude_deg/iso_country",
+        ));
+    };
+    let kind = column("type");
+    // older files only have ident and gps_code for ICAO codes
+    let codes: Vec<usize> = match code.len() {
+        4 => ["icao_code", "ident", "gps_code"]
+            .iter()
+            .filter_map(|c| column(c))
+            .collect(),
+        _ => column("iata_code").into_iter().collect(),
+    };
+
+    for line in lines {
+        let line = line?;
+        // cheap check before splitting every line of a big file
+        if !line.contains(code) {
+            continue;
+        }
+        let fields = split_csv(&line);
+        let field = |i: usize| fields.get(i).map(String::as_str).unwrap_or_default();
+        if kind.is_some_and(|k| field(k) == "closed") || !codes.iter().any(|&c| field(c) == code) {
+            continue;
+        }
+
+        return Ok(Some(Location {
+            lat: field(lat).to_string(),
+            lon: field(lon).to_string(),
+            address: Address {
+                city: Some(field(name).to_string()),
+                country: field(country).to_string(),
+                country_code: Some(field(country).to_lowercase()),
+            },
+        }));
+    }
+
+    Ok(None)
+}
+
+// fields can be quoted, with "" for a quote inside them
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
@@ -1,42 +1,349 @@
+use crate::airports;
+use crate::calc;
+use crate::colours;
+use crate::fig;
+use crate::http::{HttpClient, USER_AGENT};
 use crate::messages::Msg;
-use crate::sqlite::{Database, Location};
-use crate::{Bot, Notification, Req};
-use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use crate::music::{self, NowPlaying};
+use crate::numbers;
+use crate::reminders;
+use crate::script;
+use crate::settings::{BotConfig, Highlight, SpamConfig};
+use crate::sqlite::{CoinAlert, Database, Location, Reminder, SeenKind};
+use crate::stats::Stats;
+use crate::{send_or_drop, Bot, Notification, Origin, Req};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use chrono_humanize::{Accuracy, HumanTime, Tense};
+use chrono_tz::Tz;
 use failure::{bail, err_msg, Error};
 use futures::future::try_join_all;
 use itertools::Itertools;
 use kuchiki::traits::*;
 use openweathermap::blocking::weather;
 use openweathermap::{Clouds, CurrentWeather, Weather, Wind};
+use rand::{thread_rng, Rng};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Write;
 use std::str::FromStr;
-use std::time::Duration as STDDuration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as STDDuration, Instant};
 use tokio::spawn;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
 use urlencoding::encode;
 use webpage::{Webpage, WebpageOptions};
 
-enum Task<'a> {
+pub enum Task<'a> {
     Ignore,
     Message(&'a str),
     Seen(&'a str),
     Tell(&'a str, &'a str),
-    Weather(Option<&'a str>),
+    Weather(Option<&'a str>, Units),
+    Yesterday(Option<&'a str>, Units),
+    // someone else's saved location, .weather @nick
+    WeatherFor(&'a str, Units),
+    LocalTime(&'a str),
     Location(&'a str),
-    Coins(&'a str, &'a str),
+    // coin, time frame and whether to send the taller chart as well
+    Coins(&'a str, &'a str, bool),
     Lastfm(&'a str),
+    ListenBrainz(&'a str),
     Hang(&'a str),
+    // any single word, only a winning one counts
     HangGuess(&'a str),
+    // `.guess <word>`, a wrong one costs an attempt
+    Guess(&'a str),
     HangStart(&'a str),
     Forecast(Option<&'a str>),
+    Alerts(Option<&'a str>),
+    Rng(RngTask),
+    Calc(&'a str),
+    Uptime,
+    Ping,
+    Version,
+    Pause(bool),
+    Topic(Option<&'a str>),
+    Urban(&'a str),
+    FearGreed,
+    Stock(&'a str),
+    Suggest(&'static str),
+    Alias(AliasTask<'a>),
+    Highlight,
+    Title(&'a str),
+    CoinOverview,
+    Channels,
+    NowPlaying(NpTask<'a>),
+    Remind(RemindTask<'a>),
+    CoinAlert(CoinAlertTask<'a>),
+    OptOut(bool),
+    Mute(bool),
+    Reload,
+    Raw(&'a str),
+    TopDomains,
+    TopCommands,
+    Last(&'a str),
+    Grep(&'a str),
+    Search(&'a str),
+    Synonyms(&'a str),
+    Anagram(&'a str),
+    Scramble,
+    Trivia,
+    TriviaScores,
+    Fig(&'a str),
 }
 
-fn process_commands<'a>(nick: &'a str, msg: &'a str) -> Task<'a> {
+impl Task<'_> {
+    // the name used to refer to a command, i.e., in logs or config
+    fn name(&self) -> &'static str {
+        match self {
+            Task::Ignore => "ignore",
+            Task::Message(_) => "message",
+            Task::Seen(_) => "seen",
+            Task::Tell(_, _) => "tell",
+            Task::Weather(_, _) | Task::Yesterday(_, _) | Task::WeatherFor(_, _) => "weather",
+            Task::LocalTime(_) => "localtime",
+            Task::Location(_) => "loc",
+            Task::Coins(_, _, _) | Task::CoinOverview => "coins",
+            Task::Lastfm(_) | Task::NowPlaying(_) => "lastfm",
+            Task::Remind(_) => "remind",
+            Task::CoinAlert(_) => "coins",
+            Task::ListenBrainz(_) => "listenbrainz",
+            Task::Hang(_) | Task::HangGuess(_) | Task::Guess(_) | Task::HangStart(_) => "hang",
+            Task::Fig(_) => "fig",
+            Task::Forecast(_) => "forecast",
+            Task::Alerts(_) => "alerts",
+            Task::Rng(_) => "rng",
+            Task::Calc(_) => "calc",
+            Task::Uptime => "uptime",
+            Task::Ping => "ping",
+            Task::Version => "version",
+            Task::Pause(_) => "pause",
+            Task::Topic(_) => "topic",
+            Task::Urban(_) => "ud",
+            Task::FearGreed => "fng",
+            Task::Stock(_) => "stock",
+            Task::Suggest(_) => "suggest",
+            Task::Alias(_) => "alias",
+            Task::Highlight => "highlight",
+            Task::Channels => "channels",
+            Task::OptOut(_) => "optout",
+            Task::Mute(_) => "mute",
+            Task::Reload => "reload",
+            Task::Raw(_) => "raw",
+            Task::TopDomains => "topdomains",
+            Task::TopCommands => "topcommands",
+            Task::Last(_) => "last",
+            Task::Grep(_) => "grep",
+            Task::Search(_) => "search",
+            Task::Synonyms(_) => "syn",
+            Task::Anagram(_) => "anagram",
+            Task::Scramble => "scramble",
+            Task::Trivia | Task::TriviaScores => "trivia",
+            Task::Title(_) => "title",
+        }
+    }
+
+    // commands that make outbound requests, these are subject to the cooldown
+    fn expensive(&self) -> bool {
+        matches!(
+            self,
+            Task::Weather(_, _)
+                | Task::Yesterday(_, _)
+                | Task::WeatherFor(_, _)
+                | Task::LocalTime(_)
+                | Task::Forecast(_)
+                | Task::Alerts(_)
+                | Task::Location(_)
+                | Task::Coins(_, _, _)
+                | Task::Lastfm(_)
+                | Task::ListenBrainz(_)
+                | Task::NowPlaying(NpTask::Show(_))
+                | Task::Urban(_)
+                | Task::Synonyms(_)
+                | Task::FearGreed
+                | Task::Stock(_)
+                | Task::Title(_)
+                | Task::CoinOverview
+        )
+    }
+}
+
+pub enum AliasTask<'a> {
+    // name, expansion, channel wide
+    Add(&'a str, &'a str, bool),
+    Del(&'a str, bool),
+}
+
+pub enum RemindTask<'a> {
+    // schedule, HH:MM, timezone (utc if it's not given), message
+    Every(&'a str, &'a str, Option<&'a str>, &'a str),
+    Stop(&'a str),
+    List,
+}
+
+pub enum CoinAlertTask<'a> {
+    // coin, whether it's above, target
+    Add(&'a str, bool, f32),
+    Stop(&'a str),
+    List,
+}
+
+pub enum NpTask<'a> {
+    // whose, defaults to whoever asked
+    Show(Option<&'a str>),
+    // service, account
+    Set(&'a str, &'a str),
+}
+
+pub enum RngTask {
+    Flip,
+    // inclusive range
+    Pick(i64, i64),
+}
+
+// anything bigger than this is just someone trying to make the bot say a big number
+const RNG_MAX: i64 = 1_000_000_000;
+
+fn parse_range(range: &str) -> Option<(i64, i64)> {
+    // skip the first character so a leading '-' is treated as a negative number
+    let split = range.get(1..)?.find('-')? + 1;
+    let (low, high) = (&range[..split], &range[split + 1..]);
+    let low = low.trim().parse::<i64>().ok()?;
+    let high = high.trim().parse::<i64>().ok()?;
+
+    if low >= high || low < -RNG_MAX || high > RNG_MAX {
+        return None;
+    }
+
+    Some((low, high))
+}
+
+// splits arguments on whitespace except where they're wrapped in double quotes,
+// i.e., `"first arg" second` is ["first arg", "second"], an unbalanced quote
+// runs to the end of the input
+pub fn split_args(args: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut rest = args.trim_start();
+
+    while !rest.is_empty() {
+        let (arg, remainder) = match rest.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => match rest.find(char::is_whitespace) {
+                Some(end) => (&rest[..end], &rest[end..]),
+                None => (rest, ""),
+            },
+        };
+
+        // `""` is kept as an empty argument
+        split.push(arg);
+        rest = remainder.trim_start();
+    }
+
+    split
+}
+
+const HELP: &str = "Commands: repo | version | uptime | ping | topic | seen <nick> | localtime <nick> | last <nick> | grep <text> | optout | optin | mute | unmute | tell <nick> <message> | remind every <when> <HH:MM> <message> | weather <location|@nick> [yesterday] | alerts [location] \
+                    | loc <location> | <btc(gbp|eur)|eth(eur)|ltc|xmr|doge> \
+                    <day|week|fortnight|month|quarter|year> [detail] | coins | coinalert <coin> <above|below> <price> | topdomains | topcommands \
+                    | fng | stock <symbol> | np [nick] | lbz <user> | hang <short|medium|long> | guess <word> | fig <text> | anagram <letters> | scramble | trivia [top] | flip | pick <low-high> | roll [sides] | calc <expr> | syn <word>";
+
+const REMIND_HINT: &str =
+    "Hint: remind every <daily|weekdays|mon-sun> <HH:MM> [timezone, i.e., Europe/London] <message> | remind list | remind stop <id>";
+
+const COIN_ALERT_HINT: &str =
+    "Hint: coinalert <btc(gbp|eur)|eth(eur)|ltc|xmr|doge> <above|below> <price> | coinalert list | coinalert stop <id>";
+
+pub const COINS: [&str; 11] = [
+    "btc", "bitcoin", "btcgbp", "btceur", // bitcoin
+    "eth", "ethereum", "etheur", // ethereum
+    "ltc",    // litecoin
+    "xmr", "monero", // monero
+    "doge",   // dogecoin
+];
+
+// the time frames get_coins knows about
+pub const COIN_TIME_FRAMES: [&str; 8] = ["1d", "7d", "14d", "31d", "90d", "1y", "3y", "5y"];
+
+// built-in names for the time frames, [bot] coin_times can add to these or
+// point them somewhere else
+const COIN_TIMES: [(&str, &str); 23] = [
+    ("1d", "1d"),
+    ("day", "1d"),
+    ("24h", "1d"),
+    ("spot", "1d"),
+    ("7d", "7d"),
+    ("w", "7d"),
+    ("1w", "7d"),
+    ("week", "7d"),
+    ("weekly", "7d"),
+    ("14d", "14d"),
+    ("2w", "14d"),
+    ("fortnight", "14d"),
+    ("fortnightly", "14d"),
+    ("31d", "31d"),
+    ("30d", "31d"),
+    ("month", "31d"),
+    ("90d", "90d"),
+    ("3m", "90d"),
+    ("quarter", "90d"),
+    ("1y", "1y"),
+    ("year", "1y"),
+    ("3y", "3y"),
+    ("5y", "5y"),
+];
+
+// a built-in coin, or whichever one a [bot] coin_aliases entry points to
+fn coin_name(name: &str, aliases: &HashMap<String, String>) -> Option<&'static str> {
+    let name = aliases
+        .iter()
+        .find(|(a, _)| a.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, c)| c.as_str());
+    COINS.iter().find(|c| c.eq_ignore_ascii_case(name)).copied()
+}
+
+// the time frame get_coins should use, config takes priority over the built-ins
+fn coin_time(time: &str, extra: &HashMap<String, String>) -> Option<&'static str> {
+    let canonical = extra
+        .iter()
+        .find(|(a, _)| a.eq_ignore_ascii_case(time))
+        .map(|(_, t)| t.as_str())
+        .or_else(|| {
+            COIN_TIMES
+                .iter()
+                .find(|(a, _)| a.eq_ignore_ascii_case(time))
+                .map(|(_, t)| *t)
+        })?;
+    COIN_TIME_FRAMES
+        .iter()
+        .find(|t| t.eq_ignore_ascii_case(canonical))
+        .copied()
+}
+
+const HANG_LENGTHS: [&str; 3] = ["short", "medium", "long"];
+
+// turns a line from irc into what the bot should do about it without touching
+// anything else, `nick` is our own nick lowercased, what it promises:
+// - `.cmd`, `!cmd` and `nick: cmd` are commands, a lone `.` or `!` isn't
+// - our nick on its own is Task::Highlight
+// - a command missing what it needs is Task::Message with a hint
+// - coins (and [bot] coin_aliases for them) take an optional time frame that's
+//   normalised to one of COIN_TIME_FRAMES through COIN_TIMES and [bot]
+//   coin_times, anything unknown is 1d
+// - anything that isn't a command is Task::Ignore unless it's a single token,
+//   then a single lowercase letter is Task::Hang and anything else (except a
+//   hangman difficulty) is Task::HangGuess, a word only costs an attempt when
+//   it's a Task::Guess
+// - alongside the task is whether the line was addressed to us, i.e., it had a
+//   prefix or our nick, a hangman guess or anything ignored never is
+pub fn process_commands<'a>(nick: &'a str, msg: &'a str, settings: &BotConfig) -> (Task<'a>, bool) {
     let mut tokens = msg.split_whitespace();
     let next = tokens.next();
 
@@ -53,7 +360,8 @@ fn process_commands<'a>(nick: &'a str, msg: &'a str) -> Task<'a> {
             c if c.starts_with('!') && c.len() > 1 => c.strip_prefix('!'),
             c if c.to_lowercase().starts_with(nick) => match tokens.next() {
                 Some(n) => Some(n),
-                None => Some("help"),
+                // what happens here is up to the config
+                None => return (Task::Highlight, true),
             },
             _ => None,
         }
@@ -62,8 +370,16 @@ fn process_commands<'a>(nick: &'a str, msg: &'a str) -> Task<'a> {
     // if there's no '`boot:` help' or '`.`help' there's nothing
     // left to do, so continue with our day
     if bot_prefix.is_none() {
-        // todo: it's accepting short/medium/long here when it shouldn't
-        return match next {
+        let task = match next {
+            // the difficulties only ever start games, i.e., `.hang medium`,
+            // they should never be treated as a guess
+            Some(t)
+                if HANG_LENGTHS
+                    .iter()
+                    .any(|l| l.eq_ignore_ascii_case(t.trim())) =>
+            {
+                Task::Ignore
+            }
             Some(t) if tokens.count() == 0 => {
                 let letter = match t.trim().chars().next() {
                     Some(x) if t.trim().len() == 1 && matches!(x, 'a'..='z') => true,
@@ -78,32 +394,42 @@ fn process_commands<'a>(nick: &'a str, msg: &'a str) -> Task<'a> {
             }
             _ => Task::Ignore,
         };
+        return (task, false);
     }
 
-    let coins = [
-        "btc",
-        "bitcoin",
-        "btcgbp", // bitcoin
-        "eth",
-        "ethereum", // ethereum
-        "ltc",      // litecoin
-        "xmr",
-        "monero", // monero
-        "doge",   // dogecoin
-        "coins",
-        "shitcoins",
-    ];
-
-    match bot_prefix.unwrap() {
-        "help" | "man" | "manual" => {
-            let response =
-                "Commands: repo | seen <nick> | tell <nick> <message> | weather <location> \
-                        | loc <location> | <btc(gbp)|eth|ltc|xmr|doge> \
-                        <day|week|fortnight|month|year> \
-                        | hang <short|medium|long>";
-            Task::Message(response)
-        }
+    let task = match bot_prefix.unwrap() {
+        "help" | "man" | "manual" => Task::Message(HELP),
         "repo" | "git" => Task::Message("https://github.com/niall-/boot"),
+        "uptime" | "stats" => Task::Uptime,
+        "ping" => Task::Ping,
+        "version" => Task::Version,
+        "topic" => Task::Topic(tokens.remainder().map(str::trim).filter(|t| !t.is_empty())),
+        "channels" => Task::Channels,
+        "topdomains" => Task::TopDomains,
+        "topcommands" => Task::TopCommands,
+        "last" => match tokens.next() {
+            Some(nick) => Task::Last(nick),
+            None => Task::Message("Hint: last <nick>"),
+        },
+        "search" => match tokens.remainder().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(term) => Task::Search(term),
+            None => Task::Message("Hint: search <text>"),
+        },
+        "grep" => match tokens.remainder().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(term) => Task::Grep(term),
+            None => Task::Message("Hint: grep <text>"),
+        },
+        "reload" => Task::Reload,
+        "raw" => match tokens.remainder() {
+            Some(line) if !line.trim().is_empty() => Task::Raw(line.trim()),
+            _ => Task::Message("Hint: raw <irc line>"),
+        },
+        "pause" => Task::Pause(true),
+        "resume" => Task::Pause(false),
+        "optout" => Task::OptOut(true),
+        "optin" => Task::OptOut(false),
+        "mute" => Task::Mute(true),
+        "unmute" => Task::Mute(false),
         "seen" => match tokens.next() {
             Some(nick) if !nick.is_empty() => Task::Seen(nick),
             Some(_) => Task::Message("Hint: seen <nick>"),
@@ -116,60 +442,161 @@ fn process_commands<'a>(nick: &'a str, msg: &'a str) -> Task<'a> {
             },
             None => Task::Message("Hint: tell <nick> <message>"),
         },
-        "weather" => match tokens.remainder() {
-            Some(loc) if !loc.trim().is_empty() => Task::Weather(Some(loc.trim())),
-            _ => Task::Weather(None),
+        "weather" => {
+            let (loc, units) = split_units(tokens.remainder().unwrap_or_default());
+            match loc.and_then(|l| l.strip_prefix('@')).map(str::trim) {
+                Some("") => Task::Message("Hint: weather @<nick>"),
+                Some(nick) => Task::WeatherFor(nick, units),
+                None => match split_yesterday(loc) {
+                    Some(loc) => Task::Yesterday(loc, units),
+                    None => Task::Weather(loc, units),
+                },
+            }
+        }
+        "localtime" | "time" => match tokens.next() {
+            Some(n) => Task::LocalTime(n),
+            None => Task::Message("Hint: localtime <nick>"),
         },
         "forecast" => Task::Forecast(tokens.remainder().map(str::trim).filter(|v| !v.is_empty())),
+        "alerts" => Task::Alerts(tokens.remainder().map(str::trim).filter(|v| !v.is_empty())),
+        "fng" | "feargreed" => Task::FearGreed,
+        "title" => match tokens.next() {
+            Some(url) => Task::Title(url),
+            None => Task::Message("Hint: title <url>"),
+        },
+        "alias" => {
+            let hint = "Hint: alias <add|chanadd> <name> <command> | alias <del|chandel> <name>";
+            match (tokens.next(), tokens.next()) {
+                (Some(op @ ("add" | "chanadd")), Some(name)) if is_alias_name(name) => {
+                    match tokens.remainder().map(str::trim) {
+                        Some(expansion) if !expansion.is_empty() => Task::Alias(AliasTask::Add(
+                            name,
+                            expansion.trim_start_matches(['.', '!']),
+                            op == "chanadd",
+                        )),
+                        _ => Task::Message(hint),
+                    }
+                }
+                (Some(op @ ("del" | "chandel")), Some(name)) => {
+                    Task::Alias(AliasTask::Del(name, op == "chandel"))
+                }
+                _ => Task::Message(hint),
+            }
+        }
+        "stock" | "stocks" => match tokens.next() {
+            Some(symbol) if is_stock_symbol(symbol) => Task::Stock(symbol),
+            _ => Task::Message("Hint: stock <symbol>, i.e., stock AAPL"),
+        },
+        "syn" | "synonyms" => match tokens.next() {
+            Some(word) => Task::Synonyms(word),
+            None => Task::Message("Hint: syn <word>"),
+        },
+        "ud" | "urban" => match tokens.remainder() {
+            Some(term) if !term.trim().is_empty() => Task::Urban(term.trim()),
+            _ => Task::Message("Hint: ud <term>"),
+        },
         "loc" | "location" => match tokens.remainder() {
             Some(loc) if !loc.trim().is_empty() => Task::Location(loc.trim()),
             _ => Task::Message("Hint: loc|location <location>"),
         },
+        "coins" | "shitcoins" => Task::CoinOverview,
         // TODO: support .spot for current spot price
-        c if coins.iter().any(|e| e == &c) => {
-            let coin_times = [
-                "1d",
-                "day",
-                "24h",
-                "7d",
-                "w",
-                "1w",
-                "week",
-                "weekly",
-                "14d",
-                "2w",
-                "fortnight",
-                "fortnightly",
-                "31d",
-                "30d",
-                "month",
-                "year",
-                "1y",
-                "3y",
-                "5y",
-                "spot",
-            ];
-            let coin_time = match tokens.next() {
-                Some(n) if coin_times.iter().any(|e| e.eq_ignore_ascii_case(n)) => {
-                    match n.to_lowercase().as_ref() {
-                        "7d" | "w" | "1w" | "week" | "weekly" => "7d",
-                        "14d" | "2w" | "fortnight" | "fortnightly" => "14d",
-                        "31d" | "30d" | "month" => "31d",
-                        "year" => "1y",
-                        "3y" => "3y",
-                        "5y" => "5y",
-                        _ => "1d",
-                    }
-                }
-                Some(_) => "1d",
-                None => "1d",
+        c if coin_name(c, &settings.coin_aliases).is_some() => {
+            // .btc week detail, or just .btc detail for the last day
+            let is_detail = |t: Option<&str>| matches!(t, Some("detail" | "detailed"));
+            let time = tokens.next();
+            let (time, detail) = match is_detail(time) {
+                true => (None, true),
+                false => (time, is_detail(tokens.next())),
             };
-            Task::Coins(c, coin_time)
+            let time_frame = time
+                .and_then(|t| coin_time(t, &settings.coin_times))
+                .unwrap_or("1d");
+            Task::Coins(
+                coin_name(c, &settings.coin_aliases).unwrap(),
+                time_frame,
+                detail,
+            )
         }
+        "remind" | "remindme" => match tokens.next() {
+            Some("every") => match (tokens.next(), tokens.next(), tokens.remainder()) {
+                (Some(schedule), Some(time), Some(rest)) => {
+                    match reminders::split_timezone(rest.trim()) {
+                        (_, "") => Task::Message(REMIND_HINT),
+                        (timezone, message) => {
+                            Task::Remind(RemindTask::Every(schedule, time, timezone, message))
+                        }
+                    }
+                }
+                _ => Task::Message(REMIND_HINT),
+            },
+            Some("stop" | "cancel") => match tokens.next() {
+                Some(id) => Task::Remind(RemindTask::Stop(id.trim_start_matches('#'))),
+                None => Task::Message("Hint: remind stop <id>"),
+            },
+            Some("list") => Task::Remind(RemindTask::List),
+            _ => Task::Message(REMIND_HINT),
+        },
+        "reminders" => Task::Remind(RemindTask::List),
+        "fig" | "bigtext" => match tokens.remainder().map(str::trim) {
+            Some(t) if !t.is_empty() => Task::Fig(t),
+            _ => Task::Message("Hint: fig <text>"),
+        },
+        "coinalert" | "coinalerts" => match tokens.next().map(|t| t.to_lowercase()).as_deref() {
+            Some("list") | None => Task::CoinAlert(CoinAlertTask::List),
+            Some("stop" | "cancel") => match tokens.next() {
+                Some(id) => Task::CoinAlert(CoinAlertTask::Stop(id.trim_start_matches('#'))),
+                None => Task::Message("Hint: coinalert stop <id>"),
+            },
+            Some(c) => {
+                let coin = coin_name(c, &settings.coin_aliases);
+                let above = match tokens.next() {
+                    Some(d) if d.eq_ignore_ascii_case("above") => Some(true),
+                    Some(d) if d.eq_ignore_ascii_case("below") => Some(false),
+                    _ => None,
+                };
+                // 50,000 and $50000 are both fine
+                let target = tokens
+                    .next()
+                    .map(|t| {
+                        t.trim_start_matches(|c: char| !c.is_ascii_digit())
+                            .replace(',', "")
+                    })
+                    .and_then(|t| f32::from_str(&t).ok())
+                    .filter(|t| t.is_finite() && *t > 0.0);
+                match (coin, above, target) {
+                    (Some(coin), Some(above), Some(target)) => {
+                        Task::CoinAlert(CoinAlertTask::Add(coin, above, target))
+                    }
+                    _ => Task::Message(COIN_ALERT_HINT),
+                }
+            }
+        },
+        "np" | "nowplaying" => match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some("set"), Some(account), None) => Task::NowPlaying(NpTask::Set("lastfm", account)),
+            (Some("set"), Some(service), Some(account)) => {
+                Task::NowPlaying(NpTask::Set(service, account))
+            }
+            (Some("set"), None, _) => Task::Message("Hint: np set [service] <username>"),
+            (nick, _, _) => Task::NowPlaying(NpTask::Show(nick)),
+        },
+        "lbz" | "listenbrainz" => match tokens.next() {
+            Some(user) => Task::ListenBrainz(user.trim()),
+            None => Task::Message("Hint: lbz <username>"),
+        },
         "lastfm" => match tokens.next() {
             Some(nick) => Task::Lastfm(nick.trim()),
             None => Task::Message("noob"),
         },
+        "anagram" => match tokens.next() {
+            Some(l) if l.chars().all(|c| c.is_alphabetic()) => Task::Anagram(l),
+            _ => Task::Message("Hint: anagram <letters>"),
+        },
+        "scramble" => Task::Scramble,
+        "trivia" => match tokens.next() {
+            Some("top" | "scores") => Task::TriviaScores,
+            _ => Task::Trivia,
+        },
         "hang" => match tokens.next() {
             Some(l) => match l.trim().to_lowercase().as_ref() {
                 "short" => Task::HangStart("short"),
@@ -179,28 +606,657 @@ fn process_commands<'a>(nick: &'a str, msg: &'a str) -> Task<'a> {
             },
             None => Task::HangStart(""),
         },
-        _ => Task::Ignore,
+        "guess" => match tokens.next() {
+            Some(w) => Task::Guess(w),
+            None => Task::Message("Hint: guess <word>"),
+        },
+        "flip" => Task::Rng(RngTask::Flip),
+        "pick" => match tokens.next().and_then(parse_range) {
+            Some((low, high)) => Task::Rng(RngTask::Pick(low, high)),
+            None => Task::Message("Hint: pick <low-high>"),
+        },
+        "roll" => match tokens.next() {
+            Some(n) => match n.parse::<i64>() {
+                Ok(sides) if (2..=RNG_MAX).contains(&sides) => Task::Rng(RngTask::Pick(1, sides)),
+                _ => Task::Message("Hint: roll [sides]"),
+            },
+            None => Task::Rng(RngTask::Pick(1, 6)),
+        },
+        "calc" => match tokens.remainder() {
+            Some(expr) if !expr.trim().is_empty() => Task::Calc(expr.trim()),
+            _ => Task::Message("Hint: calc <expr>"),
+        },
+        c => match suggest_command(c) {
+            Some(s) => Task::Suggest(s),
+            None => Task::Ignore,
+        },
+    };
+    (task, true)
+}
+
+// whether `.name` is already a command, coins included
+pub fn is_builtin(name: &str, settings: &BotConfig) -> bool {
+    let name = name.to_lowercase();
+    KNOWN_COMMANDS.contains(&name.as_str())
+        || !matches!(
+            process_commands("", &format!(".{}", name), settings).0,
+            Task::Ignore | Task::Suggest(_)
+        )
+}
+
+// `.name args` for the script, only for names that could be a command so
+// chatter like "..." never gets as far as running anything
+fn script_command(content: &str) -> Option<(&str, &str)> {
+    let content = content.trim().strip_prefix(['.', '!'])?;
+    let (name, args) = content
+        .split_once(char::is_whitespace)
+        .unwrap_or((content, ""));
+    (!name.is_empty() && is_alias_name(name)).then(|| (name, args.trim()))
+}
+
+fn is_alias_name(name: &str) -> bool {
+    name.len() <= 16 && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// aliases can refer to other aliases but only this deep
+const MAX_ALIAS_DEPTH: usize = 3;
+const MAX_ALIAS_LEN: usize = 200;
+
+// replaces `.name args` with `.expansion args` if the user, or failing that the
+// channel, has an alias called name
+fn expand_alias(db: &Database, nick: &str, channel: &str, content: &str) -> String {
+    let mut content = content.trim().to_string();
+    let mut expanded: Vec<String> = Vec::new();
+
+    for _ in 0..MAX_ALIAS_DEPTH {
+        let Some(prefix) = content.chars().next().filter(|c| matches!(c, '.' | '!')) else {
+            break;
+        };
+        let (name, rest) = match content[1..].split_once(char::is_whitespace) {
+            Some((n, r)) => (n.to_lowercase(), r.to_string()),
+            None => (content[1..].to_lowercase(), String::new()),
+        };
+        // a loop, leave it as it is
+        if expanded.contains(&name) {
+            break;
+        }
+
+        let expansion = match db.check_alias(nick, &name) {
+            Ok(Some(e)) => e,
+            Ok(None) => match db.check_alias(channel, &name) {
+                Ok(Some(e)) => e,
+                _ => break,
+            },
+            Err(err) => {
+                println!("SQL error checking alias: {}", err);
+                break;
+            }
+        };
+
+        content = format!("{}{} {}", prefix, expansion, rest)
+            .trim()
+            .to_string();
+        expanded.push(name);
     }
+
+    content
+}
+
+// commands we'll suggest when someone makes a typo, admin commands and aliases
+// are left out on purpose
+const KNOWN_COMMANDS: [&str; 47] = [
+    "help",
+    "repo",
+    "uptime",
+    "ping",
+    "version",
+    "topic",
+    "seen",
+    "last",
+    "grep",
+    "optout",
+    "optin",
+    "mute",
+    "unmute",
+    "tell",
+    "remind",
+    "reminders",
+    "fig",
+    "bigtext",
+    "coinalert",
+    "coinalerts",
+    "weather",
+    "forecast",
+    "alerts",
+    "localtime",
+    "fng",
+    "stock",
+    "loc",
+    "btc",
+    "eth",
+    "ltc",
+    "xmr",
+    "doge",
+    "coins",
+    "topdomains",
+    "topcommands",
+    "lastfm",
+    "lbz",
+    "hang",
+    "guess",
+    "anagram",
+    "scramble",
+    "trivia",
+    "flip",
+    "pick",
+    "roll",
+    "calc",
+    "syn",
+];
+
+fn suggest_command(command: &str) -> Option<&'static str> {
+    let command = command.to_lowercase();
+
+    KNOWN_COMMANDS
+        .iter()
+        .map(|c| (c, levenshtein(&command, c)))
+        // short names are too easy to hit by accident, i.e., .btw
+        .filter(|(c, d)| {
+            let threshold = match c.len() {
+                0..=3 => 0,
+                4..=6 => 1,
+                _ => 2,
+            };
+            *d > 0 && *d <= threshold
+        })
+        .min_by_key(|(_, d)| *d)
+        .map(|(c, _)| *c)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            let value = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+            current.push(value);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+// state kept by the main loop between messages
+#[derive(Default)]
+pub struct State {
+    pub stats: Arc<Stats>,
+    cooldowns: HashMap<(String, &'static str), Instant>,
+    // while paused commands are ignored, shared so background tasks can check it too
+    pub paused: Arc<AtomicBool>,
+    // channel (lowercase) -> current topic
+    pub topics: HashMap<String, String>,
+    pub fear_greed: Arc<Cache<String>>,
+    // channel (lowercase) -> when we last greeted someone there
+    greeted_channels: HashMap<String, Instant>,
+    // (channel, nick) (lowercase) -> when we last greeted them
+    greeted_nicks: HashMap<(String, String), Instant>,
+    // nick (lowercase) -> when their recent messages were sent
+    recent: HashMap<String, VecDeque<Instant>>,
+    // nick (lowercase) -> when they can be listened to again
+    muted: HashMap<String, Instant>,
+    // channel (lowercase) -> the last few things said there
+    history: HashMap<String, VecDeque<Line>>,
+    // (channel, command) -> uses not yet written to command_stats and the
+    // last of them, anything here when we shut down isn't counted
+    command_uses: HashMap<(String, &'static str), (u32, DateTime<Utc>)>,
+    command_uses_written: Option<Instant>,
+}
+
+// something said in a channel, only kept in memory for .last and .grep
+pub struct Line {
+    nick: String,
+    content: String,
+    time: DateTime<Utc>,
+}
+
+impl Line {
+    fn describe(&self) -> String {
+        let ago = HumanTime::from(Utc::now().signed_duration_since(self.time))
+            .to_text_en(Accuracy::Rough, Tense::Past);
+        format!("<{}> {} ({})", self.nick, self.content, ago)
+    }
+}
+
+// a value that's only worth fetching every so often, shared with the tasks
+// that fetch it
+pub struct Cache<T> {
+    value: Mutex<Option<(Instant, T)>>,
+}
+
+impl<T> Default for Cache<T> {
+    fn default() -> Self {
+        Self {
+            value: Mutex::new(None),
+        }
+    }
+}
+
+impl<T: Clone> Cache<T> {
+    // the cached value if it was set within the last `age`
+    pub fn get(&self, age: STDDuration) -> Option<T> {
+        match &*self.value.lock().unwrap() {
+            Some((set, value)) if set.elapsed() < age => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn set(&self, value: T) {
+        *self.value.lock().unwrap() = Some((Instant::now(), value));
+    }
+}
+
+// a netsplit can rejoin dozens of users at once, only greet once in this many
+// seconds per channel and never greet the same user more than once an hour
+const GREET_CHANNEL_INTERVAL: u64 = 10;
+const GREET_NICK_INTERVAL: u64 = 60 * 60;
+
+impl State {
+    // commands are counted in memory and written in batches so counting them
+    // doesn't add a database write to every command
+    fn count_command(&mut self, db: &Database, channel: &str, command: &'static str) {
+        let channel = match channel.starts_with('#') {
+            true => channel.to_lowercase(),
+            false => "private".to_string(),
+        };
+        let uses = self
+            .command_uses
+            .entry((channel, command))
+            .or_insert((0, Utc::now()));
+        uses.0 += 1;
+        uses.1 = Utc::now();
+
+        let written = *self.command_uses_written.get_or_insert_with(Instant::now);
+        if self.command_uses.len() >= COMMAND_STATS_BATCH
+            || written.elapsed() >= COMMAND_STATS_INTERVAL
+        {
+            self.write_command_uses(db);
+        }
+    }
+
+    fn write_command_uses(&mut self, db: &Database) {
+        self.command_uses_written = Some(Instant::now());
+        if self.command_uses.is_empty() {
+            return;
+        }
+
+        let uses: Vec<_> = self
+            .command_uses
+            .drain()
+            .map(|((channel, command), (count, last))| {
+                (channel, command.to_string(), count, last.to_rfc3339())
+            })
+            .collect();
+        if let Err(err) = db.add_command_uses(&uses) {
+            println!("SQL error counting commands: {}", err);
+        }
+    }
+
+    // keeps track of how often someone is talking, returns true if they're
+    // flooding and should be ignored for a while
+    fn flooding(&mut self, nick: &str, spam: &SpamConfig) -> bool {
+        if spam.lines == 0 {
+            return false;
+        }
+        if self.is_muted(nick) {
+            return true;
+        }
+
+        let now = Instant::now();
+        let window = STDDuration::from_secs(spam.seconds);
+        let key = nick.to_lowercase();
+
+        // don't let the map grow forever
+        if self.recent.len() > 1000 {
+            self.recent.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < window)
+            });
+        }
+        self.muted.retain(|_, until| *until > now);
+
+        let times = self.recent.entry(key.clone()).or_default();
+        times.push_back(now);
+        while times
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= window)
+        {
+            times.pop_front();
+        }
+
+        if times.len() > spam.lines {
+            println!(
+                "{} sent {} messages in {}s, ignoring them for {}s",
+                nick,
+                times.len(),
+                spam.seconds,
+                spam.mute
+            );
+            self.recent.remove(&key);
+            self.muted
+                .insert(key, now + STDDuration::from_secs(spam.mute));
+            return true;
+        }
+
+        false
+    }
+
+    pub fn is_muted(&self, nick: &str) -> bool {
+        self.muted
+            .get(&nick.to_lowercase())
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    // the greeting to send when `nick` joins `channel`, if there is one and we
+    // haven't greeted too recently
+    pub fn greeting(&mut self, settings: &BotConfig, channel: &str, nick: &str) -> Option<String> {
+        if self.paused.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let greeting = settings.greeting(channel)?;
+        let now = Instant::now();
+        let channel_interval = STDDuration::from_secs(GREET_CHANNEL_INTERVAL);
+        let nick_interval = STDDuration::from_secs(GREET_NICK_INTERVAL);
+
+        let channel_key = channel.to_lowercase();
+        let nick_key = (channel_key.clone(), nick.to_lowercase());
+
+        if let Some(last) = self.greeted_channels.get(&channel_key) {
+            if now.duration_since(*last) < channel_interval {
+                return None;
+            }
+        }
+        if let Some(last) = self.greeted_nicks.get(&nick_key) {
+            if now.duration_since(*last) < nick_interval {
+                return None;
+            }
+        }
+
+        if self.greeted_nicks.len() > 1000 {
+            self.greeted_nicks
+                .retain(|_, last| now.duration_since(*last) < nick_interval);
+        }
+        self.greeted_channels.insert(channel_key, now);
+        self.greeted_nicks.insert(nick_key, now);
+
+        Some(greeting.replace("{nick}", nick))
+    }
+
+    // keeps at most `size` lines per channel, the oldest are forgotten first
+    pub fn remember(&mut self, channel: &str, nick: &str, content: &str, size: usize) {
+        if size == 0 {
+            return;
+        }
+
+        let lines = self.history.entry(channel.to_lowercase()).or_default();
+        lines.push_back(Line {
+            nick: nick.to_string(),
+            content: content.to_string(),
+            time: Utc::now(),
+        });
+        while lines.len() > size {
+            lines.pop_front();
+        }
+    }
+
+    // the last `count` lines from `nick`, oldest first
+    fn last(&self, channel: &str, nick: &str, count: usize) -> Vec<&Line> {
+        let Some(lines) = self.history.get(&channel.to_lowercase()) else {
+            return Vec::new();
+        };
+
+        let mut last: Vec<_> = lines
+            .iter()
+            .rev()
+            .filter(|l| l.nick.eq_ignore_ascii_case(nick))
+            .take(count)
+            .collect();
+        last.reverse();
+        last
+    }
+
+    // the most recent line containing `term`
+    fn grep(&self, channel: &str, term: &str) -> Option<&Line> {
+        let term = term.to_lowercase();
+        self.history
+            .get(&channel.to_lowercase())?
+            .iter()
+            .rev()
+            .find(|l| l.content.to_lowercase().contains(&term))
+    }
+
+    // returns true if the user has used this command within the last `cooldown`
+    // seconds, otherwise starts a new cooldown for them
+    fn on_cooldown(&mut self, nick: &str, command: &'static str, cooldown: u64) -> bool {
+        if cooldown == 0 {
+            return false;
+        }
+
+        let cooldown = STDDuration::from_secs(cooldown);
+        let now = Instant::now();
+
+        // don't let the map grow forever
+        if self.cooldowns.len() > 1000 {
+            self.cooldowns
+                .retain(|_, last| now.duration_since(*last) < cooldown);
+        }
+
+        let key = (nick.to_lowercase(), command);
+        match self.cooldowns.get(&key) {
+            Some(last) if now.duration_since(*last) < cooldown => true,
+            _ => {
+                self.cooldowns.insert(key, now);
+                false
+            }
+        }
+    }
+}
+
+// for commands that need an api key the operator hasn't set, saying so is
+// better than leaving people wondering whether the bot is broken
+fn not_configured(tx: &mpsc::Sender<Bot>, msg: &Msg, settings: &BotConfig, feature: &str) {
+    if settings.explain_unconfigured {
+        let response = format!("{} isn't configured on this bot", feature);
+        reply(tx, msg, response);
+    }
+}
+
+// replies go through the main loop so they're split and paced like everything
+// else we send
+fn reply(tx: &mpsc::Sender<Bot>, msg: &Msg, response: impl Into<String>) {
+    send_or_drop(tx, Bot::Privmsg(msg.origin(), response.into()));
+}
+
+// admins are nick!user@host masks, anyone can take a nick but only services
+// hand out cloaks so there's no hostmask there's no admin
+pub fn is_admin(settings: &BotConfig, msg: &Msg) -> bool {
+    let Some(hostmask) = &msg.hostmask else {
+        return false;
+    };
+    let user = format!("{}!{}", msg.source, hostmask).to_lowercase();
+    settings
+        .admins
+        .iter()
+        .any(|mask| mask_matches(&mask.to_lowercase(), &user))
+}
+
+// * matches any run of characters and ? any single one
+fn mask_matches(mask: &str, text: &str) -> bool {
+    let (mask, text) = (mask.as_bytes(), text.as_bytes());
+    // where to go back to if what follows the last * doesn't match
+    let (mut m, mut t, mut star) = (0, 0, None);
+    while t < text.len() {
+        match mask.get(m) {
+            Some(b'*') => {
+                star = Some((m, t));
+                m += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                m += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((sm, st)) => {
+                    star = Some((sm, st + 1));
+                    m = sm + 1;
+                    t = st + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    mask[m..].iter().all(|&c| c == b'*')
+}
+
+fn add_reminder(
+    db: &Database,
+    msg: &Msg,
+    schedule: reminders::Schedule,
+    time: NaiveTime,
+    timezone: Tz,
+    message: &str,
+) -> String {
+    match db.reminders_for(&msg.source) {
+        Ok(r) if r.len() as u32 >= reminders::MAX_REMINDERS => {
+            return format!(
+                "You already have {} reminders, remind stop <id> one first",
+                r.len()
+            );
+        }
+        Ok(_) => (),
+        Err(err) => {
+            println!("SQL error checking reminders: {}", err);
+            return "SQL error".to_string();
+        }
+    }
+
+    let now = Utc::now();
+    let next = reminders::next_fire(schedule, time, timezone, now);
+    let entry = Reminder {
+        id: 0,
+        nick: msg.source.to_string(),
+        channel: msg.target.to_string(),
+        message: message.to_string(),
+        schedule: schedule.as_str(),
+        time: time.format("%H:%M").to_string(),
+        timezone: timezone.name().to_string(),
+        next: next.timestamp(),
+    };
+    match db.add_reminder(&entry) {
+        Ok(id) => format!(
+            "Ok, I'll remind you {} at {} {}, the first one's {}. remind stop {} to cancel it",
+            schedule,
+            entry.time,
+            entry.timezone,
+            HumanTime::from(next - now).to_text_en(Accuracy::Rough, Tense::Future),
+            id
+        ),
+        Err(err) => {
+            println!("SQL error adding reminder: {}", err);
+            "SQL error".to_string()
+        }
+    }
+}
+
+fn add_coin_alert(db: &Database, msg: &Msg, coin: &str, above: bool, target: f32) -> String {
+    match db.coin_alerts_for(&msg.source) {
+        Ok(a) if a.len() as u32 >= reminders::MAX_COIN_ALERTS => {
+            return format!(
+                "You already have {} coin alerts, coinalert stop <id> one first",
+                a.len()
+            );
+        }
+        Ok(_) => (),
+        Err(err) => {
+            println!("SQL error checking coin alerts: {}", err);
+            return "SQL error".to_string();
+        }
+    }
+
+    let entry = CoinAlert {
+        id: 0,
+        nick: msg.source.to_string(),
+        channel: msg.target.to_string(),
+        coin: coin.to_string(),
+        above,
+        target,
+    };
+    match db.add_coin_alert(&entry) {
+        Ok(id) => format!(
+            "Ok, I'll let you know when {} goes {} {}. coinalert stop {} to cancel it",
+            coin.to_uppercase(),
+            if above { "above" } else { "below" },
+            coin_price(kraken_pair(coin), target),
+            id
+        ),
+        Err(err) => {
+            println!("SQL error adding coin alert: {}", err);
+            "SQL error".to_string()
+        }
+    }
+}
+
+// whether someone has asked for the bot to leave their messages alone, if we
+// can't tell then err on the side of leaving them alone
+pub fn is_muted(db: &Database, nick: &str) -> bool {
+    db.is_muted(nick).unwrap_or_else(|err| {
+        println!("SQL error checking mutes: {}", err);
+        true
+    })
 }
 
 pub async fn process_messages(
     msg: crate::Msg,
     db: &Database,
     client: &crate::Client,
-    api_key: Option<String>,
+    settings: &BotConfig,
     tx2: &mpsc::Sender<Bot>,
-    _req: Req,
+    req: Req,
+    state: &mut State,
 ) {
+    Stats::incr(&state.stats.messages);
+
+    if state.flooding(&msg.source, &settings.spam) {
+        return;
+    }
+
+    let paused = state.paused.load(Ordering::Relaxed);
+
     // HACK: check_notification only returns at most 2 notifications
     // if user alice spams user bob with notifications, when bob speaks he will be spammed with all
     // of those notifications at once (with some rate limiting provided by the irc crate), with
     // this hack bob will only ever receive 2 messages when he speaks, giving some end user control
     // for whether the channel is going to be spammed
-    // some ways to fix this: some persistence allowing for a user to receive any potential
-    // messages over pm, limit number of messages a user can receive, etc
-    let notifications = check_notification(&msg.source, db);
-    for n in notifications {
-        client.send_privmsg(&msg.target, &n).unwrap();
+    // with paste_url set anything more than that is pasted and bob gets a single link instead
+    if !paused {
+        let hostmask = msg.hostmask.as_deref().filter(|_| settings.match_hostmask);
+        let pasted = match &settings.paste_url {
+            Some(url) => paste_notifications(&msg, hostmask, db, url, tx2, &req),
+            None => false,
+        };
+        if !pasted {
+            let notifications = check_notification(&msg.source, hostmask, db);
+            for n in notifications {
+                reply(tx2, &msg, &n);
+            }
+        }
     }
 
     let nick = client.current_nickname().to_lowercase();
@@ -208,51 +1264,331 @@ pub async fn process_messages(
     // easter eggs
     // TODO: add support for parsing from file
     match &msg.content {
-        n if n.trim().starts_with("nn ") => {
+        _ if paused => (),
+        n if n.trim().starts_with("nn ") && !settings.is_quiet() && !is_muted(db, &msg.source) => {
             let response = match &msg.content {
                 c if c.to_lowercase().contains(&nick) => format!("nn {}", &msg.source),
                 _ => "nn".to_string(),
             };
-            client.send_privmsg(&msg.target, response).unwrap();
+            reply(tx2, &msg, response);
             return;
         }
         _ => (),
     }
 
-    let command = process_commands(&nick, &msg.content);
+    let content = match msg.content.starts_with(['.', '!']) {
+        true => expand_alias(db, &msg.source, &msg.target, &msg.content),
+        false => msg.content.to_string(),
+    };
+    let (command, addressed) = process_commands(&nick, &content, settings);
+
+    if paused && !matches!(command, Task::Pause(_)) {
+        return;
+    }
+
+    if settings.is_disabled(&msg.target, command.name()) {
+        return;
+    }
+
+    if command.expensive() && state.on_cooldown(&msg.source, command.name(), settings.cooldown) {
+        println!("{} is on cooldown for {}", msg.source, command.name());
+        return;
+    }
+
+    // only things that were meant as commands, a single word is a hangman
+    // guess as far as process_commands is concerned
+    if addressed && !matches!(command, Task::Ignore | Task::Message(_) | Task::Suggest(_)) {
+        state.count_command(db, &msg.target, command.name());
+    }
+
+    match command {
+        Task::Message(m) => reply(tx2, &msg, m),
+        Task::Seen(n) => {
+            let visible = |channel: &str| {
+                !settings.seen_private
+                    || channel.eq_ignore_ascii_case(&msg.target)
+                    || client.list_users(channel).is_some_and(|users| {
+                        users
+                            .iter()
+                            .any(|u| u.get_nickname().eq_ignore_ascii_case(&msg.source))
+                    })
+            };
+            let response = check_seen(n, db, visible, settings.seen_aliases);
+            reply(tx2, &msg, response)
+        }
+        Task::Tell(n, m) => {
+            // it's all delivered in one go so a long one floods the channel
+            let length = m.chars().count();
+            if length > settings.max_tell_length {
+                let response = format!(
+                    "That's {} characters, messages can be up to {}",
+                    length, settings.max_tell_length
+                );
+                reply(tx2, &msg, response);
+                return;
+            }
+            match db.count_notifications(n, &msg.source) {
+                Ok(count) if count >= settings.max_tells => {
+                    let response = format!(
+                        "You already have {} messages waiting for {}, wait for them to be delivered first",
+                        count, n
+                    );
+                    reply(tx2, &msg, response);
+                    return;
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    println!("SQL error counting notifications: {}", err);
+                    return;
+                }
+            }
+
+            // where they were last seen from, so it still reaches them under another nick
+            let hostmask = match settings.match_hostmask {
+                true => db.check_seen(n).ok().flatten().and_then(|s| s.hostmask),
+                false => None,
+            };
+            let entry = Notification {
+                id: 0,
+                recipient: n.to_string(),
+                via: msg.source.to_string(),
+                message: m.to_string(),
+                created: Some(Utc::now().to_rfc3339()),
+                hostmask,
+            };
+            if let Err(err) = db.add_notification(&entry) {
+                println!("SQL error adding notification: {}", err);
+                return;
+            }
+            let response = format!("Ok, I'll tell {} that", n);
+            reply(tx2, &msg, response);
+        }
+        Task::Forecast(l) => {
+            let Some(key) = settings.weather_api.clone() else {
+                not_configured(tx2, &msg, settings, "weather");
+                return;
+            };
+            let lang = settings.weather_lang.clone();
+            let ascii = settings.weather_ascii;
+            let allow_insecure = settings.allow_insecure;
+            let airports = settings.airports.clone();
+
+            let tx2 = tx2.clone();
+            let ftarget = msg.origin();
+            let db = db.clone();
+            let stats = state.stats.clone();
+            let l = l.map(|v| v.to_string());
+
+            spawn(async move {
+                let (lat, lon) = match get_or_set_user_location(
+                    &db,
+                    &msg,
+                    l.as_deref(),
+                    allow_insecure,
+                    airports.as_deref(),
+                    &tx2,
+                )
+                .await
+                {
+                    Ok(Some(v)) => v,
+                    Ok(None) => {
+                        tx2.send(Bot::Privmsg(
+                            ftarget,
+                            "tell me where you are please mate".to_string(),
+                        ))
+                        .await
+                        .unwrap();
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("failed to get weather: {e}");
+                        tx2.send(Bot::Privmsg(
+                            ftarget,
+                            "couldn't muster it sorry mate".to_string(),
+                        ))
+                        .await
+                        .unwrap();
+                        return;
+                    }
+                };
+
+                match get_forecast(&lat, &lon, &lang, &key).await {
+                    Ok(weather) => {
+                        Stats::incr(&stats.weather);
+                        let pretty = print_forecast(weather, ascii);
+                        let _res = tx2.send(Bot::Privmsg(ftarget, pretty)).await;
+                    }
+                    Err(err) => {
+                        println!("weather isn't initialised: {}", err);
+                    }
+                }
+            });
+        }
+        Task::Alerts(l) => {
+            let Some(key) = settings.weather_api.clone() else {
+                not_configured(tx2, &msg, settings, "weather");
+                return;
+            };
+            let lang = settings.weather_lang.clone();
+            let allow_insecure = settings.allow_insecure;
+            let airports = settings.airports.clone();
+
+            let tx2 = tx2.clone();
+            let ftarget = msg.origin();
+            let db = db.clone();
+            let stats = state.stats.clone();
+            let l = l.map(|v| v.to_string());
+
+            spawn(async move {
+                let (lat, lon) = match get_or_set_user_location(
+                    &db,
+                    &msg,
+                    l.as_deref(),
+                    allow_insecure,
+                    airports.as_deref(),
+                    &tx2,
+                )
+                .await
+                {
+                    Ok(Some(v)) => v,
+                    Ok(None) => {
+                        tx2.send(Bot::Privmsg(
+                            ftarget,
+                            "tell me where you are please mate".to_string(),
+                        ))
+                        .await
+                        .unwrap();
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("failed to get alerts: {e}");
+                        tx2.send(Bot::Privmsg(
+                            ftarget,
+                            "couldn't muster it sorry mate".to_string(),
+                        ))
+                        .await
+                        .unwrap();
+                        return;
+                    }
+                };
+
+                match get_alerts(&lat, &lon, &lang, &key, &req).await {
+                    Ok(alerts) => {
+                        Stats::incr(&stats.weather);
+                        let _res = tx2.send(Bot::Privmsg(ftarget, print_alerts(&alerts))).await;
+                    }
+                    Err(err) => {
+                        println!("Error fetching weather alerts: {}", err);
+                    }
+                }
+            });
+        }
+        Task::WeatherFor(n, units) => {
+            let Some(key) = settings.weather_api.clone() else {
+                not_configured(tx2, &msg, settings, "weather");
+                return;
+            };
+            let (lat, lon) = match db.check_weather(n) {
+                Ok(Some(coords)) => coords,
+                Ok(None) => {
+                    let response = format!(
+                        "I don't know where {} is, they can tell me with .weather <location>",
+                        n
+                    );
+                    reply(tx2, &msg, response);
+                    return;
+                }
+                Err(err) => {
+                    println!("SQL error checking weather: {}", err);
+                    return;
+                }
+            };
 
-    match command {
-        Task::Message(m) => client.send_privmsg(msg.target, m).unwrap(),
-        Task::Seen(n) => {
-            let response = check_seen(n, db);
-            client.send_privmsg(msg.target, response).unwrap()
+            let tx2 = tx2.clone();
+            let stats = state.stats.clone();
+            let icons = settings.weather_icons;
+            let lang = settings.weather_lang.clone();
+            let ascii = settings.weather_ascii;
+            let use_colours = settings.use_colors;
+            let ftarget = msg.origin();
+            spawn(async move {
+                match get_weather(&format!("{lat},{lon}"), units, &lang, &key).await {
+                    Ok(weather) => {
+                        Stats::incr(&stats.weather);
+                        let pretty = print_weather(weather, units, icons, ascii, use_colours);
+                        tx2.send(Bot::Privmsg(ftarget, pretty)).await.unwrap();
+                    }
+                    Err(err) => println!("weather isn't initialised: {err}"),
+                }
+            });
         }
-        Task::Tell(n, m) => {
-            let entry = Notification {
-                id: 0,
-                recipient: n.to_string(),
-                via: msg.source,
-                message: m.to_string(),
-            };
-            if let Err(err) = db.add_notification(&entry) {
-                println!("SQL error adding notification: {}", err);
+        // TODO: figure out the borrowowing issue(s?) so code doesn't have to be
+        // duplicated as much here, and especially so that it can be
+        // separated out into its own functions
+        Task::LocalTime(n) => {
+            let Some(key) = settings.weather_api.clone() else {
+                not_configured(tx2, &msg, settings, "weather");
                 return;
-            }
-            let response = format!("Ok, I'll tell {} that", n);
-            client.send_privmsg(msg.target, response).unwrap();
+            };
+            let (lat, lon) = match db.check_weather(n) {
+                Ok(Some(coords)) => coords,
+                Ok(None) => {
+                    let response = format!(
+                        "I don't know where {} is, they can tell me with .weather <location>",
+                        n
+                    );
+                    reply(tx2, &msg, response);
+                    return;
+                }
+                Err(err) => {
+                    println!("SQL error checking weather: {}", err);
+                    return;
+                }
+            };
+
+            let tx2 = tx2.clone();
+            let lang = settings.weather_lang.clone();
+            let nick = n.to_string();
+            spawn(async move {
+                // the current weather is the only place we get the utc offset from
+                let coords = format!("{lat},{lon}");
+                match get_weather(&coords, Units::Metric, &lang, &key).await {
+                    Ok(weather) => {
+                        let response = print_local_time(&nick, weather.timezone, Utc::now());
+                        tx2.send(Bot::Privmsg(msg.origin(), response))
+                            .await
+                            .unwrap();
+                    }
+                    Err(err) => println!("weather isn't initialised: {err}"),
+                }
+            });
         }
-        Task::Forecast(l) => {
-            let Some(key) = api_key.clone() else {
+        Task::Yesterday(l, units) => {
+            let Some(key) = settings.weather_api.clone() else {
+                not_configured(tx2, &msg, settings, "weather");
                 return;
             };
+            let lang = settings.weather_lang.clone();
+            let ascii = settings.weather_ascii;
+            let allow_insecure = settings.allow_insecure;
+            let airports = settings.airports.clone();
 
             let tx2 = tx2.clone();
-            let ftarget = msg.target.clone();
+            let ftarget = msg.origin();
             let db = db.clone();
+            let stats = state.stats.clone();
             let l = l.map(|v| v.to_string());
 
             spawn(async move {
-                let (lat, lon) = match get_or_set_user_location(&db, &msg, l.as_deref(), &tx2).await
+                let (lat, lon) = match get_or_set_user_location(
+                    &db,
+                    &msg,
+                    l.as_deref(),
+                    allow_insecure,
+                    airports.as_deref(),
+                    &tx2,
+                )
+                .await
                 {
                     Ok(Some(v)) => v,
                     Ok(None) => {
@@ -276,33 +1612,52 @@ pub async fn process_messages(
                     }
                 };
 
-                match get_forecast(&lat, &lon, &key).await {
-                    Ok(weather) => {
-                        let pretty = print_forecast(weather);
-                        let _res = tx2.send(Bot::Privmsg(ftarget, pretty)).await;
-                    }
+                let now = match get_weather(&format!("{lat},{lon}"), units, &lang, &key).await {
+                    Ok(w) => w,
                     Err(err) => {
-                        println!("weather isn't initialised: {}", err);
+                        println!("weather isn't initialised: {err}");
+                        return;
+                    }
+                };
+                match get_yesterday(&lat, &lon, units, &lang, &key, &req).await {
+                    Ok(yesterday) => {
+                        Stats::incr(&stats.weather);
+                        let pretty = print_yesterday(&now, &yesterday, units, ascii);
+                        tx2.send(Bot::Privmsg(ftarget, pretty)).await.unwrap();
                     }
+                    Err(err) => println!("Error fetching yesterday's weather: {}", err),
                 }
             });
         }
-        // TODO: figure out the borrowowing issue(s?) so code doesn't have to be
-        // duplicated as much here, and especially so that it can be
-        // separated out into its own functions
-        Task::Weather(l) => {
-            let Some(key) = api_key.clone() else {
+        Task::Weather(l, units) => {
+            let Some(key) = settings.weather_api.clone() else {
+                not_configured(tx2, &msg, settings, "weather");
                 return;
             };
 
             let tx2 = tx2.clone();
             let db = db.clone();
+            let stats = state.stats.clone();
+            let icons = settings.weather_icons;
+            let lang = settings.weather_lang.clone();
+            let ascii = settings.weather_ascii;
+            let allow_insecure = settings.allow_insecure;
+            let airports = settings.airports.clone();
+            let use_colours = settings.use_colors;
             let msg = msg.clone();
-            let ftarget = msg.target.clone();
+            let ftarget = msg.origin();
             let l = l.map(|v| v.to_string());
 
             spawn(async move {
-                let (lat, lon) = match get_or_set_user_location(&db, &msg, l.as_deref(), &tx2).await
+                let (lat, lon) = match get_or_set_user_location(
+                    &db,
+                    &msg,
+                    l.as_deref(),
+                    allow_insecure,
+                    airports.as_deref(),
+                    &tx2,
+                )
+                .await
                 {
                     Ok(Some(v)) => v,
                     Ok(None) => {
@@ -326,9 +1681,10 @@ pub async fn process_messages(
                     }
                 };
 
-                match get_weather(&format!("{lat},{lon}"), &key).await {
+                match get_weather(&format!("{lat},{lon}"), units, &lang, &key).await {
                     Ok(weather) => {
-                        let pretty = print_weather(weather);
+                        Stats::incr(&stats.weather);
+                        let pretty = print_weather(weather, units, icons, ascii, use_colours);
                         tx2.send(Bot::Privmsg(ftarget, pretty)).await.unwrap();
                     }
                     Err(err) => {
@@ -343,16 +1699,19 @@ pub async fn process_messages(
                     "https://www.openstreetmap.org/?mlat={}&mlon={}",
                     l.lat, l.lon
                 );
-                client.send_privmsg(msg.target, response).unwrap();
+                reply(tx2, &msg, response);
             }
             Ok(None) => {
                 let tx2 = tx2.clone();
                 let flocation = l.to_string();
-                let ftarget = msg.target.clone();
+                let ftarget = msg.origin();
+                let allow_insecure = settings.allow_insecure;
+                let airports = settings.airports.clone();
                 let response = format!("No coordinates found for {} in database", l);
                 println!("{}", response);
                 spawn(async move {
-                    let fetched_location = get_location(&flocation).await;
+                    let fetched_location =
+                        get_location(&flocation, allow_insecure, airports.as_deref()).await;
                     match fetched_location {
                         Ok(Some(l)) => {
                             let response = format!(
@@ -376,16 +1735,8 @@ pub async fn process_messages(
             }
             Err(err) => println!("Error fetching location from database: {}", err),
         },
-        Task::Coins(c, t) => {
-            let coin = match c {
-                "btc" | "bitcoin" => "XXBTZUSD",
-                "btcgbp" => "XXBTZGBP",
-                "eth" | "ethereum" => "XETHZUSD",
-                "ltc" => "XLTCZUSD",
-                "xmr" | "monero" => "XXMRZUSD",
-                "doge" => "XDGUSD",
-                _ => "XXBTZUSD",
-            };
+        Task::Coins(c, t, detail) => {
+            let coin = kraken_pair(c);
 
             // todo: we should store the json so that we only need to fetch an updated spot price
             /*let dbcoin = match t {
@@ -403,8 +1754,8 @@ pub async fn process_messages(
                     if duration > Duration::seconds(15 * 60 + 30) {
                         true
                     } else {
-                        client.send_privmsg(&msg.target, c.data_0).unwrap();
-                        client.send_privmsg(&msg.target, c.data_1).unwrap();
+                        reply(tx2, &msg, c.data_0);
+                        reply(tx2, &msg, c.data_1);
                         false
                     }
                 }
@@ -415,61 +1766,681 @@ pub async fn process_messages(
                 }
             };*/
 
-            let ftarget = msg.target.clone();
+            let ftarget = msg.origin();
             let tx2 = tx2.clone();
+            let stats = state.stats.clone();
             let time_frame = t.to_string();
+            let use_colours = settings.use_colors;
+            let req = req.clone();
             spawn(async move {
-                let coins = get_coins(coin, &time_frame).await;
+                let coins = get_coins(coin, &time_frame, use_colours, detail, &req).await;
                 match coins {
                     Ok(coins) => {
+                        Stats::incr(&stats.coins);
                         let _coin = coins.clone();
                         let coin2 = coins.clone();
                         let coin3 = coins.clone();
                         let ftarget2 = ftarget.clone();
+                        let ftarget3 = ftarget.clone();
                         //tx2.send(Bot::UpdateCoins(coin)).await.unwrap();
                         tx2.send(Bot::Privmsg(ftarget, coin2.data_0)).await.unwrap();
                         tx2.send(Bot::Privmsg(ftarget2, coin3.data_1))
                             .await
                             .unwrap();
+                        // one message with a line per row, it goes through the
+                        // same throttle as everything else
+                        if let Some(chart) = coins.chart {
+                            tx2.send(Bot::Privmsg(ftarget3, chart)).await.unwrap();
+                        }
                     }
                     Err(err) => {
                         println!("issue getting shitcoin data: {}", err);
+                        let response = format!("Error fetching {}: {}", coin, err);
+                        tx2.send(Bot::Privmsg(ftarget, response)).await.unwrap();
                     }
                 }
             });
         }
-        Task::Lastfm(n) => match get_lastfm_scrobble(n.to_string(), _req).await {
-            Ok(response) => client.send_privmsg(msg.target, response).unwrap(),
-            Err(e) => client.send_privmsg(msg.target, e).unwrap(),
+        Task::Lastfm(n) => match music::LastFm.fetch(n, &req).await {
+            Ok(response) => reply(tx2, &msg, response),
+            Err(e) => reply(tx2, &msg, e.to_string()),
         },
+        Task::ListenBrainz(user) => {
+            let user = user.to_string();
+            let tx2 = tx2.clone();
+            spawn(async move {
+                let response = match music::ListenBrainz.fetch(&user, &req).await {
+                    Ok(r) => r,
+                    Err(e) => e.to_string(),
+                };
+                tx2.send(Bot::Privmsg(msg.origin(), response))
+                    .await
+                    .unwrap();
+            });
+        }
+        Task::NowPlaying(NpTask::Set(service, account)) => {
+            let response = match music::provider(service) {
+                Some(p) => match db.add_music_account(&msg.source, p.name(), account) {
+                    Ok(_) => format!("Ok, you're {} on {}", account, p.name()),
+                    Err(err) => {
+                        println!("SQL error adding music account: {}", err);
+                        return;
+                    }
+                },
+                None => format!("I don't know {}, try lastfm or listenbrainz", service),
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::NowPlaying(NpTask::Show(nick)) => {
+            let nick = nick.unwrap_or(&msg.source).to_string();
+            let account = match db.check_music_account(&nick) {
+                Ok(a) => a,
+                Err(err) => {
+                    println!("SQL error checking music account: {}", err);
+                    return;
+                }
+            };
+            let Some((provider, account)) =
+                account.and_then(|(s, a)| music::provider(&s).map(|p| (p, a)))
+            else {
+                let response = format!(
+                    "I don't know what {} listens to, they can tell me with .np set <lastfm username>",
+                    nick
+                );
+                reply(tx2, &msg, response);
+                return;
+            };
+
+            let tx2 = tx2.clone();
+            spawn(async move {
+                let response = match provider.fetch(&account, &req).await {
+                    Ok(r) => r,
+                    Err(e) => e.to_string(),
+                };
+                tx2.send(Bot::Privmsg(msg.origin(), response))
+                    .await
+                    .unwrap();
+            });
+        }
         Task::Hang(l) if msg.target == "#games" => {
-            tx2.send(Bot::Hang(msg.target, l.to_string()))
-                .await
-                .unwrap();
+            send_or_drop(tx2, Bot::Hang(msg.target, l.to_string()));
         }
         Task::HangGuess(w) if msg.target == "#games" => {
-            tx2.send(Bot::HangGuess(msg.target, w.to_string()))
-                .await
-                .unwrap();
+            send_or_drop(tx2, Bot::HangGuess(msg.target, w.to_string(), false));
+        }
+        Task::Guess(w) if msg.target == "#games" => {
+            send_or_drop(tx2, Bot::HangGuess(msg.target, w.to_string(), true));
         }
         Task::HangStart(l) if msg.target == "#games" => {
+            // bracketed so no guess can be mistaken for one
             let target = if l.len() == 0 {
                 "<start>".to_string()
             } else {
-                l.to_string()
+                format!("<{}>", l)
+            };
+
+            send_or_drop(tx2, Bot::HangGuess(msg.target, target, true));
+        }
+        Task::Anagram(l) if msg.target == "#games" => {
+            send_or_drop(tx2, Bot::Anagram(msg.target, l.to_string()));
+        }
+        Task::Scramble if msg.target == "#games" => {
+            send_or_drop(tx2, Bot::Scramble(msg.target));
+        }
+        Task::Trivia if msg.target == "#games" => {
+            send_or_drop(tx2, Bot::Trivia(msg.target));
+        }
+        // several lines at once so it goes through the throttle
+        Task::Fig(t) if msg.target == "#games" => match fig::render(t) {
+            Some(lines) => send_or_drop(tx2, Bot::Privmsg(msg.origin(), lines.join("\n"))),
+            None => {
+                let response = format!(
+                    "Up to {} letters, numbers, spaces and !?.- only",
+                    fig::MAX_FIG_LEN
+                );
+                reply(tx2, &msg, response);
+            }
+        },
+        Task::TriviaScores => {
+            let response = match db.top_scores("trivia", TOP_SCORES) {
+                Ok(s) if s.is_empty() => "Nobody's answered a question yet".to_string(),
+                Ok(s) => {
+                    let scores = s
+                        .iter()
+                        .map(|(n, p)| format!("{} ({})", n, numbers::count(*p)))
+                        .join(", ");
+                    format!("Trivia: {}", scores)
+                }
+                Err(err) => {
+                    println!("SQL error checking scores: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Rng(r) => {
+            let response = match r {
+                RngTask::Flip => match thread_rng().gen_bool(0.5) {
+                    true => "heads".to_string(),
+                    false => "tails".to_string(),
+                },
+                RngTask::Pick(low, high) => thread_rng().gen_range(low..=high).to_string(),
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Pause(p) if is_admin(settings, &msg) => {
+            let response = match (p, state.paused.swap(p, Ordering::Relaxed)) {
+                (true, true) => "Already paused, use resume to re-enable commands",
+                (true, false) => "Paused, commands are disabled until resume",
+                (false, true) => "Resumed",
+                (false, false) => "Not paused",
+            };
+            println!("{} by {}: {}", command.name(), msg.source, response);
+            reply(tx2, &msg, response);
+        }
+        Task::Version => reply(tx2, &msg, version()),
+        Task::Uptime => reply(tx2, &msg, state.stats.summary()),
+        // how long the message spent waiting on the event loop before we got
+        // to it, not the time to the server and back
+        Task::Ping => {
+            let lag = msg.received.elapsed().as_secs_f64() * 1000.0;
+            let response = format!("pong ({:.1}ms)", lag);
+            reply(tx2, &msg, response);
+        }
+        Task::Calc(expr) => {
+            let response = match calc::evaluate(expr) {
+                Ok(value) => calc::format_result(value),
+                Err(err) => format!("Error: {}", err),
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Topic(Some(topic)) => {
+            if is_admin(settings, &msg) {
+                send_or_drop(tx2, Bot::SetTopic(msg.target, topic.to_string()));
+            }
+        }
+        Task::Topic(None) => {
+            let response = match state.topics.get(&msg.target.to_lowercase()) {
+                Some(topic) if !topic.is_empty() => {
+                    format!("Topic for {}: {}", msg.target, topic)
+                }
+                _ => format!("No topic is set for {}", msg.target),
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Urban(term) if settings.allows_ud(&msg.target) => {
+            let tx2 = tx2.clone();
+            let term = term.to_string();
+            spawn(async move {
+                let response = match get_urban(&term, &req).await {
+                    Ok(Some(response)) => response,
+                    Ok(None) => format!("No definitions found for {}", term),
+                    Err(err) => {
+                        println!("Error fetching urban dictionary for {}: {}", term, err);
+                        return;
+                    }
+                };
+                tx2.send(Bot::Privmsg(msg.origin(), response))
+                    .await
+                    .unwrap();
+            });
+        }
+        Task::Synonyms(word) => {
+            let tx2 = tx2.clone();
+            let word = word.to_string();
+            let url = settings.thesaurus_url.clone();
+            spawn(async move {
+                let response = match get_synonyms(&url, &word, &req).await {
+                    Ok(s) if s.is_empty() => format!("No synonyms found for {}", word),
+                    Ok(s) => format!("Synonyms for {}: {}", word, s.join(", ")),
+                    Err(err) => {
+                        println!("Error fetching synonyms for {}: {}", word, err);
+                        return;
+                    }
+                };
+                tx2.send(Bot::Privmsg(msg.origin(), response))
+                    .await
+                    .unwrap();
+            });
+        }
+        Task::FearGreed => {
+            // the index is only updated once a day
+            let age = STDDuration::from_secs(60 * 60);
+            if let Some(response) = state.fear_greed.get(age) {
+                reply(tx2, &msg, response);
+                return;
+            }
+
+            let tx2 = tx2.clone();
+            let cache = state.fear_greed.clone();
+            spawn(async move {
+                match get_fear_greed(&req).await {
+                    Ok(response) => {
+                        cache.set(response.clone());
+                        tx2.send(Bot::Privmsg(msg.origin(), response))
+                            .await
+                            .unwrap();
+                    }
+                    Err(err) => println!("Error fetching fear & greed index: {}", err),
+                }
+            });
+        }
+        Task::Stock(symbol) => {
+            let Some(key) = settings.stocks.api_key.clone() else {
+                not_configured(tx2, &msg, settings, "stocks");
+                return;
+            };
+
+            let tx2 = tx2.clone();
+            let url = settings.stocks.url.clone();
+            let symbol = symbol.to_uppercase();
+            spawn(async move {
+                let response = match get_stock(&symbol, &url, &key, &req).await {
+                    Ok(Some(response)) => response,
+                    Ok(None) => format!("Unknown symbol {}, hint: stock <symbol>", symbol),
+                    Err(err) => {
+                        println!("Error fetching stock quote for {}: {}", symbol, err);
+                        format!("Couldn't fetch a quote for {}", symbol)
+                    }
+                };
+                tx2.send(Bot::Privmsg(msg.origin(), response))
+                    .await
+                    .unwrap();
+            });
+        }
+        // anything that isn't built in can be handled by the operator's script
+        Task::Suggest(_) | Task::Ignore if settings.script.is_some() => {
+            let Some((name, args)) = script_command(&content) else {
+                return;
+            };
+            if settings.is_disabled(&msg.target, "script")
+                || state.on_cooldown(&msg.source, "script", settings.cooldown)
+            {
+                return;
+            }
+
+            let script = settings.script.clone().unwrap();
+            let (name, args) = (name.to_lowercase(), args.to_string());
+            let tx2 = tx2.clone();
+            let msg = msg.clone();
+            let ftarget = msg.origin();
+            spawn(async move {
+                match script::run(&script, &msg, &name, &args).await {
+                    Ok(lines) if lines.is_empty() => (),
+                    Ok(lines) => tx2
+                        .send(Bot::Privmsg(ftarget, lines.join("\n")))
+                        .await
+                        .unwrap(),
+                    Err(err) => println!("Error running script for .{}: {}", name, err),
+                }
+            });
+        }
+        Task::Suggest(c) => {
+            let response = format!("Did you mean .{}?", c);
+            reply(tx2, &msg, response);
+        }
+        Task::Alias(AliasTask::Add(name, expansion, channel)) => {
+            if channel && !is_admin(settings, &msg) {
+                return;
+            }
+            let scope = if channel { &msg.target } else { &msg.source };
+
+            // aliases can't replace real commands
+            let builtin = is_builtin(name, settings);
+            let first = expansion.split_whitespace().next().unwrap_or_default();
+            let response = if builtin {
+                format!(".{} is already a command", name)
+            } else if first.eq_ignore_ascii_case(name) {
+                "An alias can't refer to itself".to_string()
+            } else if expansion.len() > MAX_ALIAS_LEN {
+                "That alias is too long".to_string()
+            } else {
+                match db.add_alias(scope, name, expansion) {
+                    Ok(_) => format!("Ok, .{} is now .{}", name, expansion),
+                    Err(err) => {
+                        println!("SQL error adding alias: {}", err);
+                        return;
+                    }
+                }
             };
+            reply(tx2, &msg, response);
+        }
+        Task::Alias(AliasTask::Del(name, channel)) => {
+            if channel && !is_admin(settings, &msg) {
+                return;
+            }
+            let scope = if channel { &msg.target } else { &msg.source };
 
-            tx2.send(Bot::HangGuess(msg.target, target)).await.unwrap();
+            let response = match db.remove_alias(scope, name) {
+                Ok(true) => format!("Removed .{}", name),
+                Ok(false) => format!("No alias named .{}", name),
+                Err(err) => {
+                    println!("SQL error removing alias: {}", err);
+                    return;
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Highlight => match &settings.highlight {
+            Highlight::Help => reply(tx2, &msg, HELP),
+            Highlight::Message(m) => reply(tx2, &msg, m),
+            Highlight::Ignore => (),
+        },
+        Task::Title(url) => {
+            let url = match url.starts_with("http://") || url.starts_with("https://") {
+                true => url.to_string(),
+                false => format!("http://{}", url),
+            };
+            let format = TitleFormat::new(settings);
+            let stats = state.stats.clone();
+            let tx2 = tx2.clone();
+            spawn(async move {
+                let response = match fetch_title(&url, &req).await {
+                    Ok(PageTitle {
+                        title: Some(title),
+                        redirect,
+                    }) => {
+                        Stats::incr(&stats.titles);
+                        format_title(&title, redirect.as_deref(), &format)
+                    }
+                    Ok(_) => format!("No title found for {}", url),
+                    Err(err) => format!("Error fetching {}: {}", url, err),
+                };
+                tx2.send(Bot::Privmsg(msg.origin(), response))
+                    .await
+                    .unwrap();
+            });
+        }
+        Task::CoinOverview => {
+            let coins = settings.overview_coins.clone();
+            let use_colours = settings.use_colors;
+            let stats = state.stats.clone();
+            let tx2 = tx2.clone();
+            spawn(async move {
+                match get_coin_overview(&coins, &req, use_colours).await {
+                    Ok(response) => {
+                        Stats::incr(&stats.coins);
+                        tx2.send(Bot::Privmsg(msg.origin(), response))
+                            .await
+                            .unwrap();
+                    }
+                    Err(err) => println!("Error fetching coin overview: {}", err),
+                }
+            });
+        }
+        Task::Channels if is_admin(settings, &msg) => {
+            let response = match client.list_channels() {
+                Some(channels) if !channels.is_empty() => channels
+                    .iter()
+                    .map(|c| {
+                        let users = client.list_users(c).map_or(0, |u| u.len());
+                        format!("{} ({})", c, users)
+                    })
+                    .join(", "),
+                _ => "Not in any channels".to_string(),
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::OptOut(true) => {
+            // forget where they were last seen too, not just from now on
+            let response = match db
+                .add_optout(&msg.source)
+                .and_then(|_| db.remove_seen(&msg.source))
+            {
+                Ok(_) => "You won't show up in .seen anymore, .optin to undo this",
+                Err(err) => {
+                    println!("SQL error opting out: {}", err);
+                    "SQL error"
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::OptOut(false) => {
+            let response = match db.remove_optout(&msg.source) {
+                Ok(true) => "You'll show up in .seen again",
+                Ok(false) => "You weren't opted out",
+                Err(err) => {
+                    println!("SQL error opting in: {}", err);
+                    "SQL error"
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Remind(RemindTask::Every(schedule, time, timezone, message)) => {
+            let response = match (
+                reminders::Schedule::parse(schedule),
+                reminders::parse_time(time),
+                timezone.map_or(Some(Tz::UTC), reminders::parse_timezone),
+            ) {
+                (None, _, _) => REMIND_HINT.to_string(),
+                (_, None, _) => "The time should be HH:MM, i.e., 09:30".to_string(),
+                (_, _, None) => format!(
+                    "I don't know the timezone {}, try one like Europe/London",
+                    timezone.unwrap_or_default()
+                ),
+                (Some(schedule), Some(time), Some(timezone)) => {
+                    add_reminder(db, &msg, schedule, time, timezone, message)
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Remind(RemindTask::Stop(id)) => {
+            let response = match id
+                .parse::<u32>()
+                .map(|id| db.remove_reminder(id, &msg.source))
+            {
+                Err(_) => "Hint: remind stop <id>, see remind list for ids".to_string(),
+                Ok(Ok(true)) => format!("Ok, reminder #{} is gone", id),
+                Ok(Ok(false)) => format!("You don't have a reminder #{}", id),
+                Ok(Err(err)) => {
+                    println!("SQL error removing reminder: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Remind(RemindTask::List) => {
+            let response = match db.reminders_for(&msg.source) {
+                Ok(r) if r.is_empty() => "You don't have any reminders".to_string(),
+                Ok(r) => {
+                    let list = r
+                        .iter()
+                        .map(|r| {
+                            let schedule = reminders::Schedule::parse(&r.schedule)
+                                .map(|s| s.to_string())
+                                .unwrap_or_else(|| r.schedule.to_string());
+                            format!(
+                                "#{} {} at {} {}: {}",
+                                r.id, schedule, r.time, r.timezone, r.message
+                            )
+                        })
+                        .join(" | ");
+                    format!("Your reminders: {}", list)
+                }
+                Err(err) => {
+                    println!("SQL error checking reminders: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::CoinAlert(CoinAlertTask::Add(coin, above, target)) => {
+            let response = add_coin_alert(db, &msg, coin, above, target);
+            reply(tx2, &msg, response);
+        }
+        Task::CoinAlert(CoinAlertTask::Stop(id)) => {
+            let response = match id
+                .parse::<u32>()
+                .map(|id| db.remove_coin_alert(id, Some(&msg.source)))
+            {
+                Err(_) => "Hint: coinalert stop <id>, see coinalert list for ids".to_string(),
+                Ok(Ok(true)) => format!("Ok, coin alert #{} is gone", id),
+                Ok(Ok(false)) => format!("You don't have a coin alert #{}", id),
+                Ok(Err(err)) => {
+                    println!("SQL error removing coin alert: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::CoinAlert(CoinAlertTask::List) => {
+            let response = match db.coin_alerts_for(&msg.source) {
+                Ok(a) if a.is_empty() => "You don't have any coin alerts".to_string(),
+                Ok(a) => {
+                    let list = a
+                        .iter()
+                        .map(|a| {
+                            format!(
+                                "#{} {} {} {}",
+                                a.id,
+                                a.coin.to_uppercase(),
+                                if a.above { "above" } else { "below" },
+                                coin_price(kraken_pair(&a.coin), a.target)
+                            )
+                        })
+                        .join(" | ");
+                    format!("Your coin alerts: {}", list)
+                }
+                Err(err) => {
+                    println!("SQL error checking coin alerts: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Mute(true) => {
+            let response = match db.add_mute(&msg.source) {
+                Ok(_) => "Ok, no more link titles or easter eggs for your messages, commands still work. .unmute to undo this",
+                Err(err) => {
+                    println!("SQL error muting: {}", err);
+                    "SQL error"
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Mute(false) => {
+            let response = match db.remove_mute(&msg.source) {
+                Ok(true) => "Link titles and easter eggs are back on for your messages",
+                Ok(false) => "You weren't muted",
+                Err(err) => {
+                    println!("SQL error unmuting: {}", err);
+                    "SQL error"
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        // settings are owned by the main loop so it has to do the reloading
+        Task::Reload if is_admin(settings, &msg) => {
+            send_or_drop(tx2, Bot::Reload(msg.target));
+        }
+        // sent as is by the main loop, it has the client
+        Task::Raw(line) if is_admin(settings, &msg) => {
+            let event = Bot::Raw(msg.source.to_string(), msg.target, line.to_string());
+            send_or_drop(tx2, event);
+        }
+        Task::TopCommands => {
+            // so it's up to date
+            state.write_command_uses(db);
+            let channel = match msg.target.starts_with('#') {
+                true => msg.target.to_string(),
+                false => "private".to_string(),
+            };
+            let response = match db.top_commands(&channel, TOP_COMMANDS) {
+                Ok(c) if c.is_empty() => "No commands have been used here yet".to_string(),
+                Ok(c) => {
+                    let commands = c
+                        .iter()
+                        .map(|(name, count)| format!("{} ({})", name, numbers::count(*count)))
+                        .join(", ");
+                    format!("Most used: {}", commands)
+                }
+                Err(err) => {
+                    println!("SQL error checking command stats: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::TopDomains => {
+            let response = match db.top_domains(&msg.target, TOP_DOMAINS) {
+                Ok(d) if d.is_empty() => "No links have been posted here yet".to_string(),
+                Ok(d) => {
+                    let domains = d
+                        .iter()
+                        .map(|(h, c)| format!("{} ({})", h, numbers::count(*c)))
+                        .join(", ");
+                    format!("Most linked: {}", domains)
+                }
+                Err(err) => {
+                    println!("SQL error checking domains: {}", err);
+                    "SQL error".to_string()
+                }
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Last(n) => {
+            let lines = state.last(&msg.target, n, LAST_LINES);
+            if lines.is_empty() {
+                let response = format!("{} hasn't said anything recently", n);
+                reply(tx2, &msg, response);
+                return;
+            }
+            for line in lines {
+                reply(tx2, &msg, line.describe());
+            }
+        }
+        Task::Grep(t) => {
+            let response = match state.grep(&msg.target, t) {
+                Some(line) => line.describe(),
+                None => format!("Nobody's said {} recently", t),
+            };
+            reply(tx2, &msg, response);
+        }
+        Task::Search(t) if is_admin(settings, &msg) => {
+            if !settings.log_messages {
+                let response = "Messages aren't being logged, enable log_messages first";
+                reply(tx2, &msg, response);
+                return;
+            }
+            match db.search_messages(&msg.target, t, SEARCH_RESULTS) {
+                Ok(found) if found.is_empty() => {
+                    let response = format!("Nothing found for {}", t);
+                    reply(tx2, &msg, response);
+                }
+                Ok(found) => {
+                    for m in found {
+                        let time = DateTime::parse_from_rfc3339(&m.time)
+                            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                            .unwrap_or(m.time);
+                        let response = format!("[{}] <{}> {}", time, m.nick, m.content);
+                        reply(tx2, &msg, response);
+                    }
+                }
+                Err(err) => {
+                    println!("SQL error searching messages: {}", err);
+                    reply(tx2, &msg, "SQL error");
+                }
+            }
         }
         Task::Ignore => (),
         _ => (),
     }
 }
 
+pub fn version() -> String {
+    let commit = env!("BOOT_GIT_COMMIT");
+    match commit {
+        "" => format!("boot {}", env!("CARGO_PKG_VERSION")),
+        c => format!("boot {} ({})", env!("CARGO_PKG_VERSION"), c),
+    }
+}
+
 pub async fn get_or_set_user_location(
     db: &Database,
     msg: &Msg,
     location: Option<&str>,
+    allow_insecure: bool,
+    airports: Option<&str>,
     tx: &Sender<Bot>,
 ) -> Result<Option<(String, String)>, Error> {
     if let Some(location) = location {
@@ -484,7 +2455,7 @@ pub async fn get_or_set_user_location(
             return Ok(Some((coords.lat, coords.lon)));
         }
 
-        let Some(loc) = get_location(&location).await? else {
+        let Some(loc) = get_location(location, allow_insecure, airports).await? else {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 "sorry mate i have nfi where you are",
@@ -509,17 +2480,74 @@ pub async fn get_or_set_user_location(
     }
 }
 
-pub async fn process_titles(links: Vec<(String, String)>, req: Req) -> Vec<(String, String)> {
+// the links from one message and whether to colour their titles
+pub struct TitleJob {
+    pub network: usize,
+    pub links: Vec<(String, String)>,
+    pub format: TitleFormat,
+}
+
+// the title settings as they were when the link was posted
+#[derive(Clone)]
+pub struct TitleFormat {
+    prefix: String,
+    template: String,
+    use_colours: bool,
+}
+
+impl TitleFormat {
+    pub fn new(settings: &BotConfig) -> TitleFormat {
+        TitleFormat {
+            prefix: settings.title_prefix.to_string(),
+            template: settings.title_format.to_string(),
+            use_colours: settings.use_colors,
+        }
+    }
+}
+
+// links are queued up for a fixed number of these so fetching titles has its
+// own budget and a flood of links can't pile up tasks or hold up anything else
+pub async fn title_worker(
+    jobs: Arc<tokio::sync::Mutex<mpsc::Receiver<TitleJob>>>,
+    req: Req,
+    tx: Sender<Bot>,
+    stats: Arc<Stats>,
+) {
+    loop {
+        // only hold the lock while waiting for a job, not while working on it
+        let job = jobs.lock().await.recv().await;
+        let Some(job) = job else {
+            return;
+        };
+
+        let titles = process_titles(job.links, req.clone(), &job.format).await;
+        for (target, title) in titles {
+            Stats::incr(&stats.titles);
+            let origin = Origin::new(job.network, target);
+            if tx.send(Bot::Privmsg(origin, title)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+pub async fn process_titles(
+    links: Vec<(String, String)>,
+    req: Req,
+    format: &TitleFormat,
+) -> Vec<(String, String)> {
     // the following is adapted from
     // https://stackoverflow.com/questions/63434977/how-can-i-spawn-asynchronous-methods-in-a-loop
     try_join_all(links.into_iter().map(|(t, l)| {
         let req = req.clone();
+        let format = format.clone();
         spawn(async move {
-            if let Ok((target, Some(title))) = fetch_title(t, l, req).await {
-                let response = format!("↳ {}", title.replace('\n', " "));
-                Some((target, response))
-            } else {
-                None
+            match fetch_title(&l, &req).await {
+                Ok(PageTitle {
+                    title: Some(title),
+                    redirect,
+                }) => Some((t, format_title(&title, redirect.as_deref(), &format))),
+                _ => None,
             }
         })
     }))
@@ -530,12 +2558,66 @@ pub async fn process_titles(links: Vec<(String, String)>, req: Req) -> Vec<(Stri
     .collect()
 }
 
-async fn fetch_title(
-    target: String,
-    url: String,
-    req: Req,
-) -> Result<(String, Option<String>), Error> {
-    let content = req.read(&url, 8192).await?;
+// titles are usually short but some sites stuff the whole description in there
+const TITLE_MAX_LEN: usize = 300;
+
+// `redirect` is where the link actually went if it was somewhere else, i.e., a shortener
+fn format_title(title: &str, redirect: Option<&str>, format: &TitleFormat) -> String {
+    let title = title.split_whitespace().join(" ");
+    let title = match title.char_indices().nth(TITLE_MAX_LEN) {
+        Some((i, _)) => format!("{}…", &title[..i]),
+        None => title,
+    };
+    let title = match redirect {
+        Some(host) => format!("{} ({})", title, host),
+        None => title,
+    };
+    // the trailing space is left uncoloured, an empty prefix gets no colour
+    // codes at all
+    let trimmed = format.prefix.trim_end();
+    let prefix = match trimmed.is_empty() {
+        true => format.prefix.to_string(),
+        false => format!(
+            "{}{}",
+            colours::paint(trimmed, colours::GREY, format.use_colours),
+            &format.prefix[trimmed.len()..]
+        ),
+    };
+    // the prefix goes in first so that a title can't sneak a {prefix} in
+    format
+        .template
+        .replace("{prefix}", &prefix)
+        .replace("{title}", &title)
+}
+
+struct PageTitle {
+    title: Option<String>,
+    // the host we were redirected to if it's different to the link's
+    redirect: Option<String>,
+}
+
+// www.example.com and example.com are the same site as far as we're concerned
+fn site(host: &str) -> String {
+    host.trim_start_matches("www.").to_lowercase()
+}
+
+// the site a link points to, links are counted per site for .topdomains
+pub fn link_host(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    Some(site(url.host_str()?))
+}
+
+// redirects within the same site (http -> https, adding www., etc) aren't interesting
+fn redirected_host(original: &str, destination: &Url) -> Option<String> {
+    let original = Url::parse(original).ok()?;
+    let (from, to) = (site(original.host_str()?), site(destination.host_str()?));
+
+    (from != to).then_some(to)
+}
+
+async fn fetch_title(url: &str, req: &impl HttpClient) -> Result<PageTitle, Error> {
+    let (content, destination) = req.read_with_url(url, 8192).await?;
+    let redirect = redirected_host(url, &destination);
 
     let page = kuchiki::parse_html().one(content);
 
@@ -554,40 +2636,128 @@ async fn fetch_title(
                 .and_then(|t| t.attributes.borrow().get("content").map(|t| t.to_string()))
         });
 
-    Ok(match title {
+    let title = match title {
         // youtube is inconsistent, the best option here would be to use the api, an invidious api,
         // or possibly sed youtube.com with an invidious instance
-        Some(t) if t == "YouTube" && og_title.is_some() => (target, og_title),
-        Some(t) if t == "Pleroma" && og_title.is_some() => (target, og_title),
-        _ => (target, title),
-    })
+        Some(t) if t == "YouTube" && og_title.is_some() => og_title,
+        Some(t) if t == "Pleroma" && og_title.is_some() => og_title,
+        _ => title,
+    };
+
+    Ok(PageTitle { title, redirect })
 }
 
-pub fn check_seen(nick: &str, db: &Database) -> String {
+// how many players .trivia top shows
+const TOP_SCORES: u32 = 5;
+
+// after this many days .seen gives the date instead of how long ago it was
+const SEEN_ABSOLUTE_DAYS: i64 = 30;
+
+// how many other nicks .seen mentions
+const SEEN_ALIASES: u32 = 3;
+
+// how many messages .search shows
+const SEARCH_RESULTS: u32 = 3;
+
+// how many lines .last shows
+const LAST_LINES: usize = 3;
+
+// how many domains .topdomains lists
+const TOP_DOMAINS: u32 = 5;
+
+// how many commands .topcommands shows
+const TOP_COMMANDS: u32 = 5;
+
+// command uses are written once there are this many different ones waiting,
+// or once this long has passed since they were last written
+const COMMAND_STATS_BATCH: usize = 20;
+const COMMAND_STATS_INTERVAL: STDDuration = STDDuration::from_secs(60);
+
+// `visible` decides whether the channel someone was seen in can be shown
+pub fn check_seen(
+    nick: &str,
+    db: &Database,
+    visible: impl Fn(&str) -> bool,
+    aliases: bool,
+) -> String {
+    if let Ok(true) = db.is_opted_out(nick) {
+        return "that user has opted out.".to_string();
+    }
+
     match db.check_seen(nick) {
         Ok(Some(p)) => {
             let time = Utc::now();
             let previous = DateTime::parse_from_rfc3339(&p.time).unwrap();
             let duration = time.signed_duration_since(previous);
-            let human_time = HumanTime::from(duration).to_text_en(Accuracy::Rough, Tense::Past);
-            format!("{} was last seen {} {}", p.username, human_time, p.message)
+            // "a year ago" is too vague for anyone who's been gone that long
+            let human_time = match duration.num_days() >= SEEN_ABSOLUTE_DAYS {
+                true => format!("on {}", previous.format("%Y-%m-%d")),
+                false => HumanTime::from(duration).to_text_en(Accuracy::Rough, Tense::Past),
+            };
+
+            // what they said in a channel we can't show is just as private
+            if p.channel.as_deref().is_some_and(|c| !visible(c)) {
+                let action = match p.kind {
+                    SeenKind::Message => "",
+                    SeenKind::Kick => " being kicked from a channel",
+                    SeenKind::Join => " joining a channel",
+                    SeenKind::Part => " leaving a channel",
+                    SeenKind::Quit | SeenKind::Nick => "",
+                };
+                return format!("{} was last seen {}{}", p.username, human_time, action);
+            }
+
+            let action = match (p.kind, &p.channel) {
+                (SeenKind::Message, Some(c)) => format!("in {} saying: {}", c, p.message),
+                (SeenKind::Message, None) => format!("saying: {}", p.message),
+                (SeenKind::Kick, _) => format!("being kicked from {}", p.message),
+                (SeenKind::Quit, _) if p.message.is_empty() => "quitting".to_string(),
+                (SeenKind::Quit, _) => format!("quitting: {}", p.message),
+                (SeenKind::Join, _) => format!("joining {}", p.message),
+                (SeenKind::Part, _) => format!("leaving {}", p.message),
+                (SeenKind::Nick, _) => format!("changing their nick to {}", p.message),
+            };
+            format!(
+                "{} was last seen {} {}{}",
+                p.username,
+                human_time,
+                action,
+                seen_aliases(nick, db, aliases)
+            )
         }
         Ok(None) => format!("{} has not previously been seen", nick),
         Err(_err) => "SQL error".to_string(),
     }
 }
 
-pub fn check_notification(nick: &str, db: &Database) -> Vec<String> {
+// " (also seen as Y, Z)" or nothing at all
+fn seen_aliases(nick: &str, db: &Database, enabled: bool) -> String {
+    if !enabled {
+        return String::new();
+    }
+    match db.nick_aliases(nick, SEEN_ALIASES) {
+        Ok(a) if a.is_empty() => String::new(),
+        Ok(a) => format!(" (also seen as {})", a.join(", ")),
+        Err(err) => {
+            println!("SQL error checking nick aliases: {}", err);
+            String::new()
+        }
+    }
+}
+
+// how many waiting messages are sent to the channel each time someone speaks
+const TELLS_AT_ONCE: usize = 2;
+
+pub fn check_notification(nick: &str, hostmask: Option<&str>, db: &Database) -> Vec<String> {
     let mut notification: Vec<_> = Vec::new();
-    match db.check_notification(nick) {
+    match db.check_notification(nick, hostmask) {
         Ok(n) => {
             for i in n {
-                let message = format!("{}, message from {}: {}", nick, i.via, i.message);
-                notification.push(message);
+                notification.push(format_notification(nick, &i));
                 if let Err(err) = db.remove_notification(i.id) {
                     println!("SQL error checking notification: {}", err)
                 }
-                if notification.len() > 1 {
+                if notification.len() >= TELLS_AT_ONCE {
                     break;
                 }
             }
@@ -598,49 +2768,418 @@ pub fn check_notification(nick: &str, db: &Database) -> Vec<String> {
     notification
 }
 
-pub async fn get_location(loc: &str) -> Result<Option<Location>, Error> {
-    // TODO: add this to settings
-    let opt = WebpageOptions {
-        allow_insecure: true,
+fn format_notification(nick: &str, n: &Notification) -> String {
+    let age = n
+        .created
+        .as_deref()
+        .and_then(|c| DateTime::parse_from_rfc3339(c).ok())
+        .map(|c| {
+            let duration = Utc::now().signed_duration_since(c);
+            let human_time = HumanTime::from(duration).to_text_en(Accuracy::Rough, Tense::Past);
+            format!(" ({})", human_time)
+        })
+        .unwrap_or_default();
+    // matched by hostmask under a different nick
+    let recipient = match n.recipient.eq_ignore_ascii_case(nick) {
+        true => String::new(),
+        false => format!(" for {}", n.recipient),
+    };
+    format!(
+        "{}, message{} from {}{}: {}",
+        nick, recipient, n.via, age, n.message
+    )
+}
+
+// when there are more messages waiting than would be sent at once they're all
+// pasted and whoever they're for gets a link, they're taken out of the
+// database first so speaking again while it's pasting doesn't paste them twice,
+// and they're put back if it doesn't work out, returns false if there weren't
+// enough to bother
+fn paste_notifications(
+    msg: &Msg,
+    hostmask: Option<&str>,
+    db: &Database,
+    url: &str,
+    tx2: &mpsc::Sender<Bot>,
+    req: &Req,
+) -> bool {
+    let notifications = match db.check_notification(&msg.source, hostmask) {
+        Ok(n) if n.len() > TELLS_AT_ONCE => n,
+        Ok(_) => return false,
+        Err(err) => {
+            println!("SQL error checking notification: {}", err);
+            return false;
+        }
+    };
+    for n in &notifications {
+        if let Err(err) = db.remove_notification(n.id) {
+            println!("SQL error checking notification: {}", err)
+        }
+    }
+
+    let nick = msg.source.to_string();
+    let text = notifications
+        .iter()
+        .map(|n| format_notification(&nick, n))
+        .join("\n");
+    let (url, tx2, req, ftarget) = (url.to_string(), tx2.clone(), req.clone(), msg.origin());
+    spawn(async move {
+        let count = notifications.len();
+        let response = match paste(&url, text, &req).await {
+            Ok(link) => format!("{}, you have {} messages: {}", nick, count, link),
+            Err(err) => {
+                println!("Error pasting messages for {}: {}", nick, err);
+                tx2.send(Bot::RestoreNotifications(notifications))
+                    .await
+                    .unwrap();
+                format!(
+                    "{}, you have {} messages, they'll come through a couple at a time",
+                    nick, count
+                )
+            }
+        };
+        tx2.send(Bot::Privmsg(ftarget, response)).await.unwrap();
+    });
+
+    true
+}
+
+// the response should be nothing but the link
+async fn paste(url: &str, text: String, req: &Req) -> Result<String, Error> {
+    let response = req.post_text(url, text).await?;
+    let link = response.trim();
+    if !link.starts_with("http") || link.contains(char::is_whitespace) {
+        bail!("{} didn't reply with a link", url);
+    }
+
+    Ok(link.to_string())
+}
+
+// certificates are only ignored if the operator has asked for it
+fn webpage_options(allow_insecure: bool) -> WebpageOptions {
+    WebpageOptions {
+        allow_insecure,
         follow_location: true,
         max_redirections: 10,
         timeout: STDDuration::from_secs(10),
         // a legitimate user agent is necessary for some sites (twitter)
-        useragent: "Mozilla/5.0 boot-bot-rs/1.3.0".to_string(),
-    };
+        useragent: USER_AGENT.to_string(),
+    }
+}
+
+// `airports` is an OurAirports csv for looking up ICAO/IATA codes, without it
+// (or when a code isn't in it) everything goes to nominatim
+pub async fn get_location(
+    loc: &str,
+    allow_insecure: bool,
+    airports: Option<&str>,
+) -> Result<Option<Location>, Error> {
+    if let Some(path) = airports.filter(|_| airports::is_code(loc.trim())) {
+        let (path, code) = (path.to_string(), loc.trim().to_string());
+        match tokio::task::spawn_blocking(move || airports::find(&path, &code)).await? {
+            Ok(Some(airport)) => return Ok(Some(airport)),
+            Ok(None) => (),
+            Err(err) => println!("Error looking up airport {}: {}", loc, err),
+        }
+    }
+
+    let opt = webpage_options(allow_insecure);
 
     // TODO: this throws an error when a city doesn't exist for a location (i.e., it's a county)
     // TODO: nominatim has a strict limit of 1 request per second, while the channel I run the
     // bot in most certainly won't exceed this limit and I don't think it's likely many channels
     // will either (how many users are going to request weather before an op kicks the bot?)
     // something should be done about this soon to respect nominatim's TOS
+    // the whole thing is searched for so "springfield, il" still finds
+    // illinois, a few more results gives a trailing country code something to
+    // choose between
+    let limit = if country_code(loc).is_some() { 5 } else { 1 };
     let url = format!(
-        "https://nominatim.openstreetmap.org/search?q={}&format=json&addressdetails=1&limit=1",
-        &encode(loc)
+        "https://nominatim.openstreetmap.org/search?q={}&format=json&addressdetails=1&limit={}",
+        &encode(loc),
+        limit
     );
 
     let page = Webpage::from_url(&url, opt)?;
 
-    let mut entry: Vec<Location> = serde_json::from_str(&page.html.text_content)?;
+    let entries: Vec<Location> = serde_json::from_str(&page.html.text_content)?;
 
-    Ok(entry.pop())
+    Ok(pick_location(entries, loc))
 }
 
-pub async fn get_weather(coords: &str, api_key: &str) -> Result<CurrentWeather, String> {
-    let w: CurrentWeather = weather(coords, "metric", "en", api_key)?;
+// the results are ordered by importance, the first one in the country named by
+// a trailing code wins and if there isn't one (i.e., it was a state) the first
+// one does
+fn pick_location(entries: Vec<Location>, loc: &str) -> Option<Location> {
+    let in_country = country_code(loc).and_then(|code| {
+        entries.iter().position(|e| {
+            e.address
+                .country_code
+                .as_deref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(&code))
+        })
+    });
 
-    Ok(w)
+    entries.into_iter().nth(in_country.unwrap_or(0))
+}
+
+// "springfield, us" -> "us"
+fn country_code(loc: &str) -> Option<String> {
+    let (place, code) = loc.rsplit_once(',')?;
+    let (place, code) = (place.trim(), code.trim());
+
+    if place.is_empty() || code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    Some(code.to_lowercase())
+}
+
+// the unit systems supported by OpenWeatherMap, see https://openweathermap.org/api/one-call-3#data
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+    Standard,
+}
+
+impl Units {
+    fn parse(units: &str) -> Option<Units> {
+        match units.to_lowercase().as_ref() {
+            "metric" => Some(Units::Metric),
+            "imperial" => Some(Units::Imperial),
+            "standard" => Some(Units::Standard),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial",
+            Units::Standard => "standard",
+        }
+    }
+
+    // without the degree sign for clients that mangle it
+    fn temp(&self, ascii: bool) -> &'static str {
+        match (self, ascii) {
+            (Units::Metric, false) => "°C",
+            (Units::Metric, true) => "C",
+            (Units::Imperial, false) => "°F",
+            (Units::Imperial, true) => "F",
+            (Units::Standard, _) => "K",
+        }
+    }
+
+    fn speed(&self) -> &'static str {
+        match self {
+            Units::Imperial => "mph",
+            _ => "m/s",
+        }
+    }
+
+    fn to_celsius(self, temp: f64) -> f64 {
+        match self {
+            Units::Metric => temp,
+            Units::Imperial => (temp - 32.0) * 5.0 / 9.0,
+            Units::Standard => temp - 273.15,
+        }
+    }
+
+    fn celsius_to(self, temp: f64) -> f64 {
+        match self {
+            Units::Metric => temp,
+            Units::Imperial => temp * 9.0 / 5.0 + 32.0,
+            Units::Standard => temp + 273.15,
+        }
+    }
+
+    fn to_kmh(self, speed: f64) -> f64 {
+        match self {
+            Units::Imperial => speed * 1.609344,
+            _ => speed * 3.6,
+        }
+    }
 }
 
-pub async fn get_forecast(lat: &str, lon: &str, api_key: &str) -> Result<Forecast, String> {
-    eprintln!("https://api.openweathermap.org/data/2.5/forecast?lat={lat}&lon={lon}&appid={api_key}&units=metric");
+// the north american/canadian wind chill index, only defined for temperatures
+// at or below 10°C and wind speeds above 4.8km/h
+fn wind_chill(celsius: f64, kmh: f64) -> Option<f64> {
+    if celsius > 10.0 || kmh <= 4.8 {
+        return None;
+    }
+
+    let v = kmh.powf(0.16);
+    Some(13.12 + 0.6215 * celsius - 11.37 * v + 0.3965 * celsius * v)
+}
+
+// the rothfusz regression used by the US national weather service, it's only
+// meaningful from around 27°C and 40% humidity
+fn heat_index(celsius: f64, humidity: f64) -> Option<f64> {
+    if celsius < 27.0 || humidity < 40.0 {
+        return None;
+    }
+
+    let t = celsius * 9.0 / 5.0 + 32.0;
+    let rh = humidity;
+    let hi = -42.379 + 2.04901523 * t + 10.14333127 * rh
+        - 0.22475541 * t * rh
+        - 0.00683783 * t * t
+        - 0.05481717 * rh * rh
+        + 0.00122874 * t * t * rh
+        + 0.00085282 * t * rh * rh
+        - 0.00000199 * t * t * rh * rh;
+
+    Some((hi - 32.0) * 5.0 / 9.0)
+}
+
+// splits an optional trailing unit system from a location, i.e., 'london imperial'
+fn split_units(args: &str) -> (Option<&str>, Units) {
+    let args = args.trim();
+    let (location, last) = match args.rsplit_once(char::is_whitespace) {
+        Some((l, u)) => (l.trim(), u),
+        None => ("", args),
+    };
+
+    match Units::parse(last) {
+        Some(units) => (Some(location).filter(|l| !l.is_empty()), units),
+        None => (Some(args).filter(|l| !l.is_empty()), Units::default()),
+    }
+}
+
+// just the time and day, where someone is isn't ours to give away
+fn print_local_time(nick: &str, timezone: i64, now: DateTime<Utc>) -> String {
+    let Some(offset) = i32::try_from(timezone).ok().and_then(FixedOffset::east_opt) else {
+        return format!("Couldn't work out the time for {}", nick);
+    };
+
+    let time = now.with_timezone(&offset);
+    format!(
+        "It's {} on {} for {}",
+        time.format("%l:%M%p").to_string().trim(),
+        time.format("%A"),
+        nick
+    )
+}
+
+// `.weather london yesterday`, the location is None when it's just `yesterday`
+fn split_yesterday(location: Option<&str>) -> Option<Option<&str>> {
+    let location = location?;
+    if location.eq_ignore_ascii_case("yesterday") {
+        return Some(None);
+    }
+
+    match location.rsplit_once(char::is_whitespace) {
+        Some((l, y)) if y.eq_ignore_ascii_case("yesterday") => Some(Some(l.trim())),
+        _ => None,
+    }
+}
+
+// this time yesterday, only available from the one call api
+// https://openweathermap.org/api/one-call-3#history
+pub async fn get_yesterday(
+    lat: &str,
+    lon: &str,
+    units: Units,
+    lang: &str,
+    api_key: &str,
+    req: &Req,
+) -> Result<HistoricWeather, Error> {
+    let dt = (Utc::now() - Duration::days(1)).timestamp();
+    let url = format!(
+        "https://api.openweathermap.org/data/3.0/onecall/timemachine?lat={}&lon={}&dt={}&appid={}&units={}&lang={}",
+        lat,
+        lon,
+        dt,
+        api_key,
+        units.as_str(),
+        encode(lang)
+    );
+    // the url has the api key in it, keep it out of the errors we print
+    let mut response: TimeMachine = req
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url())?
+        .json()
+        .await
+        .map_err(|e| e.without_url())?;
+
+    match response.data.pop() {
+        Some(w) => Ok(w),
+        None => bail!("no data in response"),
+    }
+}
 
-    reqwest::get(format!("https://api.openweathermap.org/data/2.5/forecast?lat={lat}&lon={lon}&appid={api_key}&units=metric"))
+#[derive(Deserialize)]
+struct TimeMachine {
+    data: Vec<HistoricWeather>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoricWeather {
+    temp: f64,
+    weather: Vec<HistoricCondition>,
+}
+
+#[derive(Deserialize)]
+struct HistoricCondition {
+    description: String,
+}
+
+pub fn print_yesterday(
+    now: &CurrentWeather,
+    yesterday: &HistoricWeather,
+    units: Units,
+    ascii: bool,
+) -> String {
+    let unit = units.temp(ascii);
+    let mut response = format!(
+        "{}, {} yesterday: {:.0}{}",
+        now.name, now.sys.country, yesterday.temp, unit
+    );
+    if let Some(condition) = yesterday.weather.first() {
+        write!(response, ", {}", condition.description).unwrap();
+    }
+
+    let delta = (now.main.temp - yesterday.temp).round();
+    match delta {
+        d if d > 0.0 => write!(response, " | {:.0}{} warmer today", d, unit),
+        d if d < 0.0 => write!(response, " | {:.0}{} colder today", -d, unit),
+        _ => write!(response, " | same as today"),
+    }
+    .unwrap();
+
+    response
+}
+
+pub async fn get_weather(
+    coords: &str,
+    units: Units,
+    lang: &str,
+    api_key: &str,
+) -> Result<CurrentWeather, String> {
+    let w: CurrentWeather = weather(coords, units.as_str(), lang, api_key)?;
+
+    Ok(w)
+}
+
+pub async fn get_forecast(
+    lat: &str,
+    lon: &str,
+    lang: &str,
+    api_key: &str,
+) -> Result<Forecast, String> {
+    // the url has the api key in it, keep it out of the errors we print
+    reqwest::get(format!("https://api.openweathermap.org/data/2.5/forecast?lat={lat}&lon={lon}&appid={api_key}&units=metric&lang={}", encode(lang)))
         .await
-        .map_err(|e| e.to_string())?
+        .map_err(|e| e.without_url().to_string())?
         .json()
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.without_url().to_string())
 }
 
 #[derive(Deserialize)]
@@ -682,8 +3221,10 @@ pub struct City {
     country: String,
 }
 
-pub fn print_forecast(weather: Forecast) -> String {
+pub fn print_forecast(weather: Forecast, ascii: bool) -> String {
     let mut builder = String::new();
+    // the forecast is always metric
+    let unit = Units::Metric.temp(ascii);
 
     write!(
         builder,
@@ -707,7 +3248,7 @@ pub fn print_forecast(weather: Forecast) -> String {
 
         write!(
             builder,
-            "{}-{}: {}, {}°C to {}°C",
+            "{}-{}: {}, {}{unit} to {}{unit}",
             (friendly_time)(first_time),
             (friendly_time)(second_time),
             second.weather[0].description,
@@ -732,13 +3273,115 @@ pub fn print_forecast(weather: Forecast) -> String {
     builder
 }
 
+// alerts are only available from the one call api, everything else is excluded
+// https://openweathermap.org/api/one-call-3
+pub async fn get_alerts(
+    lat: &str,
+    lon: &str,
+    lang: &str,
+    api_key: &str,
+    req: &Req,
+) -> Result<Vec<Alert>, Error> {
+    let url = format!(
+        "https://api.openweathermap.org/data/3.0/onecall?lat={}&lon={}&exclude=current,minutely,hourly,daily&appid={}&lang={}",
+        lat,
+        lon,
+        api_key,
+        encode(lang)
+    );
+    // the url has the api key in it, keep it out of the errors we print
+    let response: OneCall = req
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url())?
+        .json()
+        .await
+        .map_err(|e| e.without_url())?;
+    Ok(response.alerts)
+}
+
+#[derive(Deserialize)]
+struct OneCall {
+    // missing entirely when there aren't any
+    #[serde(default)]
+    alerts: Vec<Alert>,
+}
+
+#[derive(Deserialize)]
+pub struct Alert {
+    sender_name: String,
+    event: String,
+    end: i64,
+    // i.e., Wind, Flood, Extreme temperature value
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+pub fn print_alerts(alerts: &[Alert]) -> String {
+    if alerts.is_empty() {
+        return "no active alerts.".to_string();
+    }
+
+    let alerts = alerts
+        .iter()
+        .map(|a| {
+            let mut alert = a.event.to_string();
+            if !a.tags.is_empty() {
+                write!(alert, " ({})", a.tags.join(", ")).unwrap();
+            }
+            if let Some(end) = Utc.timestamp_opt(a.end, 0).single() {
+                write!(alert, " until {}", end.format("%a %H:%M UTC")).unwrap();
+            }
+            if !a.sender_name.is_empty() {
+                write!(alert, " from {}", a.sender_name).unwrap();
+            }
+            alert
+        })
+        .join(" | ");
+
+    format!("Alerts: {}", alerts)
+}
+
 fn friendly_time(s: &str) -> &str {
     s.rsplitn(1, ":").next().unwrap()
 }
 
-pub fn print_weather(weather: CurrentWeather) -> String {
+// returns an icon for the weather condition and whether cloud coverage is worth showing
+// https://openweathermap.org/weather-conditions
+fn weather_condition(id: u64) -> (&'static str, bool) {
+    match id {
+        // thunderstorms
+        200..=232 => ("⛈️", true),
+        // drizzle
+        300..=321 => ("🌦️", true),
+        // rain
+        500..=531 => ("🌧️", true),
+        // snow
+        600..=622 => ("❄️", true),
+        // mist/haze/fog/etc., I don't think cloud coverage matters here
+        700..=781 => ("🌫️", false),
+        // clear
+        800 => ("☀️", false),
+        // clouds
+        801..=804 => ("☁️", true),
+        _ => ("", false),
+    }
+}
+
+pub fn print_weather(
+    weather: CurrentWeather,
+    units: Units,
+    icons: bool,
+    // no emoji, arrows or degree signs
+    ascii: bool,
+    use_colours: bool,
+) -> String {
     // this is dumb, it's only necessary because OpenWeatherMap doesn't fully capitalise weather
     // conditions, see: https://openweathermap.org/weather-conditions
+    // to_uppercase handles non-ascii too, i.e., "éclaircies" or "ясно", and
+    // scripts without case are left alone
     // https://stackoverflow.com/questions/38406793/why-is-capitalizing-the-first-letter-of-a-string-so-convoluted-in-rust/38406885#38406885
     fn uppercase(s: &str) -> String {
         let mut c = s.chars();
@@ -750,22 +3393,15 @@ pub fn print_weather(weather: CurrentWeather) -> String {
 
     let location = &format!("{}, {}", weather.name, weather.sys.country);
 
-    // if the weather condition is cloudy add cloud coverage
-    // https://openweathermap.org/weather-conditions
-    // the 700..=781 range has some conditions like
-    // mist/haze/fog but I don't think cloud coverage matters there
+    // if the weather condition is cloudy add cloud coverage, see weather_condition
     let description = uppercase(&weather.weather[0].description).to_string();
-    let description = match weather.weather[0].id {
-        // thunderstorms
-        200..=232 => format!("{}, {}% cv", description, weather.clouds.all),
-        // drizzle
-        300..=321 => format!("{}, {}% cv", description, weather.clouds.all),
-        // rain
-        500..=531 => format!("{}, {}% cv", description, weather.clouds.all),
-        // snow
-        600..=622 => format!("{}, {}% cv", description, weather.clouds.all),
-        // clouds
-        801..=804 => format!("{}, {}% cv", description, weather.clouds.all),
+    let (icon, cloud_cover) = weather_condition(weather.weather[0].id);
+    let description = match cloud_cover {
+        true => format!("{}, {}% cv", description, weather.clouds.all),
+        false => description,
+    };
+    let description = match icons && !ascii {
+        true if !icon.is_empty() => format!("{} {}", icon, description),
         _ => description,
     };
 
@@ -783,34 +3419,56 @@ pub fn print_weather(weather: CurrentWeather) -> String {
         Err(_) => "Failed to parse time".to_string(),
     };
 
-    let celsius = weather.main.temp.round() as i64;
-    let fahrenheit = ((weather.main.temp * (9.0 / 5.0)) + 32_f64).round() as i64;
+    // the weather is fetched in the units we want to display so there's no converting here
+    let temp = format!("{}{}", weather.main.temp.round() as i64, units.temp(ascii));
+    let temp = colours::paint(
+        &temp,
+        colours::temperature(units.to_celsius(weather.main.temp)),
+        use_colours,
+    );
+
+    // only mention wind chill/heat index when it's noticeably different to the temperature
+    let celsius = units.to_celsius(weather.main.temp);
+    let extreme = match (
+        wind_chill(celsius, units.to_kmh(weather.wind.speed)),
+        heat_index(celsius, weather.main.humidity),
+    ) {
+        (Some(wc), _) if celsius - wc >= 1.0 => format!(
+            " | Wind chill: {}{}",
+            units.celsius_to(wc).round() as i64,
+            units.temp(ascii)
+        ),
+        (_, Some(hi)) if hi - celsius >= 1.0 => format!(
+            " | Heat index: {}{}",
+            units.celsius_to(hi).round() as i64,
+            units.temp(ascii)
+        ),
+        _ => String::new(),
+    };
 
-    let metric_wind = weather.wind.speed.round();
-    let imperial_wind = (weather.wind.speed * 2.2369_f64).round();
+    let speed = units.speed();
     let wind = match weather.wind.gust {
-        Some(g) => {
-            let metric_gust = g.round();
-            let imperial_gust = (g * 2.2369_f64).round();
-            format!(
-                "Wind: {} mph [{} m/s], Gust: {} mph [{} m/s]",
-                imperial_wind, metric_wind, imperial_gust, metric_gust
-            )
-        }
-        None => {
-            format!("Wind: {} mph [{} m/s]", metric_wind, imperial_wind)
-        }
+        Some(g) => format!(
+            "Wind: {} {speed}, Gust: {} {speed}",
+            weather.wind.speed.round(),
+            g.round()
+        ),
+        None => format!("Wind: {} {speed}", weather.wind.speed.round()),
     };
 
-    let direction = [
-        "↓ N", "↙ NE", "← E", "↖ SE", "↑ S", "↗ SW", "→ W", "↘ NW", "↓ N",
-    ];
+    let direction = match ascii {
+        true => ["N", "NE", "E", "SE", "S", "SW", "W", "NW", "N"],
+        false => [
+            "↓ N", "↙ NE", "← E", "↖ SE", "↑ S", "↗ SW", "→ W", "↘ NW", "↓ N",
+        ],
+    };
     let degrees = weather.wind.deg.rem_euclid(360.0).round() as usize / 45;
+    let degree_sign = if ascii { " deg" } else { "°" };
 
-    format!("Weather for {}: {}, {}% Humidity | Temp: {}°C [{}°F] | {} coming from {} - {}° | Sunrise: {} | Sunset: {}",
+    format!("Weather for {}: {}, {}% Humidity | Temp: {}{} | {} coming from {} - {}{} | Sunrise: {} | Sunset: {}",
             location, description, weather.main.humidity,
-            celsius, fahrenheit,
-            wind, direction[degrees], weather.wind.deg,
+            temp, extreme,
+            wind, direction[degrees], weather.wind.deg, degree_sign,
             sunrise, sunset)
 }
 
@@ -823,6 +3481,9 @@ pub struct Coin {
     pub data_0: String,
     // XXBTZUSD high: $44192.8 (05-Tue 22:00:00 UTC) // mean: $44444.49 // low: $41529.8 (05-Tue 07:00:00 UTC)
     pub data_1: String,
+    // only when asked for, the rows of the taller chart separated by newlines
+    #[serde(default)]
+    pub chart: Option<String>,
 }
 
 fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
@@ -856,11 +3517,56 @@ struct OhlcResult {
     _last: i64,
 }
 
+// kraken reports problems in `error` with a 200 and leaves out the result,
+// anything starting with a W is only a warning
 #[derive(Debug, Deserialize)]
-struct Ohlc {
-    #[serde(rename = "error")]
-    _error: Vec<String>,
-    result: OhlcResult,
+struct Kraken<T> {
+    error: Vec<String>,
+    result: Option<T>,
+}
+
+impl<T> Kraken<T> {
+    fn errors(&self) -> Vec<&str> {
+        self.error
+            .iter()
+            .filter(|e| e.starts_with('E'))
+            .map(|e| e.as_str())
+            .collect()
+    }
+
+    fn into_result(self) -> Result<T, Error> {
+        let errors = self.errors();
+        if !errors.is_empty() {
+            bail!("Kraken: {}", errors.join(", "));
+        }
+        self.result.ok_or(err_msg("Kraken didn't return any data"))
+    }
+
+    // worth trying again in a moment rather than giving up straight away
+    fn is_transient(&self) -> bool {
+        let errors = self.errors();
+        !errors.is_empty()
+            && errors
+                .iter()
+                .all(|e| e.starts_with("EAPI:Rate limit") || e.starts_with("EService:"))
+    }
+}
+
+const KRAKEN_ATTEMPTS: u32 = 3;
+
+async fn kraken<T: DeserializeOwned>(url: &str, req: &impl HttpClient) -> Result<T, Error> {
+    let mut attempt = 1;
+    loop {
+        let page = req.read(url, 0).await?;
+        let response: Kraken<T> = serde_json::from_str(&page)?;
+        if attempt < KRAKEN_ATTEMPTS && response.is_transient() {
+            println!("Kraken: {}, trying again", response.errors().join(", "));
+            tokio::time::sleep(STDDuration::from_secs(2 * attempt as u64)).await;
+            attempt += 1;
+            continue;
+        }
+        return response.into_result();
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -880,8 +3586,8 @@ struct TickerData {
     _l: Vec<String>,
     #[serde(rename = "h")]
     _h: Vec<String>,
-    #[serde(rename = "o")]
-    _o: String,
+    // today's opening price
+    o: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -890,37 +3596,123 @@ struct TickerResult {
     data: HashMap<String, TickerData>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Ticker {
-    //#[serde(rename = "error")] _error: Vec<String>,
-    result: TickerResult,
+fn is_stock_symbol(symbol: &str) -> bool {
+    (1..=12).contains(&symbol.len())
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '^'))
+}
+
+#[derive(Debug, Deserialize)]
+struct StockQuote {
+    // current price
+    c: f64,
+    // change since the previous close
+    d: Option<f64>,
+    // percent change since the previous close
+    dp: Option<f64>,
+    // time of the last trade
+    t: i64,
+}
+
+pub async fn get_stock(
+    symbol: &str,
+    url: &str,
+    api_key: &str,
+    req: &Req,
+) -> Result<Option<String>, Error> {
+    let url = format!("{}?symbol={}&token={}", url, encode(symbol), api_key);
+    // the url has the token in it, keep it out of the errors we print
+    let response = req
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url())?;
+    let quote: StockQuote = response.json().await.map_err(|e| e.without_url())?;
+
+    // unknown symbols come back as a quote full of zeros
+    if quote.t == 0 || quote.c == 0.0 {
+        return Ok(None);
+    }
+
+    let change = match (quote.d, quote.dp) {
+        (Some(d), Some(dp)) => format!(" ({:+.2}, {:+.2}%)", d, dp),
+        _ => String::new(),
+    };
+
+    Ok(Some(format!(
+        "{symbol} ${:.2}{change} {}",
+        quote.c,
+        print_date(quote.t, "1d")
+    )))
+}
+
+pub fn kraken_pair(coin: &str) -> &'static str {
+    match coin {
+        "btc" | "bitcoin" => "XXBTZUSD",
+        "btcgbp" => "XXBTZGBP",
+        "btceur" => "XXBTZEUR",
+        "eth" | "ethereum" => "XETHZUSD",
+        "etheur" => "XETHZEUR",
+        "ltc" => "XLTCZUSD",
+        "xmr" | "monero" => "XXMRZUSD",
+        "doge" => "XDGUSD",
+        _ => "XXBTZUSD",
+    }
 }
 
-pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
-    // TODO: add this to settings
-    let opt = WebpageOptions {
-        allow_insecure: true,
-        follow_location: true,
-        max_redirections: 10,
-        timeout: STDDuration::from_secs(10),
-        // a legitimate user agent is necessary for some sites (twitter)
-        useragent: "Mozilla/5.0 boot-bot-rs/1.3.0".to_string(),
-    };
-    // ...
-    let opt2 = WebpageOptions {
-        allow_insecure: true,
-        follow_location: true,
-        max_redirections: 10,
-        timeout: STDDuration::from_secs(10),
-        // a legitimate user agent is necessary for some sites (twitter)
-        useragent: "Mozilla/5.0 boot-bot-rs/1.3.0".to_string(),
-    };
+// just the current price and today's opening price, no ohlc data
+pub async fn get_spot(pair: &str, req: &Req) -> Result<(f32, f32), Error> {
+    let url = format!("https://api.kraken.com/0/public/Ticker?pair={pair}");
+    let ticker: Kraken<TickerResult> = req.get(&url).send().await?.json().await?;
+
+    // the result isn't always keyed by the pair we asked for, i.e., XDGUSD
+    let data = ticker
+        .into_result()?
+        .data
+        .into_values()
+        .next()
+        .ok_or(err_msg("Unable to parse spot data"))?;
+    let spot = data.c.first().ok_or(err_msg("Unable to parse spot data"))?;
+
+    Ok((f32::from_str(spot)?, f32::from_str(&data.o)?))
+}
+
+// BTC $44131.9 | ETH $2250.1 | ...
+pub async fn get_coin_overview(
+    coins: &[String],
+    req: &Req,
+    use_colours: bool,
+) -> Result<String, Error> {
+    let spots = try_join_all(coins.iter().map(|c| get_spot(kraken_pair(c), req))).await?;
+
+    let overview = coins
+        .iter()
+        .zip(spots)
+        .map(|(c, (spot, open))| {
+            let price = format!("{}{}", fiat_sign(kraken_pair(c)), price(spot));
+            let price = colours::paint(&price, colours::up_down(spot >= open), use_colours);
+            format!("{} {}", c.to_uppercase(), price)
+        })
+        .join(" | ");
 
+    Ok(overview)
+}
+
+pub async fn get_coins(
+    coin: &str,
+    time_frame: &str,
+    use_colours: bool,
+    detail: bool,
+    req: &impl HttpClient,
+) -> Result<Coin, Error> {
     let (interval, since) = match time_frame {
         "1d" => (60, Utc::now() - Duration::hours(24)),
         "7d" => (240, Utc::now() - Duration::days(7)),
         "14d" => (240, Utc::now() - Duration::days(14)),
         "31d" => (1440, Utc::now() - Duration::days(31)),
+        "90d" => (1440, Utc::now() - Duration::days(90)),
         "1y" => (21600, Utc::now() - Duration::days(365)),
         "3y" => (21600, Utc::now() - Duration::days(1095)),
         "5y" => (21600, Utc::now() - Duration::days(1825)),
@@ -937,10 +3729,8 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
     println!("ohlc: {ohlc_url}");
     println!("ticker: {ticker_url}");
 
-    let ohlc_page = Webpage::from_url(&ohlc_url, opt)?;
-    let ticker_page = Webpage::from_url(&ticker_url, opt2)?;
-    let mut coin_json: Ohlc = serde_json::from_str(&ohlc_page.html.text_content)?;
-    let mut ticker_json: Ticker = serde_json::from_str(&ticker_page.html.text_content)?;
+    let mut coin_json: OhlcResult = kraken(&ohlc_url, req).await?;
+    let mut ticker_json: TickerResult = kraken(&ticker_url, req).await?;
     let spot_time = Utc::now().timestamp();
 
     //let json_data = r#"{"error":[],"result":{"XXBTZUSD":[[1701730800,"41970.0","41984.7","41793.6","41984.7","41877.4","135.24641260",1812],[1701734400,"41983.0","41983.0","41750.0","41879.5","41833.9","178.09065890",1197],[1701738000,"41879.5","41904.5","41617.6","41799.9","41745.8","113.18066859",1270],[1701741600,"41800.0","41804.6","41621.0","41729.9","41733.5","51.02022883",863],[1701745200,"41730.3","41826.4","41717.9","41818.0","41793.5","51.86326154",725],[1701748800,"41822.4","41825.0","41721.6","41765.7","41773.6","30.21526676",679],[1701752400,"41765.7","41911.7","41721.1","41909.2","41889.6","91.74214454",779],[1701756000,"41909.2","41917.1","41664.5","41720.0","41822.5","98.96134530",1020],[1701759600,"41720.0","41720.0","41427.1","41515.1","41529.8","124.90751096",1330],[1701763200,"41515.1","41624.8","41447.4","41608.4","41555.8","126.96394249",877],[1701766800,"41612.3","41707.1","41608.2","41706.0","41672.2","12.36149485",655],[1701770400,"41706.1","41755.0","41633.7","41633.7","41709.0","32.74293494",709],[1701774000,"41633.7","41729.6","41568.3","41725.7","41656.5","44.50569904",749],[1701777600,"41725.7","41872.3","41691.8","41872.3","41801.8","44.29458914",770],[1701781200,"41872.3","42050.0","41820.9","41835.9","41950.9","265.79221665",2100],[1701784800,"41835.9","42230.0","41835.8","42222.0","42051.8","209.26798469",2066],[1701788400,"42222.0","42490.3","42110.0","42293.0","42278.0","337.86431557",2457],[1701792000,"42293.0","42787.0","42139.5","42735.0","42534.1","561.04636522",3996],[1701795600,"42735.0","43990.0","42691.6","43394.5","43361.0","1111.03024097",7849],[1701799200,"43386.4","44050.0","43320.0","43725.9","43735.8","364.09461761",3573],[1701802800,"43725.8","43943.5","43620.0","43804.1","43755.3","202.74502157",2999],[1701806400,"43804.0","43836.6","43437.0","43782.3","43647.0","175.58621286",2442],[1701810000,"43785.1","44216.0","43724.0","43912.9","43933.1","343.40651248",3343],[1701813600,"43913.0","44465.0","43809.0","44355.0","44192.3","423.89511718",3326]],"last":1701810000}}"#;
@@ -949,13 +3739,11 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
     //let mut ticker_json = serde_json::from_str::<Ticker>(ticker_data)?;
 
     let mut coins = coin_json
-        .result
         .data
         .remove(coin)
         .ok_or(err_msg("Unable to parse coin data"))?;
 
     let spot: TickerData = ticker_json
-        .result
         .data
         .remove(coin)
         .ok_or(err_msg("Unable to parse spot data"))?;
@@ -968,7 +3756,6 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
     let mut min: (f32, usize, i64) = (0.0, 0, 0); // price, count, time
     let mut max: (f32, usize, i64) = (0.0, 0, 0); // price, count, time
     let mut mean: f32 = 0.0;
-    let mut tmp: f32 = 0.0; // tmp value used to sum
 
     // what we want is the min, max, mean, values the prices
     // for 2 week values we average the data to avoid long graphs
@@ -983,17 +3770,7 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
             let high = c.high.parse::<f32>().unwrap_or(c.vwap);
             let low = c.low.parse::<f32>().unwrap_or(c.vwap);
 
-            match time_frame {
-                "14d" => {
-                    if count % 2 == 0 {
-                        prices.push(c.vwap + tmp);
-                        tmp = 0.0;
-                    } else {
-                        tmp += c.vwap;
-                    }
-                }
-                _ => prices.push(c.vwap),
-            }
+            prices.push(c.vwap);
             if high > max.0 {
                 max = (high, count, c.time);
             } else if low < min.0 {
@@ -1003,11 +3780,7 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
         mean += c.vwap;
     }
 
-    match time_frame {
-        // not technically correct but whatever
-        "14d" => prices.push(spot * 2.0),
-        _ => prices.push(spot),
-    }
+    let prices = graph_prices(prices, spot, time_frame);
     if spot > max.0 {
         max = (spot, max.1, spot_time)
     } else if spot < min.0 {
@@ -1018,22 +3791,29 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
     let len = coins.len() + 1;
     mean /= len as f32;
 
-    let sign = match coin {
-        e if e.ends_with("GBP") => "£",
-        _ => "$",
-    };
+    let sign = fiat_sign(coin);
 
     let colour = matches!(time_frame, "3y" | "5y");
 
-    let graph = graph(initial, prices, !colour);
+    let chart = detail.then(|| chart(&prices, &sign).join("\n"));
+
+    let graph = graph(&Sparkline {
+        initial,
+        prices,
+        colour: !colour,
+    });
     let graph = if time_frame != "3y" && time_frame != "5y" {
         format!(
-            "{coin} {sign}{} {} {graph} spot: {sign}{} {}",
-            coins[0].vwap,
+            "{coin} {sign}{} {} {graph} spot: {} {}",
+            price(coins[0].vwap),
             print_date(coins[0].time, time_frame),
             //coins[len - 1].vwap,
             //print_date(coins[len - 1].time, time_frame),
-            spot,
+            colours::paint(
+                &format!("{sign}{}", price(spot)),
+                colours::up_down(spot > coins[0].vwap),
+                use_colours
+            ),
             print_date(spot_time, time_frame)
         )
     } else {
@@ -1041,10 +3821,11 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
     };
 
     let stats = format!(
-        "{coin} high: {sign}{} {} // mean: {sign}{mean} // low: {sign}{} {}",
-        max.0,
+        "{coin} high: {sign}{} {} // mean: {sign}{} // low: {sign}{} {}",
+        price(max.0),
         print_date(max.2, time_frame),
-        min.0,
+        price(mean),
+        price(min.0),
         print_date(min.2, time_frame),
     );
 
@@ -1054,142 +3835,1008 @@ pub async fn get_coins(coin: &str, time_frame: &str) -> Result<Coin, Error> {
         date: recent.time,
         data_0: graph,
         data_1: stats,
+        chart,
     };
 
     Ok(result)
 }
 
+// the bars to graph with spot as the last one, for 14d each pair of bars is
+// averaged so the graph is the same length as a week, an odd bar out at the
+// end is left as is so everything stays on the same scale
+fn graph_prices(prices: Vec<f32>, spot: f32, time_frame: &str) -> Vec<f32> {
+    let mut prices = match time_frame {
+        "14d" => prices
+            .chunks(2)
+            .map(|pair| pair.iter().sum::<f32>() / pair.len() as f32)
+            .collect(),
+        _ => prices,
+    };
+    prices.push(spot);
+    prices
+}
+
+// $50,000.00
+pub fn coin_price(pair: &str, p: f32) -> String {
+    format!("{}{}", fiat_sign(pair), price(p))
+}
+
+// kraken pairs always end with the fiat currency, i.e., XXBTZGBP, fall back to
+// the currency code when we don't know its symbol
+fn fiat_sign(pair: &str) -> String {
+    let signs = [
+        ("USD", "$"),
+        ("GBP", "£"),
+        ("EUR", "€"),
+        ("JPY", "¥"),
+        ("CAD", "C$"),
+        ("AUD", "A$"),
+    ];
+
+    if let Some((_, sign)) = signs.iter().find(|(code, _)| pair.ends_with(code)) {
+        return sign.to_string();
+    }
+
+    match pair.len().checked_sub(3).and_then(|i| pair.get(i..)) {
+        Some(code) => format!("{} ", code),
+        None => "$".to_string(),
+    }
+}
+
+// the more a coin is worth the fewer decimals are worth showing, 44,131.90 but
+// 0.0812 and 0.00001234
+fn price(p: f32) -> String {
+    let decimals = match p.abs() {
+        p if p >= 1.0 => 2,
+        p if p >= 0.01 => 4,
+        _ => 8,
+    };
+    numbers::thousands(&format!("{p:.decimals$}"))
+}
+
 fn print_date(date: i64, time_frame: &str) -> String {
     let time = NaiveDateTime::parse_from_str(&date.to_string(), "%s").unwrap();
     match time_frame {
         // 29-Nov-2023
-        "7d" | "14d" | "31d" | "1y" | "3y" | "5y" => time.format("(%d-%b-%Y)").to_string(),
+        "7d" | "14d" | "31d" | "90d" | "1y" | "3y" | "5y" => time.format("(%d-%b-%Y)").to_string(),
         // Tue-05 02:00:00 UTC
         _ => time.format("(%a-%d %T UTC)").to_string(),
     }
 }
 
-// the following is adapted from
-// https://github.com/jiri/rust-spark
-fn graph(initial: f32, prices: Vec<f32>, colour: bool) -> String {
-    let ticks = "▁▂▃▄▅▆▇█";
-    let colour_red = match colour {
-        true => "\x0304",
-        false => "",
-    };
-    let colour_green = match colour {
-        true => "\x0303",
-        false => "",
-    };
-    let colour_esc = match colour {
-        true => "\x03",
-        false => "",
-    };
+// everything needed to render a sparkline of prices
+struct Sparkline {
+    // the price before the first entry in `prices`, the first bar is green if
+    // it's higher than this and red otherwise, every other bar is compared with
+    // the bar before it
+    initial: f32,
+    // a price of zero (or near enough) means there's no data for that period
+    prices: Vec<f32>,
+    // whether to wrap each bar in IRC colour codes
+    colour: bool,
+}
 
-    /* XXX: This doesn't feel like idiomatic Rust */
-    let mut min: f32 = f32::MAX;
-    let mut max: f32 = 0.0;
+const TICKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-    for &i in prices.iter() {
-        if i > max {
-            max = i;
-        }
-        if i < min && i > 0.0 {
-            min = i;
-        }
-    }
+// the following is adapted from
+// https://github.com/jiri/rust-spark
+//
+// returns one character per price, prices are scaled so the lowest non-zero
+// price is the shortest tick and the highest the tallest, if every price is the
+// same they're all the shortest tick, and prices with no data are a space
+fn ticks(prices: &[f32]) -> Vec<Option<char>> {
+    // the same cut off as below so a near-zero price that's drawn as a gap
+    // doesn't drag the scale down for everything else
+    let min = prices
+        .iter()
+        .copied()
+        .filter(|&p| p > 0.001)
+        .fold(f32::MAX, f32::min);
+    let max = prices.iter().copied().fold(0.0, f32::max);
 
     let ratio = if max == min {
         1.0
     } else {
-        (ticks.chars().count() - 1) as f32 / (max - min)
+        (TICKS.len() - 1) as f32 / (max - min)
     };
 
+    prices
+        .iter()
+        .map(|&p| match p {
+            p if p <= 0.001 => None,
+            p => {
+                let tick = ((p - min) * ratio).round() as usize;
+                Some(TICKS[tick.min(TICKS.len() - 1)])
+            }
+        })
+        .collect()
+}
+
+fn colour_tick(tick: char, up: bool, colour: bool) -> String {
+    match (colour, up) {
+        (false, _) => tick.to_string(),
+        (true, up) => colours::colour(&tick.to_string(), colours::up_down(up)),
+    }
+}
+
+// renders prices as a single line of ticks, green where the price went up
+// and red where it went down (or stayed the same) when colour is enabled
+fn graph(spark: &Sparkline) -> String {
+    let mut previous = spark.initial;
     let mut v = String::new();
-    for (count, p) in prices.iter().enumerate() {
-        let ratio = ((p - min) * ratio).round() as usize;
 
-        if count == 0 {
-            if *p <= 0.001 {
-                v.push_str(" ");
-            } else if p > &initial {
-                v.push_str(&format!(
-                    "{colour_green}{}{colour_esc}",
-                    ticks.chars().nth(ratio).unwrap()
-                ));
-            } else {
-                v.push_str(&format!(
-                    "{colour_red}{}{colour_esc}",
-                    ticks.chars().nth(ratio).unwrap()
-                ));
-            }
-        } else {
-            if *p <= 0.001 {
-                v.push_str(" ");
-            } else if p > &prices[count - 1] {
-                // if the current price is higher than the previous price
-                // the bar should be green, else red
-                v.push_str(&format!(
-                    "{colour_green}{}{colour_esc}",
-                    ticks.chars().nth(ratio).unwrap()
-                ));
-            } else {
-                v.push_str(&format!(
-                    "{colour_red}{}{colour_esc}",
-                    ticks.chars().nth(ratio).unwrap()
-                ));
-            }
+    for (tick, &p) in ticks(&spark.prices).into_iter().zip(spark.prices.iter()) {
+        match tick {
+            Some(t) => v.push_str(&colour_tick(t, p > previous, spark.colour)),
+            None => v.push(' '),
         }
+        previous = p;
     }
 
     v
 }
 
-async fn get_lastfm_scrobble(user: String, req: Req) -> Result<String, Error> {
-    let url = format!("https://www.last.fm/user/{}", encode(&user));
-    let content = req.read(&url, 8192).await?;
-
-    async fn take_last_played(user: String, html: String) -> Option<String> {
-        let page = kuchiki::parse_html().one(html);
-        let recent_tracks = page
-            .select_first(r#"section[id="recent-tracks-section"]"#)
-            .ok()?;
-        let chartlist = recent_tracks
-            .as_node()
-            .select_first(r#"tr[class*="chartlist-row"]"#)
-            .ok()?;
-        let title = chartlist
-            .as_node()
-            .select_first(r#"td[class="chartlist-name"]"#)
-            .ok()?;
-        let artist = chartlist
-            .as_node()
-            .select_first(r#"td[class="chartlist-artist"]"#)
-            .ok()?;
-        let played = chartlist
-            .as_node()
-            .select_first(r#"td[class*="chartlist-timestamp"]"#)
-            .ok()?;
-        let last_played = match played.text_contents().trim() {
-            "Scrobbling now" => format!(
-                "{} is now playing {} by {}",
-                user,
-                title.text_contents().trim(),
-                artist.text_contents().trim()
-            ),
-            _ => format!(
-                "{} last played {} by {} {}",
-                user,
-                title.text_contents().trim(),
-                artist.text_contents().trim(),
-                played.text_contents().trim()
-            ),
+const CHART_ROWS: usize = 6;
+
+// a taller version of graph for when a single line isn't enough detail, each
+// price is a column of blocks CHART_ROWS high with the high and low labelled
+fn chart(prices: &[f32], sign: &str) -> Vec<String> {
+    let min = prices
+        .iter()
+        .copied()
+        .filter(|&p| p > 0.001)
+        .fold(f32::MAX, f32::min);
+    let max = prices.iter().copied().fold(0.0, f32::max);
+    if max <= 0.001 {
+        return Vec::new();
+    }
+
+    // how many eighths of a row each price fills, the lowest price still gets
+    // one so it doesn't look like missing data
+    let steps = CHART_ROWS * TICKS.len();
+    let heights: Vec<usize> = prices
+        .iter()
+        .map(|&p| match p {
+            p if p <= 0.001 => 0,
+            _ if max == min => 1,
+            p => 1 + ((p - min) / (max - min) * (steps - 1) as f32).round() as usize,
+        })
+        .collect();
+
+    let high = format!("{sign}{}", price(max));
+    let low = format!("{sign}{}", price(min));
+    let width = high.chars().count().max(low.chars().count());
+
+    (0..CHART_ROWS)
+        .rev()
+        .map(|row| {
+            let label = match row {
+                r if r == CHART_ROWS - 1 => high.as_str(),
+                0 => low.as_str(),
+                _ => "",
+            };
+            let bars: String = heights
+                .iter()
+                .map(|h| match h.saturating_sub(row * TICKS.len()) {
+                    0 => ' ',
+                    n => TICKS[n.min(TICKS.len()) - 1],
+                })
+                .collect();
+            format!("{label:>width$} ┤{}", bars.trim_end())
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct FearGreedResponse {
+    data: Vec<FearGreedData>,
+}
+
+#[derive(Deserialize)]
+struct FearGreedData {
+    #[serde(deserialize_with = "from_str")]
+    value: u32,
+    value_classification: String,
+}
+
+async fn get_fear_greed(req: &Req) -> Result<String, Error> {
+    let url = "https://api.alternative.me/fng/";
+    let fng: FearGreedResponse = req.get(url).send().await?.json().await?;
+
+    match fng.data.first() {
+        Some(d) => Ok(format!(
+            "Fear & Greed: {} ({})",
+            d.value, d.value_classification
+        )),
+        None => bail!("no data in response"),
+    }
+}
+
+#[derive(Deserialize)]
+struct Synonym {
+    word: String,
+}
+
+const MAX_SYNONYMS: usize = 10;
+
+// `url`?rel_syn=<word>, anything that returns the same fields as datamuse will do
+async fn get_synonyms(url: &str, word: &str, req: &Req) -> Result<Vec<String>, Error> {
+    let url = format!("{}?rel_syn={}&max={}", url, encode(word), MAX_SYNONYMS);
+    let synonyms: Vec<Synonym> = req.get(&url).send().await?.json().await?;
+
+    Ok(synonyms
+        .into_iter()
+        .take(MAX_SYNONYMS)
+        .map(|s| s.word)
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct UrbanResponse {
+    list: Vec<UrbanDefinition>,
+}
+
+#[derive(Deserialize)]
+struct UrbanDefinition {
+    word: String,
+    definition: String,
+    example: String,
+}
+
+const UD_DEFINITION_LEN: usize = 300;
+const UD_EXAMPLE_LEN: usize = 150;
+
+// urban dictionary wraps cross references in [brackets] and is fond of newlines
+fn clean_urban(text: &str, max: usize) -> String {
+    let text = text.replace(['[', ']'], "");
+    let text = text.split_whitespace().join(" ");
+
+    match text.char_indices().nth(max) {
+        Some((i, _)) => format!("{}…", text[..i].trim_end()),
+        None => text,
+    }
+}
+
+async fn get_urban(term: &str, req: &Req) -> Result<Option<String>, Error> {
+    let url = format!(
+        "https://api.urbandictionary.com/v0/define?term={}",
+        encode(term)
+    );
+    let urban: UrbanResponse = req.get(&url).send().await?.json().await?;
+
+    // the api returns the definitions sorted by votes
+    let Some(top) = urban.list.first() else {
+        return Ok(None);
+    };
+
+    let mut response = format!(
+        "{}: {}",
+        top.word,
+        clean_urban(&top.definition, UD_DEFINITION_LEN)
+    );
+    let example = clean_urban(&top.example, UD_EXAMPLE_LEN);
+    if !example.is_empty() {
+        write!(response, " | Example: {}", example)?;
+    }
+
+    Ok(Some(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Canned, ReqBuilder};
+    use crate::sqlite::Address;
+
+    #[test]
+    fn pick_ranges() {
+        assert_eq!(parse_range("1-100"), Some((1, 100)));
+        assert_eq!(parse_range("-10-10"), Some((-10, 10)));
+        assert_eq!(parse_range("-10--5"), Some((-10, -5)));
+        assert_eq!(parse_range("1 - 2"), Some((1, 2)));
+        assert_eq!(parse_range("1-1"), None);
+        assert_eq!(parse_range("100-1"), None);
+        assert_eq!(parse_range("1-1000000001"), None);
+        assert_eq!(parse_range("-1000000001-1"), None);
+        assert_eq!(
+            parse_range("-1000000000-1000000000"),
+            Some((-RNG_MAX, RNG_MAX))
+        );
+        assert_eq!(parse_range("1-"), None);
+        assert_eq!(parse_range("a-b"), None);
+        assert_eq!(parse_range("-"), None);
+    }
+
+    #[test]
+    fn roll_sides() {
+        let settings = BotConfig::default();
+        let roll = |msg| match process_commands("boot", msg, &settings).0 {
+            Task::Rng(RngTask::Pick(low, high)) => Some((low, high)),
+            _ => None,
+        };
+
+        assert_eq!(roll(".roll"), Some((1, 6)));
+        assert_eq!(roll(".roll 20"), Some((1, 20)));
+        assert_eq!(roll(".roll 1"), None);
+        assert_eq!(roll(".roll 0"), None);
+        assert_eq!(roll(".roll -6"), None);
+        assert_eq!(roll(".roll 1000000001"), None);
+    }
+
+    fn spark(initial: f32, prices: &[f32], colour: bool) -> String {
+        graph(&Sparkline {
+            initial,
+            prices: prices.to_vec(),
+            colour,
+        })
+    }
+
+    #[test]
+    fn ticks_scale_between_min_and_max() {
+        assert_eq!(
+            ticks(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]),
+            TICKS.iter().copied().map(Some).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ticks(&[10.0, 80.0, 45.0]),
+            [Some('▁'), Some('█'), Some('▅')]
+        );
+    }
+
+    #[test]
+    fn ticks_all_equal() {
+        // max == min falls back to a ratio of 1 rather than dividing by zero
+        assert_eq!(ticks(&[5.0, 5.0, 5.0]), [Some('▁'); 3]);
+        assert_eq!(ticks(&[42.0]), [Some('▁')]);
+        assert_eq!(ticks(&[]), []);
+    }
+
+    #[test]
+    fn ticks_missing_data() {
+        assert_eq!(ticks(&[0.0, 1.0, 2.0]), [None, Some('▁'), Some('█')]);
+        assert_eq!(ticks(&[0.0005, 1.0, 2.0]), [None, Some('▁'), Some('█')]);
+        assert_eq!(ticks(&[0.0, 0.0]), [None, None]);
+    }
+
+    #[test]
+    fn graph_without_colour() {
+        assert_eq!(spark(1.0, &[1.0, 2.0, 0.0, 8.0], false), "▁▂ █");
+        assert_eq!(spark(3.0, &[3.0], false), "▁");
+    }
+
+    #[test]
+    fn graph_colours_up_and_down() {
+        let up = |t| colours::colour(t, colours::GREEN);
+        let down = |t| colours::colour(t, colours::RED);
+
+        // the first bar compares against the initial price, the rest against
+        // the bar before, staying the same counts as down
+        assert_eq!(
+            spark(2.0, &[1.0, 8.0, 8.0, 1.0], true),
+            [down("▁"), up("█"), down("█"), down("▁")].concat()
+        );
+        assert_eq!(spark(0.5, &[1.0], true), up("▁"));
+        // a gap isn't coloured but the next bar still compares against it
+        assert_eq!(
+            spark(1.0, &[2.0, 0.0, 1.0], true),
+            [up("█"), " ".to_string(), up("▁")].concat()
+        );
+    }
+
+    #[test]
+    fn fourteen_day_series() {
+        // 14 days of 4 hour bars less the first, which is the initial price
+        let bars: Vec<f32> = (1..84).map(|i| 100.0 + i as f32).collect();
+        let prices = graph_prices(bars, 190.0, "14d");
+
+        assert_eq!(prices.len(), 42 + 1);
+        assert_eq!(prices[0], 101.5);
+        // the odd bar out isn't averaged with anything
+        assert_eq!(prices[41], 183.0);
+        // spot is the real price, not doubled to match summed pairs
+        assert_eq!(prices.last(), Some(&190.0));
+
+        let prices = graph_prices(vec![1.0, 3.0, 5.0, 7.0], 6.0, "14d");
+        assert_eq!(prices, [2.0, 6.0, 6.0]);
+    }
+
+    #[test]
+    fn other_series_are_kept() {
+        let bars: Vec<f32> = (1..42).map(|i| i as f32).collect();
+        let prices = graph_prices(bars.clone(), 50.0, "7d");
+
+        assert_eq!(prices.len(), bars.len() + 1);
+        assert_eq!(prices[..bars.len()], bars[..]);
+        assert_eq!(prices.last(), Some(&50.0));
+    }
+
+    #[test]
+    fn fiat_signs() {
+        assert_eq!(fiat_sign("XXBTZUSD"), "$");
+        assert_eq!(fiat_sign("XXBTZGBP"), "£");
+        assert_eq!(fiat_sign("XXBTZEUR"), "€");
+        assert_eq!(fiat_sign("XETHZEUR"), "€");
+        assert_eq!(fiat_sign("XDGUSD"), "$");
+        assert_eq!(fiat_sign("XBTJPY"), "¥");
+        assert_eq!(fiat_sign("XBTCAD"), "C$");
+        assert_eq!(fiat_sign("XBTAUD"), "A$");
+        // unknown currencies fall back to their code
+        assert_eq!(fiat_sign("XBTCHF"), "CHF ");
+        assert_eq!(fiat_sign("XBT"), "XBT ");
+        assert_eq!(fiat_sign("BT"), "$");
+    }
+
+    #[test]
+    fn coin_keywords_use_their_currency() {
+        for (coin, sign) in [
+            ("btc", "$"),
+            ("btcgbp", "£"),
+            ("btceur", "€"),
+            ("etheur", "€"),
+        ] {
+            assert_eq!(fiat_sign(kraken_pair(coin)), sign, "{}", coin);
+        }
+    }
+
+    #[test]
+    fn quoted_args() {
+        assert_eq!(split_args(r#""first arg" second"#), ["first arg", "second"]);
+        assert_eq!(split_args("  one   two  "), ["one", "two"]);
+        assert_eq!(split_args(r#"a "" b"#), ["a", "", "b"]);
+        assert_eq!(split_args(r#"say"hi" there"#), [r#"say"hi""#, "there"]);
+        assert_eq!(split_args(r#""a""b""#), ["a", "b"]);
+        assert_eq!(split_args(""), Vec::<&str>::new());
+        assert_eq!(split_args("   "), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn unbalanced_quotes() {
+        // an unclosed quote runs to the end of the input
+        assert_eq!(split_args(r#"one "two three"#), ["one", "two three"]);
+        assert_eq!(split_args(r#"""#), [""]);
+        assert_eq!(split_args(r#"one ""#), ["one", ""]);
+        // a stray quote in the middle of a word is just part of it
+        assert_eq!(split_args(r#"it"s fine"#), [r#"it"s"#, "fine"]);
+    }
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        match actual {
+            Some(a) => assert!((a - expected).abs() < 0.5, "{} isn't {}", a, expected),
+            None => panic!("expected {}, got None", expected),
+        }
+    }
+
+    #[test]
+    fn wind_chill_reference_values() {
+        // from environment canada's wind chill table
+        assert_near(wind_chill(-10.0, 20.0), -18.0);
+        assert_near(wind_chill(0.0, 10.0), -3.0);
+        assert_near(wind_chill(-20.0, 30.0), -33.0);
+        assert_near(wind_chill(5.0, 40.0), -1.0);
+    }
+
+    #[test]
+    fn wind_chill_thresholds() {
+        assert_eq!(wind_chill(10.5, 30.0), None);
+        assert_eq!(wind_chill(-5.0, 4.8), None);
+        assert!(wind_chill(10.0, 5.0).is_some());
+    }
+
+    #[test]
+    fn heat_index_reference_values() {
+        // from the US national weather service's heat index chart, in °F
+        let fahrenheit = |c: f64| c * 9.0 / 5.0 + 32.0;
+        let celsius = |f: f64| (f - 32.0) * 5.0 / 9.0;
+        assert_near(heat_index(celsius(90.0), 70.0).map(fahrenheit), 106.0);
+        assert_near(heat_index(celsius(100.0), 50.0).map(fahrenheit), 118.0);
+        assert_near(heat_index(celsius(86.0), 40.0).map(fahrenheit), 85.0);
+    }
+
+    #[test]
+    fn heat_index_thresholds() {
+        assert_eq!(heat_index(26.9, 90.0), None);
+        assert_eq!(heat_index(35.0, 39.0), None);
+        assert!(heat_index(27.0, 40.0).is_some());
+    }
+
+    #[test]
+    fn local_time_leaves_out_the_location() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 15, 22, 30, 0).unwrap();
+
+        assert_eq!(
+            print_local_time("alice", 0, now),
+            "It's 10:30PM on Friday for alice"
+        );
+        // past midnight east of utc
+        assert_eq!(
+            print_local_time("bob", 9 * 3600, now),
+            "It's 7:30AM on Saturday for bob"
+        );
+        assert_eq!(
+            print_local_time("carol", -5 * 3600, now),
+            "It's 5:30PM on Friday for carol"
+        );
+        assert_eq!(
+            print_local_time("dave", 100_000, now),
+            "Couldn't work out the time for dave"
+        );
+    }
+
+    #[test]
+    fn price_precision_by_magnitude() {
+        assert_eq!(price(41733.5), "41,733.50");
+        assert_eq!(price(1.0), "1.00");
+        assert_eq!(price(0.99), "0.9900");
+        assert_eq!(price(0.0812), "0.0812");
+        assert_eq!(price(0.01), "0.0100");
+        assert_eq!(price(0.00001234), "0.00001234");
+        assert_eq!(price(0.0), "0.00000000");
+        assert_eq!(price(-0.5), "-0.5000");
+        assert_eq!(price(-1250.0), "-1,250.00");
+    }
+
+    #[test]
+    fn coin_prices_have_their_sign() {
+        assert_eq!(coin_price("XXBTZUSD", 50000.0), "$50,000.00");
+        assert_eq!(coin_price("XDGUSD", 0.0812), "$0.0812");
+        assert_eq!(coin_price("XXBTZEUR", 1234.5), "€1,234.50");
+    }
+
+    fn msg(source: &str, hostmask: Option<&str>) -> Msg {
+        Msg {
+            network: 0,
+            current_nick: "boot".to_string(),
+            source: source.to_string(),
+            target: "#boot".to_string(),
+            hostmask: hostmask.map(String::from),
+            content: String::new(),
+            received: std::time::Instant::now(),
+        }
+    }
+
+    #[test]
+    fn masks() {
+        assert!(mask_matches("*!*@user/niall", "niall!~n@user/niall"));
+        assert!(mask_matches("niall!?n@*", "niall!~n@example.org"));
+        assert!(mask_matches("*", ""));
+        assert!(mask_matches("a*b*c", "abbbc"));
+        assert!(!mask_matches("*!*@user/niall", "niall!~n@user/niall.evil"));
+        assert!(!mask_matches("a*b", "abc"));
+        assert!(!mask_matches("?", ""));
+    }
+
+    #[test]
+    fn admins_need_their_hostmask() {
+        let settings = BotConfig {
+            admins: vec!["*!*@user/Niall".to_string()],
+            ..BotConfig::default()
+        };
+        assert!(is_admin(&settings, &msg("anyone", Some("~n@user/niall"))));
+        assert!(!is_admin(&settings, &msg("niall", Some("~n@example.org"))));
+        // the nick alone is never enough
+        assert!(!is_admin(&settings, &msg("niall", None)));
+    }
+
+    async fn title(req: &Canned, url: &str) -> (Option<String>, Option<String>) {
+        let page = fetch_title(url, req).await.unwrap();
+        (page.title, page.redirect)
+    }
+
+    #[tokio::test]
+    async fn page_titles() {
+        let req = Canned::default()
+            .page(
+                "https://example.com",
+                include_str!("../tests/fixtures/title.html"),
+            )
+            .page(
+                "https://www.youtube.com",
+                include_str!("../tests/fixtures/youtube.html"),
+            )
+            .page(
+                "https://pleroma.example",
+                include_str!("../tests/fixtures/pleroma.html"),
+            )
+            .page(
+                "https://blank.example",
+                include_str!("../tests/fixtures/no_title.html"),
+            )
+            .redirect(
+                "https://t.co",
+                "https://www.example.com/",
+                include_str!("../tests/fixtures/title.html"),
+            )
+            .redirect(
+                "http://example.com",
+                "https://www.example.com/",
+                include_str!("../tests/fixtures/title.html"),
+            );
+
+        // og:title is only used when the title is no help
+        let example = Some("Example Domain".to_string());
+        assert_eq!(
+            title(&req, "https://example.com/").await,
+            (example.clone(), None)
+        );
+        assert_eq!(
+            title(&req, "https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+                .await
+                .0
+                .unwrap(),
+            "Rick Astley - Never Gonna Give You Up (Official Music Video)"
+        );
+        assert_eq!(
+            title(&req, "https://pleroma.example/notice/AbC123")
+                .await
+                .0
+                .unwrap(),
+            "niall"
+        );
+        assert_eq!(title(&req, "https://blank.example/").await, (None, None));
+
+        let redirect = Some("example.com".to_string());
+        assert_eq!(
+            title(&req, "https://t.co/abc").await,
+            (example.clone(), redirect)
+        );
+        assert_eq!(title(&req, "http://example.com/").await, (example, None));
+        assert!(fetch_title("https://nowhere.example/", &req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn coins_from_kraken() {
+        let req = Canned::default()
+            .page(
+                "https://api.kraken.com/0/public/OHLC?pair=XXBTZUSD&",
+                include_str!("../tests/fixtures/kraken_ohlc.json"),
+            )
+            .page(
+                "https://api.kraken.com/0/public/Ticker?pair=XXBTZUSD",
+                include_str!("../tests/fixtures/kraken_ticker.json"),
+            )
+            .page(
+                "https://api.kraken.com/0/public/OHLC?pair=NOPE",
+                include_str!("../tests/fixtures/kraken_error.json"),
+            );
+
+        let coin = get_coins("XXBTZUSD", "1d", false, false, &req)
+            .await
+            .unwrap();
+        assert!(
+            coin.data_0.starts_with("XXBTZUSD $41,877.40 "),
+            "{}",
+            coin.data_0
+        );
+        assert!(coin.data_0.contains("spot: $44,099.90"), "{}", coin.data_0);
+        assert!(coin.data_1.contains("high: $44,099.90"), "{}", coin.data_1);
+        assert_eq!(coin.date, 1701741600);
+        assert!(coin.chart.is_none());
+
+        let err = get_coins("NOPE", "1d", false, false, &req)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Kraken: EQuery:Unknown asset pair");
+    }
+
+    #[test]
+    fn commands() {
+        let settings = BotConfig::default();
+        let task = |msg| process_commands("boot", msg, &settings).0;
+
+        assert!(matches!(task(".help"), Task::Message(HELP)));
+        assert!(matches!(task("!man"), Task::Message(HELP)));
+        assert!(matches!(task(".uptime"), Task::Uptime));
+        assert!(matches!(task(".ping"), Task::Ping));
+        assert!(matches!(task(".version"), Task::Version));
+        assert!(matches!(task(".topic"), Task::Topic(None)));
+        assert!(matches!(
+            task(".topic  new topic "),
+            Task::Topic(Some("new topic"))
+        ));
+        assert!(matches!(task(".channels"), Task::Channels));
+        assert!(matches!(task(".topdomains"), Task::TopDomains));
+        assert!(matches!(task(".topcommands"), Task::TopCommands));
+        assert!(matches!(task(".last bob"), Task::Last("bob")));
+        assert!(matches!(task(".search foo bar"), Task::Search("foo bar")));
+        assert!(matches!(task(".grep foo"), Task::Grep("foo")));
+        assert!(matches!(task(".reload"), Task::Reload));
+        assert!(matches!(
+            task(".raw PRIVMSG #a :hi"),
+            Task::Raw("PRIVMSG #a :hi")
+        ));
+        assert!(matches!(task(".pause"), Task::Pause(true)));
+        assert!(matches!(task(".resume"), Task::Pause(false)));
+        assert!(matches!(task(".optout"), Task::OptOut(true)));
+        assert!(matches!(task(".optin"), Task::OptOut(false)));
+        assert!(matches!(task(".mute"), Task::Mute(true)));
+        assert!(matches!(task(".unmute"), Task::Mute(false)));
+        assert!(matches!(task(".seen bob"), Task::Seen("bob")));
+        assert!(matches!(
+            task(".tell bob hi there"),
+            Task::Tell("bob", "hi there")
+        ));
+        assert!(matches!(task(".weather"), Task::Weather(None, _)));
+        assert!(matches!(task(".weather @bob"), Task::WeatherFor("bob", _)));
+        assert!(matches!(task(".time bob"), Task::LocalTime("bob")));
+        assert!(matches!(task(".forecast"), Task::Forecast(None)));
+        assert!(matches!(task(".alerts paris"), Task::Alerts(Some("paris"))));
+        assert!(matches!(task(".fng"), Task::FearGreed));
+        assert!(matches!(task(".title https://example.com"), Task::Title(_)));
+        assert!(matches!(
+            task(".alias add hi .tell bob hi"),
+            Task::Alias(AliasTask::Add("hi", "tell bob hi", false))
+        ));
+        assert!(matches!(
+            task(".alias chandel hi"),
+            Task::Alias(AliasTask::Del("hi", true))
+        ));
+        assert!(matches!(task(".stock AAPL"), Task::Stock("AAPL")));
+        assert!(matches!(task(".syn happy"), Task::Synonyms("happy")));
+        assert!(matches!(task(".ud yeet"), Task::Urban("yeet")));
+        assert!(matches!(task(".loc new york"), Task::Location("new york")));
+        assert!(matches!(task(".coins"), Task::CoinOverview));
+        assert!(matches!(
+            task(".remind every day 09:00 stretch"),
+            Task::Remind(RemindTask::Every("day", "09:00", None, "stretch"))
+        ));
+        assert!(matches!(
+            task(".remind every mon 09:00 Europe/London stand up"),
+            Task::Remind(RemindTask::Every(
+                "mon",
+                "09:00",
+                Some("Europe/London"),
+                "stand up"
+            ))
+        ));
+        assert!(matches!(
+            task(".remind stop #3"),
+            Task::Remind(RemindTask::Stop("3"))
+        ));
+        assert!(matches!(task(".reminders"), Task::Remind(RemindTask::List)));
+        assert!(matches!(task(".fig hi"), Task::Fig("hi")));
+        assert!(matches!(
+            task(".coinalert"),
+            Task::CoinAlert(CoinAlertTask::List)
+        ));
+        assert!(matches!(
+            task(".coinalert btc above $50,000"),
+            Task::CoinAlert(CoinAlertTask::Add("btc", true, t)) if t == 50000.0
+        ));
+        assert!(matches!(
+            task(".coinalert stop #2"),
+            Task::CoinAlert(CoinAlertTask::Stop("2"))
+        ));
+        assert!(matches!(task(".np"), Task::NowPlaying(NpTask::Show(None))));
+        assert!(matches!(
+            task(".np bob"),
+            Task::NowPlaying(NpTask::Show(Some("bob")))
+        ));
+        assert!(matches!(
+            task(".np set bob"),
+            Task::NowPlaying(NpTask::Set("lastfm", "bob"))
+        ));
+        assert!(matches!(task(".lbz bob"), Task::ListenBrainz("bob")));
+        assert!(matches!(task(".lastfm bob"), Task::Lastfm("bob")));
+        assert!(matches!(task(".anagram abc"), Task::Anagram("abc")));
+        assert!(matches!(task(".scramble"), Task::Scramble));
+        assert!(matches!(task(".trivia"), Task::Trivia));
+        assert!(matches!(task(".trivia top"), Task::TriviaScores));
+        assert!(matches!(task(".hang"), Task::HangStart("")));
+        assert!(matches!(task(".hang LONG"), Task::HangStart("long")));
+        assert!(matches!(task(".flip"), Task::Rng(RngTask::Flip)));
+        assert!(matches!(task(".calc 1 + 1"), Task::Calc("1 + 1")));
+        assert!(matches!(task(".wether"), Task::Suggest("weather")));
+        assert!(matches!(task(".zzzzzz"), Task::Ignore));
+    }
+
+    #[test]
+    fn hints() {
+        let settings = BotConfig::default();
+        let hint = |msg| match process_commands("boot", msg, &settings).0 {
+            Task::Message(m) => Some(m),
+            _ => None,
+        };
+
+        for (msg, expected) in [
+            (".last", "Hint: last <nick>"),
+            (".search  ", "Hint: search <text>"),
+            (".grep", "Hint: grep <text>"),
+            (".raw", "Hint: raw <irc line>"),
+            (".seen", "Hint: seen <nick>"),
+            (".tell", "Hint: tell <nick> <message>"),
+            (".tell bob", "Hint: tell <nick> <message>"),
+            (".weather @", "Hint: weather @<nick>"),
+            (".localtime", "Hint: localtime <nick>"),
+            (".title", "Hint: title <url>"),
+            (".stock", "Hint: stock <symbol>, i.e., stock AAPL"),
+            (".syn", "Hint: syn <word>"),
+            (".ud", "Hint: ud <term>"),
+            (".loc", "Hint: loc|location <location>"),
+            (".remind", REMIND_HINT),
+            (".remind every day 09:00", REMIND_HINT),
+            (".remind every day 09:00 Europe/London", REMIND_HINT),
+            (".remind stop", "Hint: remind stop <id>"),
+            (".fig", "Hint: fig <text>"),
+            (".coinalert stop", "Hint: coinalert stop <id>"),
+            (".coinalert btc sideways 5", COIN_ALERT_HINT),
+            (".coinalert btc above", COIN_ALERT_HINT),
+            (".coinalert nope above 5", COIN_ALERT_HINT),
+            (".np set", "Hint: np set [service] <username>"),
+            (".lbz", "Hint: lbz <username>"),
+            (".anagram 123", "Hint: anagram <letters>"),
+            (".pick", "Hint: pick <low-high>"),
+            (".roll x", "Hint: roll [sides]"),
+            (".calc", "Hint: calc <expr>"),
+        ] {
+            assert_eq!(hint(msg), Some(expected), "{}", msg);
+        }
+
+        let alias = "Hint: alias <add|chanadd> <name> <command> | alias <del|chandel> <name>";
+        assert_eq!(hint(".alias"), Some(alias));
+        assert_eq!(hint(".alias add hi"), Some(alias));
+        assert_eq!(hint(".alias add h.i .help"), Some(alias));
+    }
+
+    #[test]
+    fn addressing() {
+        let settings = BotConfig::default();
+        let task = |msg| process_commands("boot", msg, &settings).0;
+
+        assert!(matches!(task("./ping"), Task::Ping));
+        assert!(matches!(task("!ping"), Task::Ping));
+        assert!(matches!(task("boot: ping"), Task::Ping));
+        assert!(matches!(task("Boot, ping"), Task::Ping));
+        assert!(matches!(task("boot: seen bob"), Task::Seen("bob")));
+        assert!(matches!(task("boot"), Task::Highlight));
+        assert!(matches!(task("boot:"), Task::Highlight));
+        // a lone . or ! is just punctuation
+        assert!(matches!(task(". ping"), Task::Ignore));
+        assert!(matches!(task("ping me later"), Task::Ignore));
+
+        let addressed = |msg| process_commands("boot", msg, &settings).1;
+        assert!(addressed("!ping"));
+        assert!(addressed("boot: seen bob"));
+        assert!(addressed("boot"));
+        // still meant for us even if it isn't anything we know
+        assert!(addressed(".hepl"));
+        assert!(!addressed("."));
+        assert!(!addressed("ping"));
+        assert!(!addressed("ping me later"));
+    }
+
+    #[test]
+    fn coin_time_frames() {
+        let mut settings = BotConfig::default();
+        settings
+            .coin_aliases
+            .insert("bitty".to_string(), "btc".to_string());
+        settings
+            .coin_times
+            .insert("month".to_string(), "31d".to_string());
+        settings
+            .coin_times
+            .insert("week".to_string(), "14d".to_string());
+        let coins = |msg| match process_commands("boot", msg, &settings).0 {
+            Task::Coins(coin, time, detail) => Some((coin, time, detail)),
+            _ => None,
         };
-        Some(last_played)
+
+        assert_eq!(coins(".btc"), Some(("btc", "1d", false)));
+        assert_eq!(coins(".BTC 1W"), Some(("btc", "7d", false)));
+        assert_eq!(coins(".eth fortnight detail"), Some(("eth", "14d", true)));
+        assert_eq!(coins(".doge detail"), Some(("doge", "1d", true)));
+        assert_eq!(coins(".btc whenever"), Some(("btc", "1d", false)));
+        // [bot] coin_times adds names and takes priority over the built-ins
+        assert_eq!(coins(".bitty month"), Some(("btc", "31d", false)));
+        assert_eq!(coins(".ltc week"), Some(("ltc", "14d", false)));
+        assert_eq!(coins(".notacoin"), None);
+    }
+
+    #[test]
+    fn hangman_guesses() {
+        let settings = BotConfig::default();
+        let task = |msg| process_commands("boot", msg, &settings).0;
+
+        assert!(matches!(task("e"), Task::Hang("e")));
+        assert!(matches!(task(" e "), Task::Hang("e")));
+        assert!(matches!(task("E"), Task::HangGuess("E")));
+        assert!(matches!(task("apple"), Task::HangGuess("apple")));
+        assert!(matches!(task(".guess apple"), Task::Guess("apple")));
+        assert!(matches!(
+            task("!guess"),
+            Task::Message("Hint: guess <word>")
+        ));
+        // difficulties only ever start a game
+        assert!(matches!(task("medium"), Task::Ignore));
+        assert!(matches!(task("two words"), Task::Ignore));
+    }
+
+    // answers every request with `status` and a json `body`
+    async fn serve(status: &'static str, body: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/quote", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn stock_quotes() {
+        let req = ReqBuilder::new().build().unwrap();
+
+        let url = serve("200 OK", r#"{"c":189.5,"d":1.25,"dp":0.66,"t":1700000000}"#).await;
+        let quote = get_stock("AAPL", &url, "secret", &req).await.unwrap();
+        assert!(quote.unwrap().starts_with("AAPL $189.50 (+1.25, +0.66%)"));
+
+        let url = serve("200 OK", r#"{"c":0,"d":null,"dp":null,"t":0}"#).await;
+        let quote = get_stock("NOPE", &url, "secret", &req).await.unwrap();
+        assert_eq!(quote, None);
+
+        // an error status is an error, not an unknown symbol, and the token
+        // in the url stays out of it
+        let url = serve("401 Unauthorized", r#"{"error":"Invalid API key."}"#).await;
+        let err = get_stock("AAPL", &url, "secret", &req).await.unwrap_err();
+        assert!(err.to_string().contains("401"));
+        assert!(!err.to_string().contains("secret"));
+    }
+
+    fn places(found: &[(&str, &str, &str)]) -> Vec<Location> {
+        found
+            .iter()
+            .map(|(city, country, code)| Location {
+                lat: "0".to_string(),
+                lon: "0".to_string(),
+                address: Address {
+                    city: Some(city.to_string()),
+                    country: country.to_string(),
+                    country_code: Some(code.to_string()),
+                },
+            })
+            .collect()
+    }
+
+    fn picked(found: &[(&str, &str, &str)], loc: &str) -> Option<String> {
+        pick_location(places(found), loc)
+            .map(|l| format!("{}, {}", l.address.city.unwrap(), l.address.country))
     }
 
-    match take_last_played(user, content).await {
-        Some(r) => Ok(r),
-        None => bail!("No song data found!"),
+    #[test]
+    fn country_codes() {
+        assert_eq!(country_code("Springfield, US"), Some("us".to_string()));
+        assert_eq!(country_code("London,ca"), Some("ca".to_string()));
+        assert_eq!(country_code("London"), None);
+        assert_eq!(country_code(", US"), None);
+        assert_eq!(country_code("Washington, D.C."), None);
+
+        let london = [
+            ("London", "United Kingdom", "gb"),
+            ("London", "Canada", "ca"),
+        ];
+        assert_eq!(picked(&london, "London, CA").unwrap(), "London, Canada");
+        assert_eq!(picked(&london, "London").unwrap(), "London, United Kingdom");
+
+        // a state abbreviation isn't the country of any result so it's left
+        // to nominatim, which already knows "il" is illinois
+        let springfield = [("Springfield", "United States", "us")];
+        assert_eq!(
+            picked(&springfield, "Springfield, IL").unwrap(),
+            "Springfield, United States"
+        );
+        let paris = [("Paris", "United States", "us"), ("Paris", "France", "fr")];
+        assert_eq!(picked(&paris, "Paris, TX").unwrap(), "Paris, United States");
+        assert_eq!(picked(&[], "Austin, TX"), None);
     }
 }
@@ -0,0 +1,213 @@
+use failure::{bail, Error};
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub const MAX_EXPR_LEN: usize = 128;
+
+// a small recursive descent parser for basic arithmetic, the grammar is:
+// expr    = term (('+' | '-') term)*
+// term    = unary (('*' | '/' | '%') unary)*
+// unary   = '-' unary | power
+// power   = primary ('^' unary)?
+// primary = number | '(' expr ')'
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        while let Some(c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+        self.chars.peek().copied()
+    }
+
+    fn expr(&mut self) -> Result<f64, Error> {
+        let mut value = self.term()?;
+        loop {
+            match self.peek() {
+                Some('+') => {
+                    self.chars.next();
+                    value += self.term()?;
+                }
+                Some('-') => {
+                    self.chars.next();
+                    value -= self.term()?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, Error> {
+        let mut value = self.unary()?;
+        loop {
+            match self.peek() {
+                Some('*') => {
+                    self.chars.next();
+                    value *= self.unary()?;
+                }
+                Some('/') => {
+                    self.chars.next();
+                    let divisor = self.unary()?;
+                    if divisor == 0.0 {
+                        bail!("division by zero");
+                    }
+                    value /= divisor;
+                }
+                Some('%') => {
+                    self.chars.next();
+                    let divisor = self.unary()?;
+                    if divisor == 0.0 {
+                        bail!("division by zero");
+                    }
+                    value %= divisor;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, Error> {
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(-self.unary()?)
+            }
+            Some('+') => {
+                self.chars.next();
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, Error> {
+        let base = self.primary()?;
+        match self.peek() {
+            Some('^') => {
+                self.chars.next();
+                // right associative, 2^3^2 is 2^9
+                let exponent = self.unary()?;
+                Ok(base.powf(exponent))
+            }
+            _ => Ok(base),
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, Error> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.expr()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(value)
+                    }
+                    _ => bail!("missing closing parenthesis"),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !c.is_ascii_digit() && c != '.' {
+                        break;
+                    }
+                    number.push(c);
+                    self.chars.next();
+                }
+                match number.parse::<f64>() {
+                    Ok(n) => Ok(n),
+                    Err(_) => bail!("invalid number {}", number),
+                }
+            }
+            Some(c) => bail!("unexpected '{}'", c),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+}
+
+pub fn evaluate(expr: &str) -> Result<f64, Error> {
+    if expr.len() > MAX_EXPR_LEN {
+        bail!("expression is too long");
+    }
+
+    let mut parser = Parser {
+        chars: expr.chars().peekable(),
+    };
+    let value = parser.expr()?;
+
+    if let Some(c) = parser.peek() {
+        bail!("unexpected '{}'", c);
+    }
+    if !value.is_finite() {
+        bail!("result is out of range");
+    }
+
+    Ok(value)
+}
+
+pub fn format_result(value: f64) -> String {
+    // print whole numbers without the trailing .0 as long as they fit in an i64
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(expr: &str) -> String {
+        match evaluate(expr) {
+            Ok(value) => format_result(value),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(calc("2+2*3"), "8");
+        assert_eq!(calc("(2+2)*3"), "12");
+        assert_eq!(calc("10 - 4 - 3"), "3");
+        assert_eq!(calc("2^3^2"), "512");
+        assert_eq!(calc("-2^2"), "-4");
+        assert_eq!(calc("2*-3"), "-6");
+        assert_eq!(calc("7 % 4"), "3");
+        assert_eq!(calc("1/4"), "0.25");
+        assert_eq!(calc(" 1.5 + .5 "), "2");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(calc("1/0"), "division by zero");
+        assert_eq!(calc("1%(2-2)"), "division by zero");
+        assert_eq!(calc("10^400"), "result is out of range");
+        assert_eq!(calc("(1+2"), "missing closing parenthesis");
+        assert_eq!(calc("1+2)"), "unexpected ')'");
+        assert_eq!(calc("2+"), "unexpected end of expression");
+        assert_eq!(calc("1.2.3"), "invalid number 1.2.3");
+        assert_eq!(calc("2x"), "unexpected 'x'");
+        assert_eq!(
+            calc(&"1+".repeat(MAX_EXPR_LEN / 2 - 1)),
+            "unexpected end of expression"
+        );
+        assert_eq!(
+            calc(&"1+".repeat(MAX_EXPR_LEN / 2 + 1)),
+            "expression is too long"
+        );
+    }
+
+    #[test]
+    fn whole_numbers() {
+        assert_eq!(format_result(3.0), "3");
+        assert_eq!(format_result(-0.5), "-0.5");
+        assert_eq!(format_result(1e15), "1000000000000000");
+    }
+}
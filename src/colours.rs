@@ -0,0 +1,39 @@
+// mirc colour codes, see https://modern.ircdocs.horse/formatting#colors
+// these are always two digits so text starting with a number isn't mistaken
+// for part of the code
+pub const GREEN: &str = "03";
+pub const RED: &str = "04";
+pub const ORANGE: &str = "07";
+pub const LIGHT_GREEN: &str = "09";
+pub const CYAN: &str = "11";
+pub const LIGHT_BLUE: &str = "12";
+pub const GREY: &str = "14";
+
+pub fn colour(text: &str, colour: &str) -> String {
+    format!("\x03{colour}{text}\x03")
+}
+
+// colours text only when colours are turned on
+pub fn paint(text: &str, colour: &str, enabled: bool) -> String {
+    match enabled {
+        true => self::colour(text, colour),
+        false => text.to_string(),
+    }
+}
+
+pub fn up_down(up: bool) -> &'static str {
+    match up {
+        true => GREEN,
+        false => RED,
+    }
+}
+
+pub fn temperature(celsius: f64) -> &'static str {
+    match celsius {
+        c if c < 0.0 => LIGHT_BLUE,
+        c if c < 10.0 => CYAN,
+        c if c < 20.0 => LIGHT_GREEN,
+        c if c < 30.0 => ORANGE,
+        _ => RED,
+    }
+}
@@ -0,0 +1,195 @@
+use crate::http::Req;
+use crate::poller::Poller;
+use crate::settings::FeedConfig;
+use crate::sqlite::Database;
+use crate::{Bot, Origin};
+use failure::Error;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+
+// a feed that's been down for a while can come back with a whole backlog of
+// entries, we don't want to dump all of them into the channel at once
+const MAX_ANNOUNCE: usize = 3;
+
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+}
+
+pub async fn fetch_feed(url: &str, req: &Req) -> Result<Vec<FeedEntry>, Error> {
+    let content = req.read(url, 1024).await?;
+    parse_feed(&content)
+}
+
+fn parse_feed(content: &str) -> Result<Vec<FeedEntry>, Error> {
+    let feed = feed_rs::parser::parse(content.as_bytes())?;
+
+    let entries = feed
+        .entries
+        .into_iter()
+        .map(|e| FeedEntry {
+            id: e.id,
+            title: e
+                .title
+                .map(|t| t.content.replace('\n', " "))
+                .unwrap_or_else(|| "(untitled)".to_string()),
+            link: e.links.first().map(|l| l.href.to_string()),
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+// what to say about entries that haven't been `seen` and the ids to remember.
+// the first time we see a feed we only want to record what's already there,
+// otherwise every restart with a new feed would flood the channel
+fn announce(entries: Vec<FeedEntry>, seen: &[String]) -> (Vec<String>, Vec<String>) {
+    let first_poll = seen.is_empty();
+
+    let new: Vec<FeedEntry> = entries
+        .into_iter()
+        .filter(|e| !seen.contains(&e.id))
+        .collect();
+
+    let mut announcements = Vec::new();
+    if !first_poll {
+        // feeds are newest first, announce them in the order they were published
+        for e in new.iter().take(MAX_ANNOUNCE).rev() {
+            announcements.push(match &e.link {
+                Some(l) => format!("{} {}", e.title, l),
+                None => e.title.to_string(),
+            });
+        }
+    }
+
+    (announcements, new.into_iter().map(|e| e.id).collect())
+}
+
+// how often a feed is checked, never more than once a minute
+pub fn interval(feed: &FeedConfig) -> Duration {
+    Duration::from_secs(feed.interval.max(60))
+}
+
+pub async fn poll_feed(
+    feed: FeedConfig,
+    network: usize,
+    db: Database,
+    req: Req,
+    tx: Sender<Bot>,
+    mut poller: Poller,
+) {
+    loop {
+        poller.tick().await;
+
+        let entries = match fetch_feed(&feed.url, &req).await {
+            Ok(e) => e,
+            Err(err) => {
+                println!("Error fetching feed {}: {}", feed.url, err);
+                continue;
+            }
+        };
+
+        let seen = match db.check_feed_entries(&feed.url) {
+            Ok(s) => s,
+            Err(err) => {
+                println!("SQL error checking feed entries: {}", err);
+                continue;
+            }
+        };
+
+        let (announcements, ids) = announce(entries, &seen);
+        if ids.is_empty() {
+            continue;
+        }
+
+        // nothing's listening once the network's gone
+        for response in announcements {
+            let origin = Origin::new(network, &feed.channel);
+            if tx.send(Bot::Privmsg(origin, response)).await.is_err() {
+                return;
+            }
+        }
+        if tx
+            .send(Bot::UpdateFeed(feed.url.to_string(), ids))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(seen: &[&str]) -> Vec<String> {
+        seen.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn entries() {
+        let entries = parse_feed(include_str!("../tests/fixtures/feed.xml")).unwrap();
+        let titles: Vec<_> = entries.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            [
+                "boot 1.5.0",
+                "boot 1.4.0",
+                "boot 1.3.0",
+                "(untitled)",
+                "boot 1.2.0"
+            ]
+        );
+        assert_eq!(entries[0].id, "release-1.5.0");
+        assert_eq!(entries[3].link, None);
+    }
+
+    #[test]
+    fn first_poll_is_quiet() {
+        let entries = parse_feed(include_str!("../tests/fixtures/feed.xml")).unwrap();
+        let (announcements, new) = announce(entries, &[]);
+        assert!(announcements.is_empty());
+        assert_eq!(new.len(), 5);
+    }
+
+    #[test]
+    fn only_new_entries() {
+        let feed = include_str!("../tests/fixtures/feed.xml");
+
+        let seen = ids(&["release-1.4.0", "release-1.3.0", "notes", "release-1.2.0"]);
+        let (announcements, new) = announce(parse_feed(feed).unwrap(), &seen);
+        assert_eq!(
+            announcements,
+            ["boot 1.5.0 https://example.com/releases/1.5.0"]
+        );
+        assert_eq!(new, ["release-1.5.0"]);
+
+        // everything's been seen
+        let seen = ids(&[
+            "release-1.5.0",
+            "release-1.4.0",
+            "release-1.3.0",
+            "notes",
+            "release-1.2.0",
+        ]);
+        let (announcements, new) = announce(parse_feed(feed).unwrap(), &seen);
+        assert!(announcements.is_empty());
+        assert!(new.is_empty());
+
+        // a backlog only announces the newest few, oldest first, but all of
+        // it is remembered
+        let seen = ids(&["release-1.2.0"]);
+        let (announcements, new) = announce(parse_feed(feed).unwrap(), &seen);
+        assert_eq!(
+            announcements,
+            [
+                "boot 1.3.0 https://example.com/releases/1.3.0",
+                "boot 1.4.0 https://example.com/releases/1.4.0",
+                "boot 1.5.0 https://example.com/releases/1.5.0",
+            ]
+        );
+        assert_eq!(new.len(), 4);
+    }
+}
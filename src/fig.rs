@@ -0,0 +1,124 @@
+// a small built-in banner font for .fig, three rows tall so it doesn't flood
+// the channel, i.e.,
+//  _   _   _  ___
+// |_) / \ / \  |
+// |_) \_/ \_/  |
+
+// anything longer than this is refused rather than cut short
+pub const MAX_FIG_LEN: usize = 10;
+
+const ROWS: usize = 3;
+
+fn glyph(c: char) -> Option<[&'static str; ROWS]> {
+    let g = match c.to_ascii_uppercase() {
+        'A' => [" _ ", "|_|", "| |"],
+        'B' => [" _ ", "|_)", "|_)"],
+        'C' => [" _", "/ ", "\\_"],
+        'D' => [" _ ", "| \\", "|_/"],
+        'E' => [" _", "|_", "|_"],
+        'F' => [" _", "|_", "| "],
+        'G' => [" __", "/ _", "\\_|"],
+        'H' => ["   ", "|_|", "| |"],
+        'I' => ["___", " | ", "_|_"],
+        'J' => ["  _", "  |", "|_|"],
+        'K' => ["  ", "|/", "|\\"],
+        'L' => ["  ", "| ", "|_"],
+        'M' => ["    ", "|\\/|", "|  |"],
+        'N' => ["    ", "|\\ |", "| \\|"],
+        'O' => [" _ ", "/ \\", "\\_/"],
+        'P' => [" _ ", "|_)", "|  "],
+        'Q' => [" _ ", "/ \\", "\\_X"],
+        'R' => [" _ ", "|_)", "| \\"],
+        'S' => [" _", "(_", " _)"],
+        'T' => ["___", " | ", " | "],
+        'U' => ["   ", "| |", "|_|"],
+        'V' => ["   ", "\\ /", " V "],
+        'W' => ["    ", "|  |", "|/\\|"],
+        'X' => ["  ", "\\/", "/\\"],
+        'Y' => ["   ", "\\_/", " | "],
+        'Z' => ["__", " /", "/_"],
+        '0' => [" _ ", "|/|", "|_|"],
+        '1' => [" ", "|", "|"],
+        '2' => [" _ ", " _|", "|_ "],
+        '3' => ["_ ", "_|", "_|"],
+        '4' => ["   ", "|_|", "  |"],
+        '5' => [" _ ", "|_ ", " _|"],
+        '6' => [" _ ", "|_ ", "|_|"],
+        '7' => ["__", " /", "/ "],
+        '8' => [" _ ", "|_|", "|_|"],
+        '9' => [" _ ", "|_|", " _|"],
+        ' ' => ["  ", "  ", "  "],
+        '!' => [" ", "|", "o"],
+        '?' => ["_ ", " )", "o "],
+        '.' => [" ", " ", "o"],
+        '-' => ["  ", "__", "  "],
+        _ => return None,
+    };
+
+    Some(g)
+}
+
+// the banner's lines, or None when the text is empty, too long or has
+// characters the font doesn't cover
+pub fn render(text: &str) -> Option<Vec<String>> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > MAX_FIG_LEN {
+        return None;
+    }
+
+    let glyphs = text.chars().map(glyph).collect::<Option<Vec<_>>>()?;
+
+    let lines = (0..ROWS)
+        .map(|row| {
+            let line = glyphs.iter().map(|g| g[row]).collect::<Vec<_>>().join(" ");
+            // the reset code keeps leading spaces from being trimmed by
+            // us or by clients, which would throw the columns out of line
+            format!("\x0f{}", line.trim_end())
+        })
+        // an all-blank top row, i.e., for "hull", is just wasted flood allowance
+        .filter(|l| l.len() > 1)
+        .collect();
+
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // without the reset codes
+    fn rows(text: &str) -> Option<Vec<String>> {
+        let lines = render(text)?;
+        Some(lines.iter().map(|l| l.replace('\x0f', "")).collect())
+    }
+
+    #[test]
+    fn banner() {
+        assert_eq!(
+            rows("boot").unwrap(),
+            [" _   _   _  ___", "|_) / \\ / \\  |", "|_) \\_/ \\_/  |"]
+        );
+        // every line starts with a reset so leading spaces survive
+        assert!(render("boot")
+            .unwrap()
+            .iter()
+            .all(|l| l.starts_with('\x0f')));
+        assert_eq!(rows(" Boot ").unwrap(), rows("BOOT").unwrap());
+    }
+
+    #[test]
+    fn blank_top_row() {
+        assert_eq!(rows("hull").unwrap(), ["|_| | | |  |", "| | |_| |_ |_"]);
+        assert_eq!(rows("hi").unwrap().len(), ROWS);
+    }
+
+    #[test]
+    fn refused() {
+        assert_eq!(render(""), None);
+        assert_eq!(render("   "), None);
+        assert!(render(&"a".repeat(MAX_FIG_LEN)).is_some());
+        assert_eq!(render(&"a".repeat(MAX_FIG_LEN + 1)), None);
+        assert_eq!(render("héllo"), None);
+        assert_eq!(render("a_b"), None);
+    }
+}
@@ -1,14 +1,43 @@
 use bytes::BytesMut;
+use futures::future::BoxFuture;
 use futures::StreamExt;
-use reqwest::{Client, Error, RequestBuilder};
+use reqwest::{Client, Error, RequestBuilder, Url};
+use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::Semaphore;
 
 pub static USER_AGENT: &str = "Mozilla/5.0 boot-bot-rs/1.3.0";
 
+// whatever pages are read through, this is Req everywhere except when something
+// else needs to stand in for the network, i.e., with canned responses
+pub trait HttpClient: Send + Sync {
+    // the first `kb` kilobytes of a page and the url we ended up at after any redirects
+    fn read_with_url<'a>(
+        &'a self,
+        url: &'a str,
+        kb: usize,
+    ) -> BoxFuture<'a, Result<(String, Url), failure::Error>>;
+
+    fn read<'a>(
+        &'a self,
+        url: &'a str,
+        kb: usize,
+    ) -> BoxFuture<'a, Result<String, failure::Error>> {
+        Box::pin(async move {
+            let (content, _) = self.read_with_url(url, kb).await?;
+            Ok(content)
+        })
+    }
+}
+
 #[derive(Default)]
 pub struct ReqBuilder<'a> {
     timeout: Option<Duration>,
     user_agent: Option<&'a str>,
+    max_concurrent: Option<usize>,
+    local_address: Option<IpAddr>,
+    allow_insecure: bool,
 }
 
 impl<'a> ReqBuilder<'a> {
@@ -26,6 +55,24 @@ impl<'a> ReqBuilder<'a> {
         self
     }
 
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent);
+        self
+    }
+
+    // binding to an unspecified address (0.0.0.0 or ::) picks the ip version
+    // without tying requests to a particular interface
+    pub fn local_address(mut self, local_address: Option<IpAddr>) -> Self {
+        self.local_address = local_address;
+        self
+    }
+
+    // skips certificate verification, only for hosts with self-signed certs
+    pub fn allow_insecure(mut self, allow_insecure: bool) -> Self {
+        self.allow_insecure = allow_insecure;
+        self
+    }
+
     pub fn build(&self) -> Result<Req, Error> {
         let timeout = match self.timeout {
             Some(t) => t,
@@ -35,13 +82,23 @@ impl<'a> ReqBuilder<'a> {
             Some(u) => u,
             _ => USER_AGENT,
         };
+        // a semaphore without any permits would block every request forever
+        let max_concurrent = match self.max_concurrent {
+            Some(m) => m.max(1),
+            _ => 8,
+        };
 
         let client = reqwest::Client::builder()
             .timeout(timeout)
             .user_agent(user_agent)
+            .local_address(self.local_address)
+            .danger_accept_invalid_certs(self.allow_insecure)
             .build()?;
 
-        let req = Req { client };
+        let req = Req {
+            client,
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+        };
 
         Ok(req)
     }
@@ -50,6 +107,9 @@ impl<'a> ReqBuilder<'a> {
 #[derive(Clone)]
 pub struct Req {
     client: Client,
+    // shared between clones so that every task reading through this client
+    // counts towards the same limit
+    permits: Arc<Semaphore>,
 }
 
 impl Req {
@@ -59,13 +119,59 @@ impl Req {
     pub fn get(&self, url: &str) -> RequestBuilder {
         self.client.get(url)
     }
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(url)
+    }
+    // POSTs `body` as json and returns the response, this and post_form are
+    // test only until something needs them
+    #[cfg(test)]
+    pub async fn post_json<T: serde::Serialize + ?Sized>(
+        &self,
+        url: &str,
+        body: &T,
+    ) -> Result<String, reqwest::Error> {
+        self.send(self.post(url).json(body)).await
+    }
+    // POSTs `body` url encoded, i.e., &[("key", "value")], and returns the response
+    #[cfg(test)]
+    pub async fn post_form<T: serde::Serialize + ?Sized>(
+        &self,
+        url: &str,
+        body: &T,
+    ) -> Result<String, reqwest::Error> {
+        self.send(self.post(url).form(body)).await
+    }
+    // POSTs `body` as it is and returns the response
+    pub async fn post_text(&self, url: &str, body: String) -> Result<String, reqwest::Error> {
+        self.send(self.post(url).body(body)).await
+    }
+    // counts towards max_concurrent like read does, error statuses are errors
+    async fn send(&self, request: RequestBuilder) -> Result<String, reqwest::Error> {
+        // the semaphore is never closed so this can't fail
+        let _permit = self.permits.acquire().await.unwrap();
+
+        request.send().await?.error_for_status()?.text().await
+    }
     pub async fn read(&self, url: &str, kb: usize) -> Result<String, reqwest::Error> {
+        let (content, _) = self.read_with_url(url, kb).await?;
+        Ok(content)
+    }
+    // like read but also returns the url we ended up at after any redirects
+    pub async fn read_with_url(
+        &self,
+        url: &str,
+        kb: usize,
+    ) -> Result<(String, Url), reqwest::Error> {
         let size = match kb {
             s if s > 0 => s * 1024,
             _ => 0,
         };
 
+        // the semaphore is never closed so this can't fail
+        let _permit = self.permits.acquire().await.unwrap();
+
         let body = self.get(url).send().await?;
+        let final_url = body.url().clone();
 
         let mut stream = body.bytes_stream();
         let mut bytes = BytesMut::new();
@@ -80,6 +186,135 @@ impl Req {
             }
         }
 
-        Ok(String::from_utf8_lossy(&bytes).into_owned())
+        Ok((String::from_utf8_lossy(&bytes).into_owned(), final_url))
+    }
+}
+
+impl HttpClient for Req {
+    fn read_with_url<'a>(
+        &'a self,
+        url: &'a str,
+        kb: usize,
+    ) -> BoxFuture<'a, Result<(String, Url), failure::Error>> {
+        Box::pin(async move { Ok(Req::read_with_url(self, url, kb).await?) })
+    }
+}
+
+// stands in for the network in tests, a page is served for any url starting
+// with its prefix, from `to` if it's a redirect
+#[cfg(test)]
+#[derive(Default)]
+pub struct Canned {
+    pages: Vec<(String, String, Option<String>)>,
+}
+
+#[cfg(test)]
+impl Canned {
+    pub fn page(mut self, prefix: &str, body: &str) -> Self {
+        self.pages
+            .push((prefix.to_string(), body.to_string(), None));
+        self
+    }
+
+    pub fn redirect(mut self, prefix: &str, to: &str, body: &str) -> Self {
+        let to = Some(to.to_string());
+        self.pages.push((prefix.to_string(), body.to_string(), to));
+        self
+    }
+}
+
+#[cfg(test)]
+impl HttpClient for Canned {
+    fn read_with_url<'a>(
+        &'a self,
+        url: &'a str,
+        kb: usize,
+    ) -> BoxFuture<'a, Result<(String, Url), failure::Error>> {
+        Box::pin(async move {
+            let Some((_, body, to)) = self.pages.iter().find(|(p, _, _)| url.starts_with(p)) else {
+                failure::bail!("nothing canned for {}", url);
+            };
+            let body = match kb {
+                0 => body.as_bytes(),
+                kb => &body.as_bytes()[..body.len().min(kb * 1024)],
+            };
+            let url = Url::parse(to.as_deref().unwrap_or(url))?;
+            Ok((String::from_utf8_lossy(body).into_owned(), url))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // answers one request with "ok" and hands back the content type and body it
+    // was sent
+    async fn posted<F>(post: impl FnOnce(Req, String) -> F) -> (String, String)
+    where
+        F: std::future::Future<Output = String>,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/post", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            // the headers then however much body they said there'd be
+            let (head, length) = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).into_owned();
+                if let Some((head, _)) = text.split_once("\r\n\r\n") {
+                    let length = header(head, "content-length").parse::<usize>().unwrap();
+                    break (head.to_string(), length);
+                }
+            };
+            while request.len() < head.len() + 4 + length {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+            stream.write_all(response.as_bytes()).await.unwrap();
+            let body = String::from_utf8_lossy(&request[head.len() + 4..]).into_owned();
+            (header(&head, "content-type"), body)
+        });
+
+        let req = ReqBuilder::new().build().unwrap();
+        assert_eq!(post(req, url).await, "ok");
+        server.await.unwrap()
+    }
+
+    fn header(head: &str, name: &str) -> String {
+        head.lines()
+            .filter_map(|l| l.split_once(':'))
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.trim().to_string())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn json_posts() {
+        let (content_type, body) = posted(|req, url| async move {
+            let body = HashMap::from([("q", "hello")]);
+            req.post_json(&url, &body).await.unwrap()
+        })
+        .await;
+        assert_eq!(content_type, "application/json");
+        assert_eq!(body, r#"{"q":"hello"}"#);
+    }
+
+    #[tokio::test]
+    async fn form_posts() {
+        let (content_type, body) = posted(|req, url| async move {
+            let body = [("q", "hello world"), ("source", "en")];
+            req.post_form(&url, &body).await.unwrap()
+        })
+        .await;
+        assert_eq!(content_type, "application/x-www-form-urlencoded");
+        assert_eq!(body, "q=hello+world&source=en");
     }
 }
@@ -1,39 +1,136 @@
 #![feature(str_split_whitespace_remainder)]
 use futures::prelude::*;
 use irc::client::prelude::*;
+mod airports;
 mod bot;
+mod calc;
+mod colours;
+mod feeds;
+mod fig;
 mod http;
 mod messages;
+mod music;
+mod numbers;
+mod poller;
+mod reminders;
+mod script;
 mod settings;
 mod sqlite;
+mod stats;
+mod throttle;
+mod trivia;
 //use crate::bot::{check_notification, check_seen, Coin};
-use crate::bot::Coin;
+use crate::bot::{Coin, TitleFormat, TitleJob};
 use crate::http::{Req, ReqBuilder};
 use crate::messages::Msg;
-use crate::settings::Settings;
-use crate::sqlite::{Database, Location, Notification, Seen};
+use crate::poller::Pollers;
+use crate::settings::{Difficulty, HangmanConfig, Settings};
+use crate::sqlite::{Database, Location, LoggedMessage, Notification, Seen};
+use crate::throttle::{split_message, Throttle};
+use crate::trivia::{Question, Round};
+use chrono::Utc;
 use irc::client::ClientStream;
 use messages::process_message;
-use rand::prelude::IteratorRandom;
+use rand::prelude::{IteratorRandom, SliceRandom};
 use rand::{thread_rng, Rng};
+use std::collections::HashMap;
 use std::fmt::{Display, Error, Formatter, Write};
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+// a channel or nick on one of the networks we're connected to, anything sent
+// in reply to a message goes back to where the message came from
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub network: usize,
+    pub target: String,
+}
+
+impl Origin {
+    pub fn new(network: usize, target: impl Into<String>) -> Origin {
+        Origin {
+            network,
+            target: target.into(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Bot {
     Message(Msg),
-    Links(Vec<(String, String)>),
-    Privmsg(String, String),
+    // source nick, (target, url)
+    Links(String, Vec<(String, String)>),
+    Privmsg(Origin, String),
+    // source nick, target, message, dropped if the source has muted the bot
+    EasterEgg(String, Origin, String),
     UpdateSeen(Seen),
+    // messages taken out to be pasted that need to go back when that fails
+    RestoreNotifications(Vec<Notification>),
+    // old nick, new nick, user@host
+    NickAlias(String, String, String),
     UpdateWeather(String, String, String),
     UpdateLocation(String, Location),
     UpdateCoins(Coin),
+    UpdateFeed(String, Vec<String>),
+    // reminder id, when it next goes off
+    UpdateReminder(u32, i64),
+    // coin alert id, once it's gone off
+    ClearCoinAlert(u32),
+    UpdateTopic(String, String),
+    SetTopic(String, String),
+    // channel, nick
+    Join(String, String),
+    // channel, when we're the one joining
+    Joined(String),
+    // channel, mode, see JoinActions
+    Mode(String, String),
     Quit(String, String),
     Hang(String, String),
-    HangGuess(String, String),
+    // target, word, whether it was a .guess
+    HangGuess(String, String, bool),
+    // target, letters
+    Anagram(String, String),
+    Scramble(String),
+    // channel
+    Trivia(String),
+    TriviaQuestion(String, Question),
+    // channel, round
+    TriviaTimeout(String, u64),
+    // target to report back to
+    Reload(String),
+    // admin, target to report back to, irc line
+    Raw(String, String, String),
+    // the connection closed or the watchdog gave up on it, see run_network
+    Disconnected,
+}
+
+// commands that can't be sent with .raw, there are better ways to quit and the
+// rest would only get us disconnected or worse
+const RAW_BLOCKED: [&str; 6] = ["QUIT", "SQUIT", "KILL", "DIE", "RESTART", "PASS"];
+
+// seconds after joining before setting modes, ChanServ needs time to op us
+const JOIN_MODE_DELAY: u64 = 5;
+
+// messages with links waiting for a title worker, anything past this is dropped
+const TITLE_QUEUE: usize = 32;
+
+// seconds to wait before reconnecting a network, doubled after every failed
+// attempt in a row up to RECONNECT_MAX
+const RECONNECT_DELAY: u64 = 10;
+const RECONNECT_MAX: u64 = 300;
+
+// the main loop sends some events to itself, awaiting a full channel there would
+// mean waiting on ourselves forever, and for anything else that isn't worth
+// waiting for a full channel means we're already behind so drop it
+pub fn send_or_drop(tx: &mpsc::Sender<Bot>, event: Bot) {
+    if let Err(mpsc::error::TrySendError::Full(event)) = tx.try_send(event) {
+        println!("Event channel is full, dropping {:?}", event);
+    }
 }
 
 struct Hang {
@@ -56,6 +153,103 @@ impl Default for Hang {
     }
 }
 
+// wrong guesses allowed before the game is lost
+const MAX_ATTEMPTS: u8 = 7;
+
+impl Hang {
+    // i.e., "-a--a- | 5 guesses left | found: [a] | wrong: [e, s]"
+    fn status(&self) -> String {
+        let mut found: Vec<_> = self
+            .state
+            .chars()
+            .filter(|c| c.is_ascii_lowercase())
+            .map(String::from)
+            .collect();
+        found.sort();
+        found.dedup();
+
+        let left = MAX_ATTEMPTS.saturating_sub(self.attempts);
+        format!(
+            "{} | {} {} left | found: {} | wrong: {}",
+            self.state,
+            left,
+            if left == 1 { "guess" } else { "guesses" },
+            PrintCharsNicely(&found),
+            PrintCharsNicely(&self.guesses)
+        )
+    }
+}
+
+// how guessing the whole word went
+#[derive(Debug, PartialEq)]
+enum WordGuess {
+    Won,
+    // it's been guessed already, nothing's lost
+    Repeated,
+    Wrong,
+    // and that was the last attempt
+    Hanged,
+    // not a guess, most likely just people talking
+    Ignored,
+}
+
+impl Hang {
+    // anyone saying the word wins but only a .guess that could fit costs an
+    // attempt, otherwise any chatter would hang the game
+    fn guess_word(&mut self, word: &str, addressed: bool) -> WordGuess {
+        if !self.started {
+            return WordGuess::Ignored;
+        }
+        if word.eq_ignore_ascii_case(&self.word) {
+            self.state = self.word.to_string();
+            return WordGuess::Won;
+        }
+        if !addressed || word.chars().count() != self.word.chars().count() {
+            return WordGuess::Ignored;
+        }
+
+        let word = word.to_lowercase();
+        if self.guesses.contains(&word) {
+            return WordGuess::Repeated;
+        }
+        self.guesses.push(word);
+        self.attempts += 1;
+
+        match self.attempts >= MAX_ATTEMPTS {
+            true => WordGuess::Hanged,
+            false => WordGuess::Wrong,
+        }
+    }
+}
+
+// what to say when the last guess has been used up
+fn hanged(word: &str, rng: &mut impl Rng) -> Vec<String> {
+    let n = rng.gen_range(1..100) > 50;
+    let o: u32 = rng.gen_range(1..100);
+
+    let mut dead: Vec<String> = vec![
+        "  +---+".to_string(),
+        "  |   |".to_string(),
+        "  O   |".to_string(),
+        " /|\\  |".to_string(),
+        " /`\\  |".to_string(),
+        "      |".to_string(),
+        "=======".to_string(),
+    ];
+
+    if n {
+        dead[4] = " / \\  |".to_string();
+    }
+
+    let mut lines = if o > 95 { dead } else { Vec::new() };
+    lines.push(format!(
+        "{} dead, jim! The word was {}.",
+        if n { "She's" } else { "He's" },
+        word
+    ));
+    lines
+}
+
 // credits: 99% dilflover69, 1% me
 pub struct PrintCharsNicely<'a>(&'a Vec<String>);
 
@@ -83,83 +277,620 @@ enum WordType {
 // https://stackoverflow.com/questions/50788009/how-do-i-get-a-random-line-from-a-file
 const FILENAME: &str = "/usr/share/dict/british-english";
 
-fn find_word(style: WordType) -> String {
+// nothing at all when the dictionary can't be read, the games say so instead
+fn words() -> impl Iterator<Item = String> {
     let f = File::open(FILENAME)
-        .unwrap_or_else(|e| panic!("(;_;) file not found: {}: {}", FILENAME, e));
-    let f = BufReader::new(f);
+        .map_err(|e| println!("(;_;) file not found: {}: {}", FILENAME, e))
+        .ok();
 
-    let lines = f
-        .lines()
-        .map(|l| l.expect("readerror"))
+    f.into_iter()
+        .flat_map(|f| BufReader::new(f).lines().map_while(Result::ok))
         .filter(|l| !l.ends_with("'s"))
-        .filter(|l| match style {
-            WordType::Short => l.len() < 6,
-            WordType::Medium => (4..9).contains(&l.len()),
-            WordType::Long => l.len() > 8,
-        });
+}
+
+// how often each letter shows up in english text (%), a-z
+const LETTER_FREQUENCY: [f32; 26] = [
+    8.2, 1.5, 2.8, 4.3, 12.7, 2.2, 2.0, 6.1, 7.0, 0.15, 0.77, 4.0, 2.4, 6.7, 7.5, 1.9, 0.095, 6.0,
+    6.3, 9.1, 2.8, 0.98, 2.4, 0.15, 2.0, 0.074,
+];
+
+// the average rarity of the letters in a word, people guess common letters
+// first so "jazz" is a lot harder than "entertainment" despite being shorter
+fn word_difficulty(word: &str) -> f32 {
+    let mut letters: Vec<_> = word
+        .bytes()
+        .filter(u8::is_ascii_lowercase)
+        .map(|b| usize::from(b - b'a'))
+        .collect();
+    letters.sort_unstable();
+    letters.dedup();
+    if letters.is_empty() {
+        return 0.0;
+    }
+
+    let rarity: f32 = letters
+        .iter()
+        .map(|&l| -(LETTER_FREQUENCY[l] / 100.0).log2())
+        .sum();
+    rarity / letters.len() as f32
+}
+
+fn fits(style: &WordType, word: &str, lengths: &HangmanConfig) -> bool {
+    match style {
+        WordType::Short => word.len() <= lengths.short,
+        WordType::Medium => word.len() > lengths.short && word.len() < lengths.long,
+        WordType::Long => word.len() >= lengths.long,
+    }
+}
+
+fn find_word(style: WordType, lengths: &HangmanConfig) -> Option<String> {
+    if lengths.difficulty == Difficulty::Frequency {
+        let bucket = frequency_bucket(scored_words(), &style, lengths);
+        return bucket
+            .choose(&mut rand::thread_rng())
+            .map(|w| w.to_string());
+    }
+
+    let lines = words().filter(|l| fits(&style, l, lengths));
 
-    lines.choose(&mut rand::thread_rng()).expect("emptyfile")
+    lines.choose(&mut rand::thread_rng())
+}
+
+// the whole dictionary with each word's difficulty, easiest first, this is
+// only worked out once since it means reading and sorting every word
+fn scored_words() -> &'static [(f32, String)] {
+    static SCORED: OnceLock<Vec<(f32, String)>> = OnceLock::new();
+
+    SCORED.get_or_init(|| {
+        let mut words: Vec<_> = words()
+            .map(|w| (word_difficulty(&w.to_lowercase()), w))
+            .collect();
+        words.sort_by(|a, b| a.0.total_cmp(&b.0));
+        words
+    })
+}
+
+// the words of the right length, then the easiest third of those for short
+// and the hardest third for long
+fn frequency_bucket<'a>(
+    scored: &'a [(f32, String)],
+    style: &WordType,
+    lengths: &HangmanConfig,
+) -> Vec<&'a str> {
+    let words: Vec<_> = scored
+        .iter()
+        .map(|(_, w)| w.as_str())
+        .filter(|w| fits(style, w, lengths))
+        .collect();
+
+    let third = words.len() / 3;
+    let bucket = match style {
+        WordType::Short => &words[..third],
+        WordType::Medium => &words[third..third * 2],
+        WordType::Long => &words[third * 2..],
+    };
+    bucket.to_vec()
+}
+
+// words are anagrams of each other if they have the same letters once sorted
+fn anagram_key(word: &str) -> Vec<char> {
+    let mut key: Vec<_> = word.to_lowercase().chars().collect();
+    key.sort_unstable();
+    key
+}
+
+const MAX_ANAGRAMS: usize = 10;
+
+fn find_anagrams(letters: &str) -> Vec<String> {
+    let key = anagram_key(letters);
+    let mut found: Vec<_> = words()
+        .filter(|w| w.len() == letters.len())
+        .map(|w| w.to_lowercase())
+        .filter(|w| !w.eq_ignore_ascii_case(letters) && anagram_key(w) == key)
+        .collect();
+    found.dedup();
+    found.truncate(MAX_ANAGRAMS);
+    found
+}
+
+// a word for .scramble along with its letters shuffled, proper nouns are left
+// out since they'd be near impossible to guess
+fn scramble_word(lengths: &HangmanConfig) -> Option<(String, String)> {
+    let word = words()
+        .filter(|w| w.len() > lengths.short && w.len() < lengths.long)
+        .filter(|w| w.chars().all(|c| c.is_ascii_lowercase()))
+        .choose(&mut rand::thread_rng())?;
+
+    let mut letters: Vec<_> = word.chars().collect();
+    // shuffling can give the word back, give up eventually in case every
+    // letter is the same
+    for _ in 0..10 {
+        letters.shuffle(&mut rand::thread_rng());
+        if letters.iter().collect::<String>() != word {
+            break;
+        }
+    }
+    Some((word, letters.into_iter().collect()))
 }
 
 async fn run_bot(
     mut stream: ClientStream,
+    network: usize,
     current_nick: &str,
     tx: mpsc::Sender<Bot>,
+    last_received: Arc<AtomicI64>,
 ) -> Result<(), failure::Error> {
     while let Some(message) = stream.next().await.transpose()? {
-        process_message(current_nick, &message, tx.clone()).await;
+        last_received.store(Utc::now().timestamp(), Ordering::Relaxed);
+        process_message(network, current_nick, &message, tx.clone()).await;
     }
 
     Ok(())
 }
 
+// a connection can die without the stream ever returning an error, so if the server
+// has gone quiet we PING it, and if that doesn't get a response either we give up
+// on it and the network reconnects
+async fn watchdog(
+    sender: Sender,
+    server: String,
+    timeout: u64,
+    last_received: Arc<AtomicI64>,
+    tx: mpsc::Sender<Bot>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs((timeout / 2).max(1)));
+    let mut pinged = false;
+
+    loop {
+        interval.tick().await;
+
+        let quiet = Utc::now().timestamp() - last_received.load(Ordering::Relaxed);
+        if quiet < timeout as i64 {
+            pinged = false;
+            continue;
+        }
+
+        if !pinged {
+            println!(
+                "Nothing received from {} for {}s, sending PING",
+                server, quiet
+            );
+            if let Err(err) = sender.send(Command::PING(server.to_string(), None)) {
+                println!("Error sending PING: {}", err);
+            }
+            pinged = true;
+        } else if quiet >= 2 * timeout as i64 {
+            println!(
+                "Nothing received from {} for {}s, connection is stalled",
+                server, quiet
+            );
+            // the network might already be on its way out
+            let _ = tx.send(Bot::Disconnected).await;
+            return;
+        }
+    }
+}
+
+// resolves on ctrl-c or, on unix, SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("unable to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+    }
+
+    #[cfg(not(unix))]
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        println!("Unable to listen for ctrl-c: {}", err);
+        std::future::pending::<()>().await;
+    }
+}
+
+// every network has its own event loop so anything for another network got
+// here by mistake, there's no telling which channel it'd end up in so drop it
+fn send_lines(throttle: &mut Throttle, network: usize, origin: Origin, message: &str) {
+    if origin.network != network {
+        println!(
+            "Dropping message for {} on network {}, this is network {}",
+            origin.target, origin.network, network
+        );
+        return;
+    }
+    say(throttle, &origin.target, message);
+}
+
+// split to fit and queued behind whatever else is going to the target
+fn say(throttle: &mut Throttle, target: &str, message: &str) {
+    for line in split_message(target, message) {
+        throttle.send(target.to_string(), line);
+    }
+}
+
+// logs a message and every so often removes any older than `retention` days
+fn log_message(db: &Database, msg: &Msg, retention: u64, last_prune: &mut Option<Instant>) {
+    let entry = LoggedMessage {
+        channel: msg.target.to_string(),
+        nick: msg.source.to_string(),
+        content: msg.content.to_string(),
+        time: Utc::now().to_rfc3339(),
+    };
+    if let Err(err) = db.add_message(&entry) {
+        println!("SQL error logging message: {}", err);
+    }
+
+    if retention == 0 || last_prune.is_some_and(|p| p.elapsed() < Duration::from_secs(60 * 60)) {
+        return;
+    }
+    *last_prune = Some(Instant::now());
+    let before = Utc::now() - chrono::Duration::days(retention as i64);
+    match db.prune_messages(&before.to_rfc3339()) {
+        Ok(0) => (),
+        Ok(n) => println!("Pruned {} logged messages", n),
+        Err(err) => println!("SQL error pruning messages: {}", err),
+    }
+}
+
+// boot [config], otherwise $BOOT_CONFIG, otherwise ./config.toml
+fn config_path() -> String {
+    std::env::args()
+        .nth(1)
+        .or_else(|| std::env::var("BOOT_CONFIG").ok())
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| "config.toml".to_string())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), failure::Error> {
-    let settings = Settings::load("config.toml")?;
+    let config_path = config_path();
+    let settings = Settings::load(&config_path)?;
+    // returning the error from main would print its debug representation
+    if let Err(err) = settings.validate() {
+        println!("{}", err);
+        std::process::exit(1);
+    }
+    let names = settings.network_names();
     let db = if let Some(ref path) = settings.bot.db {
-        Database::open(path)?
+        Database::open(path, &names)?
     } else {
         let path = "./database.sqlite";
-        Database::open(path)?
+        Database::open(path, &names)?
     };
-    let api_key = settings.bot.weather_api;
-    let mut client = Client::from_config(settings.irc).await?;
+    let req_client = ReqBuilder::new()
+        .max_concurrent(settings.bot.max_requests)
+        .local_address(settings.bot.http_address())
+        .allow_insecure(settings.bot.allow_insecure)
+        .build()?;
+
+    // scoring the dictionary takes a moment, get it out of the way now rather
+    // than in the middle of the first game
+    if settings.bot.hangman.difficulty == Difficulty::Frequency {
+        tokio::task::spawn_blocking(scored_words);
+    }
+
+    // each network gets its own connection and event loop so replies always go
+    // out on the connection they came in on, and one going down leaves the
+    // others alone
+    let networks = (0..settings.network_count()).filter_map(|i| {
+        let db = db.for_network(&settings.network_name(i)?);
+        let settings = settings.network(i)?;
+        Some(supervise_network(
+            i,
+            settings,
+            &config_path,
+            db,
+            req_client.clone(),
+        ))
+    });
+    future::join_all(networks).await;
+
+    Ok(())
+}
+
+// how a network's event loop finished
+enum Exit {
+    // we quit or we're shutting down
+    Done,
+    // the connection closed or stalled and needs making again
+    Reconnect,
+}
+
+// the tasks started for a connection, they go with it so a reconnect doesn't
+// leave the old ones running alongside the new
+#[derive(Default)]
+struct Tasks(Vec<tokio::task::JoinHandle<()>>);
+
+impl Tasks {
+    fn spawn(&mut self, task: impl Future<Output = ()> + Send + 'static) {
+        self.0.push(tokio::spawn(task));
+    }
+}
+
+impl Drop for Tasks {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+// runs a network until it's done, reconnecting whenever the connection stalls
+// or fails, anything reloaded carries over to the new connection
+async fn supervise_network(
+    network: usize,
+    mut settings: Settings,
+    config_path: &str,
+    db: Database,
+    req_client: Req,
+) {
+    let server = settings.irc.server.clone().unwrap_or_default();
+    let mut delay = RECONNECT_DELAY;
+
+    loop {
+        let exit = run_network(
+            network,
+            &mut settings,
+            config_path,
+            db.clone(),
+            req_client.clone(),
+        )
+        .await;
+        match exit {
+            Ok(Exit::Done) => return,
+            // it was connected, so start over with a short wait
+            Ok(Exit::Reconnect) => delay = RECONNECT_DELAY,
+            Err(err) => println!("Error on {}: {}", server, err),
+        }
+
+        println!("Reconnecting to {} in {}s", server, delay);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(delay)) => (),
+            _ = shutdown_signal() => return,
+        }
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+async fn run_network(
+    network: usize,
+    settings: &mut Settings,
+    config_path: &str,
+    db: Database,
+    req_client: Req,
+) -> Result<Exit, failure::Error> {
+    let watchdog_timeout = settings.bot.watchdog;
+    let server = settings.irc.server.clone().unwrap_or_default();
+    let mut client = Client::from_config(settings.irc.clone()).await?;
     let stream = client.stream()?;
     client.identify()?;
 
-    let req_client = ReqBuilder::new().build()?;
+    let mut state = bot::State::default();
 
-    let (tx, mut rx) = mpsc::channel::<Bot>(32);
+    let (tx, mut rx) = mpsc::channel::<Bot>(settings.bot.channel_capacity.max(1));
     let tx2 = tx.clone();
 
+    let last_received = Arc::new(AtomicI64::new(Utc::now().timestamp()));
+
+    let mut tasks = Tasks::default();
+
     let nick = client.current_nickname().to_string();
-    tokio::spawn(async move { run_bot(stream, &nick, tx.clone()).await });
+    let last = last_received.clone();
+    tasks.spawn(async move {
+        if let Err(err) = run_bot(stream, network, &nick, tx.clone(), last).await {
+            println!("Error reading from the server: {}", err);
+        }
+        // after we've quit this goes nowhere
+        let _ = tx.send(Bot::Disconnected).await;
+    });
+
+    if watchdog_timeout > 0 {
+        let sender = client.sender();
+        let tx2 = tx2.clone();
+        tasks.spawn(
+            async move { watchdog(sender, server, watchdog_timeout, last_received, tx2).await },
+        );
+    }
 
+    let mut pollers = Pollers::new();
+
+    for feed in settings.bot.feeds.clone() {
+        let db = db.clone();
+        let req_client = req_client.clone();
+        let tx2 = tx2.clone();
+        let interval = feeds::interval(&feed);
+        let poller = pollers.register(interval, interval / 10);
+        tasks.spawn(
+            async move { feeds::poll_feed(feed, network, db, req_client, tx2, poller).await },
+        );
+    }
+
+    {
+        let db = db.clone();
+        let tx2 = tx2.clone();
+        let poller = pollers.register(reminders::CHECK_INTERVAL, reminders::CHECK_JITTER);
+        tasks.spawn(async move { reminders::poll_reminders(network, db, tx2, poller).await });
+    }
+
+    {
+        let db = db.clone();
+        let tx2 = tx2.clone();
+        let req_client = req_client.clone();
+        let poller = pollers.register(reminders::COIN_ALERT_INTERVAL, reminders::COIN_ALERT_JITTER);
+        tasks.spawn(async move {
+            reminders::poll_coin_alerts(network, db, req_client, tx2, poller).await
+        });
+    }
+
+    let (titles, title_jobs) = mpsc::channel::<TitleJob>(TITLE_QUEUE);
+    let title_jobs = Arc::new(tokio::sync::Mutex::new(title_jobs));
+    for _ in 0..settings.bot.title_workers.max(1) {
+        let jobs = title_jobs.clone();
+        let req_client = req_client.clone();
+        let tx2 = tx2.clone();
+        let stats = state.stats.clone();
+        tasks.spawn(async move { bot::title_worker(jobs, req_client, tx2, stats).await });
+    }
+
+    let mut throttle = Throttle::new(
+        client.sender(),
+        settings.bot.flood.lines,
+        settings.bot.flood.seconds,
+    );
+
+    let mut last_prune: Option<Instant> = None;
     let mut rng = thread_rng();
     let mut hangman: Hang = Hang::default();
+    // the word and its scrambled letters while a round of .scramble is going
+    let mut scramble: Option<(String, String)> = None;
+    // channel (lowercase) -> the question waiting to be answered there
+    let mut trivia: HashMap<String, Round> = HashMap::new();
+    let mut trivia_rounds: u64 = 0;
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let cmd = tokio::select! {
+            cmd = rx.recv() => match cmd {
+                Some(cmd) => cmd,
+                None => break,
+            },
+            _ = &mut shutdown => {
+                println!("Shutting down");
+                if let Err(err) = client.send_quit(&settings.bot.quit_message) {
+                    println!("Error sending quit: {}", err);
+                }
+                // give the quit a moment to make it out before the runtime goes away
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                break;
+            }
+        };
 
-    while let Some(cmd) = rx.recv().await {
         match cmd {
             Bot::Message(msg) => {
-                bot::process_messages(msg, &db, &client, api_key.clone(), &tx2, req_client.clone())
-                    .await;
+                // nothing is kept for anyone who's opted out
+                let keep = msg.target.starts_with('#')
+                    && matches!(db.is_opted_out(&msg.source), Ok(false));
+                // commands aren't worth remembering and .grep would find itself
+                if keep && !msg.content.starts_with(['.', '!']) {
+                    state.remember(&msg.target, &msg.source, &msg.content, settings.bot.history);
+                }
+                if keep && settings.bot.log_messages {
+                    log_message(&db, &msg, settings.bot.log_retention, &mut last_prune);
+                }
+                let channel = msg.target.to_lowercase();
+                if trivia
+                    .get_mut(&channel)
+                    .is_some_and(|r| r.guess(&msg.source, &msg.content))
+                {
+                    let round = trivia.remove(&channel).unwrap();
+                    let response = match db.add_point("trivia", &msg.source) {
+                        Ok(points) => format!(
+                            "{} got it! The answer was {}. ({} points)",
+                            msg.source, round.question.answer, points
+                        ),
+                        Err(err) => {
+                            println!("SQL error adding point: {}", err);
+                            format!(
+                                "{} got it! The answer was {}.",
+                                msg.source, round.question.answer
+                            )
+                        }
+                    };
+                    throttle.send(msg.target.to_string(), response);
+                    continue;
+                }
+                bot::process_messages(
+                    msg,
+                    &db,
+                    &client,
+                    &settings.bot,
+                    &tx2,
+                    req_client.clone(),
+                    &mut state,
+                )
+                .await;
             }
-            Bot::Links(u) => {
-                let tx2 = tx2.clone();
-                let req_client = req_client.clone();
-                tokio::spawn(async move {
-                    let titles = bot::process_titles(u, req_client).await;
-                    for t in titles {
-                        tx2.send(Bot::Privmsg(t.0, t.1)).await.unwrap();
+            Bot::Links(source, u) => {
+                // every link counts for .topdomains, it's only the titles
+                // that are held back
+                for (channel, url) in &u {
+                    let Some(host) = bot::link_host(url) else {
+                        continue;
+                    };
+                    if let Err(err) = db.add_domain(channel, &host) {
+                        println!("SQL error updating domains: {}", err);
                     }
-                });
+                }
+                if settings.bot.is_quiet() || state.is_muted(&source) || bot::is_muted(&db, &source)
+                {
+                    continue;
+                }
+                let u: Vec<_> = u
+                    .into_iter()
+                    .filter(|(t, _)| settings.bot.titles_enabled(t))
+                    .collect();
+                if u.is_empty() {
+                    continue;
+                }
+                let job = TitleJob {
+                    network,
+                    links: u,
+                    format: TitleFormat::new(&settings.bot),
+                };
+                if let Err(mpsc::error::TrySendError::Full(job)) = titles.try_send(job) {
+                    println!("Title queue is full, skipping {} links", job.links.len());
+                }
+            }
+            Bot::EasterEgg(source, origin, m) => {
+                if settings.bot.is_quiet() || bot::is_muted(&db, &source) {
+                    continue;
+                }
+                send_lines(&mut throttle, network, origin, &m);
+            }
+            Bot::Privmsg(origin, m) => {
+                send_lines(&mut throttle, network, origin, &m);
             }
-            Bot::Privmsg(t, m) => client.send_privmsg(t, m).unwrap(),
             Bot::UpdateSeen(e) => {
+                match db.is_opted_out(&e.username) {
+                    Ok(false) => (),
+                    Ok(true) => continue,
+                    Err(err) => {
+                        println!("SQL error checking opt out: {}", err);
+                        continue;
+                    }
+                }
                 if let Err(err) = db.add_seen(&e) {
                     println!("SQL error adding seen: {}", err);
                 };
             }
+            Bot::RestoreNotifications(notifications) => {
+                for n in notifications {
+                    if let Err(err) = db.add_notification(&n) {
+                        println!("SQL error restoring notification: {}", err);
+                    };
+                }
+            }
+            Bot::NickAlias(old, new, hostmask) => {
+                match (db.is_opted_out(&old), db.is_opted_out(&new)) {
+                    (Ok(false), Ok(false)) => (),
+                    (Err(err), _) | (_, Err(err)) => {
+                        println!("SQL error checking opt out: {}", err);
+                        continue;
+                    }
+                    _ => continue,
+                }
+                let time = Utc::now().to_rfc3339();
+                for nick in [&old, &new] {
+                    if let Err(err) = db.add_nick_host(nick, &hostmask, &time) {
+                        println!("SQL error adding nick alias: {}", err);
+                    };
+                }
+            }
             Bot::UpdateWeather(user, lat, lon) => {
                 if let Err(err) = db.add_weather(&user, &lat, &lon) {
                     println!("SQL error updating weather: {}", err);
@@ -175,6 +906,111 @@ async fn main() -> Result<(), failure::Error> {
                     println!("SQL error updating coins: {}", err);
                 };
             }
+            Bot::UpdateTopic(channel, topic) => {
+                state.topics.insert(channel.to_lowercase(), topic);
+            }
+            Bot::Reload(target) => {
+                let response = match Settings::load(config_path).and_then(|new| {
+                    new.validate()?;
+                    new.network(network)
+                        .ok_or_else(|| failure::err_msg("this network isn't in the config anymore"))
+                }) {
+                    Ok(new) => {
+                        let reload = settings.reload(new);
+                        let mut response = if reload.changed.is_empty() {
+                            "Reloaded, nothing changed".to_string()
+                        } else {
+                            format!("Reloaded, changed: {}", reload.changed.join(", "))
+                        };
+                        if !reload.restart.is_empty() {
+                            response.push_str(&format!(
+                                ", needs a restart: {}",
+                                reload.restart.join(", ")
+                            ));
+                        }
+                        response
+                    }
+                    Err(err) => format!("Error reloading config: {}", err),
+                };
+                say(&mut throttle, &target, &response);
+            }
+            Bot::Join(channel, nick) => {
+                if settings.bot.is_quiet() {
+                    continue;
+                }
+                if let Some(greeting) = state.greeting(&settings.bot, &channel, &nick) {
+                    say(&mut throttle, &channel, &greeting);
+                }
+            }
+            Bot::Raw(admin, target, line) => {
+                println!("{} in {} sent raw: {}", admin, target, line);
+                // checked after parsing so a prefix can't hide the command
+                let response = match line.parse::<Message>() {
+                    Ok(message) => {
+                        let command = String::from(&message.command);
+                        let command = command.split(' ').next().unwrap_or_default();
+                        if RAW_BLOCKED.iter().any(|c| c.eq_ignore_ascii_case(command)) {
+                            format!("{} isn't allowed, sorry", command.to_uppercase())
+                        } else {
+                            match client.send(message) {
+                                Ok(_) => "Sent".to_string(),
+                                Err(err) => format!("Error sending: {}", err),
+                            }
+                        }
+                    }
+                    Err(_) => "That isn't a valid IRC line".to_string(),
+                };
+                println!("raw from {}: {}", admin, response);
+                say(&mut throttle, &target, &response);
+            }
+            Bot::Joined(channel) => {
+                let Some(actions) = settings.bot.join_actions(&channel) else {
+                    continue;
+                };
+                for command in &actions.chanserv {
+                    let command = command.replace("{channel}", &channel);
+                    println!("Joined {}, sending ChanServ {}", channel, command);
+                    if let Err(err) = client.send_privmsg("ChanServ", &command) {
+                        println!("Error messaging ChanServ: {}", err);
+                    }
+                }
+                for mode in actions.modes.clone() {
+                    let tx2 = tx2.clone();
+                    let channel = channel.to_string();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(JOIN_MODE_DELAY)).await;
+                        let _ = tx2.send(Bot::Mode(channel, mode)).await;
+                    });
+                }
+            }
+            Bot::Mode(channel, mode) => {
+                println!("Setting mode {} on {}", mode, channel);
+                let mut args = vec![channel.to_string()];
+                args.extend(mode.split_whitespace().map(String::from));
+                if let Err(err) = client.send(Command::Raw("MODE".to_string(), args)) {
+                    println!("Error setting mode on {}: {}", channel, err);
+                }
+            }
+            Bot::SetTopic(channel, topic) => {
+                if let Err(err) = client.send_topic(&channel, &topic) {
+                    println!("Error setting topic for {}: {}", channel, err);
+                }
+            }
+            Bot::UpdateReminder(id, next) => {
+                if let Err(err) = db.set_reminder_next(id, next) {
+                    println!("SQL error updating reminder: {}", err);
+                };
+            }
+            Bot::ClearCoinAlert(id) => {
+                if let Err(err) = db.remove_coin_alert(id, None) {
+                    println!("SQL error clearing coin alert: {}", err);
+                };
+            }
+            Bot::UpdateFeed(url, entries) => {
+                if let Err(err) = db.add_feed_entries(&url, &entries) {
+                    println!("SQL error updating feed: {}", err);
+                };
+            }
             Bot::Quit(t, m) => {
                 // this won't handle sanick, but it should be good enough
                 let nick = client.current_nickname().to_string();
@@ -183,23 +1019,103 @@ async fn main() -> Result<(), failure::Error> {
                     break;
                 }
             }
-            Bot::HangGuess(t, w) => {
-                let lengths: [&str; 4] = ["<start>", "short", "medium", "long"];
+            Bot::Disconnected => return Ok(Exit::Reconnect),
+            Bot::Anagram(t, letters) => {
+                let found = find_anagrams(&letters);
+                let response = if found.is_empty() {
+                    format!("No anagrams of {} found", letters)
+                } else {
+                    format!("Anagrams of {}: {}", letters, found.join(", "))
+                };
+                say(&mut throttle, &t, &response);
+            }
+            Bot::Scramble(t) => {
+                if scramble.is_none() {
+                    scramble = scramble_word(&settings.bot.hangman);
+                }
+                match &scramble {
+                    Some((_, letters)) => {
+                        say(&mut throttle, &t, &format!("Unscramble: {}", letters))
+                    }
+                    None => say(&mut throttle, &t, "Couldn't find a word to scramble"),
+                }
+            }
+            Bot::Trivia(t) => {
+                if let Some(round) = trivia.get(&t.to_lowercase()) {
+                    throttle.send(t, round.question.ask());
+                    continue;
+                }
+                let tx2 = tx2.clone();
+                let req_client = req_client.clone();
+                tokio::spawn(async move {
+                    match trivia::fetch_question(&req_client).await {
+                        Ok(q) => {
+                            let _ = tx2.send(Bot::TriviaQuestion(t, q)).await;
+                        }
+                        Err(err) => println!("Error fetching trivia question: {}", err),
+                    }
+                });
+            }
+            Bot::TriviaQuestion(t, question) => {
+                let channel = t.to_lowercase();
+                // someone else asked for a question at the same time
+                if trivia.contains_key(&channel) {
+                    continue;
+                }
+                trivia_rounds += 1;
+                let id = trivia_rounds;
+                say(&mut throttle, &t, &question.ask());
+                trivia.insert(channel, Round::new(id, question));
+
+                let tx2 = tx2.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(trivia::TIMEOUT)).await;
+                    let _ = tx2.send(Bot::TriviaTimeout(t, id)).await;
+                });
+            }
+            Bot::TriviaTimeout(t, id) => {
+                let channel = t.to_lowercase();
+                if trivia.get(&channel).is_some_and(|r| r.id == id) {
+                    let round = trivia.remove(&channel).unwrap();
+                    let response = format!("Time's up! The answer was {}.", round.question.answer);
+                    throttle.send(t, response);
+                }
+            }
+            Bot::HangGuess(t, w, addressed) => {
+                if scramble
+                    .as_ref()
+                    .is_some_and(|(word, _)| w.eq_ignore_ascii_case(word))
+                {
+                    say(
+                        &mut throttle,
+                        &t,
+                        &format!("A winner is you! The word was {}.", w),
+                    );
+                    scramble = None;
+                    continue;
+                }
+                let lengths: [&str; 4] = ["<start>", "<short>", "<medium>", "<long>"];
                 if lengths.contains(&&w[..]) {
                     if hangman.started {
-                        client
-                            .send_privmsg(t, "A game is already in progress!")
-                            .unwrap();
+                        say(&mut throttle, &t, "A game is already in progress!");
                         continue;
                     } else {
-                        hangman.started = true;
                         let style = match w.as_ref() {
-                            "short" => WordType::Short,
-                            "medium" => WordType::Medium,
-                            "long" => WordType::Long,
+                            "<short>" => WordType::Short,
+                            "<medium>" => WordType::Medium,
+                            "<long>" => WordType::Long,
                             _ => WordType::Medium,
                         };
-                        hangman.word = find_word(style).to_lowercase();
+                        let Some(word) = find_word(style, &settings.bot.hangman) else {
+                            say(
+                                &mut throttle,
+                                &t,
+                                "Couldn't find a word for that, try another length",
+                            );
+                            continue;
+                        };
+                        hangman.started = true;
+                        hangman.word = word.to_lowercase();
                         let replaced: String = hangman
                             .word
                             .chars()
@@ -210,27 +1126,32 @@ async fn main() -> Result<(), failure::Error> {
                             })
                             .collect();
                         hangman.state = replaced;
-                        client
-                            .send_privmsg(
-                                t,
-                                format!(
-                                    "{} {}/7 {}",
-                                    &hangman.state,
-                                    &hangman.attempts,
-                                    PrintCharsNicely(&hangman.guesses)
-                                ),
-                            )
-                            .unwrap();
+                        say(&mut throttle, &t, &hangman.status());
                         continue;
                     }
-                } else if w == hangman.word {
-                    client
-                        .send_privmsg(
-                            t,
-                            format!("A winner is you! The word was {}.", &hangman.word),
-                        )
-                        .unwrap();
-                    hangman = Hang::default();
+                }
+                match hangman.guess_word(&w, addressed) {
+                    WordGuess::Won => {
+                        say(
+                            &mut throttle,
+                            &t,
+                            &format!("A winner is you! The word was {}.", &hangman.state),
+                        );
+                        hangman = Hang::default();
+                    }
+                    WordGuess::Repeated => say(&mut throttle, &t, &hangman.status()),
+                    WordGuess::Wrong => {
+                        let response =
+                            format!("It's not {}! {}", w.to_lowercase(), hangman.status());
+                        say(&mut throttle, &t, &response);
+                    }
+                    WordGuess::Hanged => {
+                        for line in hanged(&hangman.word, &mut rng) {
+                            say(&mut throttle, &t, &line);
+                        }
+                        hangman = Hang::default();
+                    }
+                    WordGuess::Ignored => (),
                 }
             }
             Bot::Hang(t, l) => {
@@ -240,73 +1161,22 @@ async fn main() -> Result<(), failure::Error> {
 
                 if !hangman.word.contains(&l) {
                     if hangman.guesses.contains(&l) {
-                        client
-                            .send_privmsg(
-                                t,
-                                format!(
-                                    "{} {}/7 {}",
-                                    &hangman.state,
-                                    &hangman.attempts,
-                                    PrintCharsNicely(&hangman.guesses)
-                                ),
-                            )
-                            .unwrap();
+                        say(&mut throttle, &t, &hangman.status());
                         continue;
                     }
 
                     hangman.guesses.push(l);
                     hangman.attempts += 1;
 
-                    if hangman.attempts >= 7 {
-                        let n = rng.gen_range(1..100) > 50;
-                        let o: u32 = rng.gen_range(1..100);
-
-                        let mut dead: Vec<String> = vec![
-                            "  +---+".to_string(),
-                            "  |   |".to_string(),
-                            "  O   |".to_string(),
-                            " /|\\  |".to_string(),
-                            " /`\\  |".to_string(),
-                            "      |".to_string(),
-                            "=======".to_string(),
-                        ];
-
-                        if n {
-                            dead[4] = " / \\  |".to_string();
-                        }
-
-                        if o > 95 {
-                            for i in dead {
-                                client.send_privmsg(&t, i).unwrap();
-                            }
+                    if hangman.attempts >= MAX_ATTEMPTS {
+                        for line in hanged(&hangman.word, &mut rng) {
+                            say(&mut throttle, &t, &line);
                         }
-
-                        client
-                            .send_privmsg(
-                                t,
-                                format!(
-                                    "{} dead, jim! The word was {}.",
-                                    if n { "She's" } else { "He's" },
-                                    hangman.word
-                                ),
-                            )
-                            .unwrap();
-
                         hangman = Hang::default();
                         continue;
                     }
 
-                    client
-                        .send_privmsg(
-                            t,
-                            format!(
-                                "{} {}/7 {}",
-                                &hangman.state,
-                                &hangman.attempts,
-                                PrintCharsNicely(&hangman.guesses)
-                            ),
-                        )
-                        .unwrap();
+                    say(&mut throttle, &t, &hangman.status());
                     continue;
                 }
 
@@ -316,30 +1186,141 @@ async fn main() -> Result<(), failure::Error> {
                 }
 
                 if hangman.state == hangman.word {
-                    client
-                        .send_privmsg(
-                            t,
-                            format!("A winner is you! The word was {}.", &hangman.word),
-                        )
-                        .unwrap();
+                    say(
+                        &mut throttle,
+                        &t,
+                        &format!("A winner is you! The word was {}.", &hangman.word),
+                    );
                     hangman = Hang::default();
                     continue;
                 }
 
-                client
-                    .send_privmsg(
-                        t,
-                        format!(
-                            "{} {}/7 {}",
-                            &hangman.state,
-                            &hangman.attempts,
-                            PrintCharsNicely(&hangman.guesses)
-                        ),
-                    )
-                    .unwrap();
+                say(&mut throttle, &t, &hangman.status());
             }
         }
     }
 
-    Ok(())
+    Ok(Exit::Done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rare_letters_are_harder() {
+        assert!(word_difficulty("jazz") > word_difficulty("entertainment"));
+        assert!(word_difficulty("quiz") > word_difficulty("tea"));
+        assert!(word_difficulty("rhythm") > word_difficulty("stone"));
+        // "e" and "t" are the most common letters
+        assert!(word_difficulty("tee") < word_difficulty("tin"));
+    }
+
+    #[test]
+    fn difficulty_counts_each_letter_once() {
+        assert_eq!(word_difficulty("eeee"), word_difficulty("e"));
+        assert_eq!(word_difficulty("abab"), word_difficulty("ab"));
+        // only lowercase ascii letters are scored
+        assert_eq!(word_difficulty("e-mail's"), word_difficulty("emails"));
+        assert_eq!(word_difficulty(""), 0.0);
+        assert_eq!(word_difficulty("123"), 0.0);
+    }
+
+    #[test]
+    fn frequency_buckets_respect_lengths() {
+        let lengths = HangmanConfig {
+            short: 3,
+            long: 6,
+            difficulty: Difficulty::Frequency,
+        };
+        let mut scored: Vec<_> = [
+            "tea",
+            "ten",
+            "zap",
+            "jab",
+            "set",
+            "fez", // short
+            "stone",
+            "tents",
+            "jazzy",
+            "quake",
+            "rinse",
+            "fjord", // medium
+            "entertain",
+            "settlement",
+            "jukeboxes",
+            "quizzical",
+            "statement",
+            "skyjacks", // long
+        ]
+        .iter()
+        .map(|w| (word_difficulty(w), w.to_string()))
+        .collect();
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let short = frequency_bucket(&scored, &WordType::Short, &lengths);
+        let medium = frequency_bucket(&scored, &WordType::Medium, &lengths);
+        let long = frequency_bucket(&scored, &WordType::Long, &lengths);
+
+        // the easiest third of the short words and the hardest of the long
+        assert_eq!(short.len(), 2);
+        assert!(short.iter().all(|w| w.len() <= 3));
+        assert!(short.contains(&"tea") && short.contains(&"ten"));
+        assert_eq!(medium.len(), 2);
+        assert!(medium.iter().all(|w| w.len() > 3 && w.len() < 6));
+        assert_eq!(long.len(), 2);
+        assert!(long.iter().all(|w| w.len() >= 6));
+        assert!(long.contains(&"quizzical") && long.contains(&"jukeboxes"));
+    }
+
+    fn game(word: &str) -> Hang {
+        Hang {
+            started: true,
+            word: word.to_string(),
+            state: "-".repeat(word.len()),
+            ..Hang::default()
+        }
+    }
+
+    #[test]
+    fn word_guesses() {
+        let mut hang = game("hello");
+
+        // chatter the same length as the word costs nothing
+        assert_eq!(hang.guess_word("howdy", false), WordGuess::Ignored);
+        assert_eq!(hang.attempts, 0);
+        // nor does a .guess that couldn't fit
+        assert_eq!(hang.guess_word("hi", true), WordGuess::Ignored);
+        assert_eq!(hang.attempts, 0);
+
+        assert_eq!(hang.guess_word("Howdy", true), WordGuess::Wrong);
+        assert_eq!(hang.attempts, 1);
+        assert_eq!(hang.guesses, ["howdy"]);
+        assert_eq!(hang.guess_word("howdy", true), WordGuess::Repeated);
+        assert_eq!(hang.attempts, 1);
+        // lengths are in characters, not bytes
+        assert_eq!(hang.guess_word("héllo", true), WordGuess::Wrong);
+        assert_eq!(hang.attempts, 2);
+
+        // saying the word wins whether it's a .guess or not
+        assert_eq!(hang.guess_word("HELLO", false), WordGuess::Won);
+        assert_eq!(hang.state, "hello");
+    }
+
+    #[test]
+    fn word_guesses_can_hang() {
+        let mut hang = game("hello");
+        for (i, guess) in ["aaaaa", "bbbbb", "ccccc", "ddddd", "eeeee", "fffff"]
+            .iter()
+            .enumerate()
+        {
+            assert_eq!(hang.guess_word(guess, true), WordGuess::Wrong);
+            assert_eq!(hang.attempts as usize, i + 1);
+        }
+        assert_eq!(hang.guess_word("ggggg", true), WordGuess::Hanged);
+
+        // nothing counts without a game
+        let mut hang = Hang::default();
+        assert_eq!(hang.guess_word("hello", true), WordGuess::Ignored);
+    }
 }
@@ -1,44 +1,76 @@
-use crate::sqlite::Seen;
-use crate::Bot;
+use crate::sqlite::{Seen, SeenKind};
+use crate::throttle::colour_code_len;
+use crate::{send_or_drop, Bot, Origin};
 use chrono::Utc;
 use irc::client::prelude::*;
 use linkify::{LinkFinder, LinkKind};
 use rand::random;
+use std::time::Instant;
 use tokio::sync::mpsc;
 
 #[derive(Debug, Clone)]
 pub struct Msg {
+    // which of our networks this came from, see Settings::network
+    pub network: usize,
     pub current_nick: String,
     pub source: String,
-    // privmsg target (nick/channel) or target nick for kick/invite
+    // privmsg target (nick/channel) or target nick for kick/invite, for private
+    // messages this is the sender's nick
     pub target: String,
+    // user@host of whoever sent it, when the server tells us
+    pub hostmask: Option<String>,
     // somewhat confusingly this will be the channel for kick/invite
     // kick could use an additional field for the kick message,
     // however I don't think we'll ever really care about that
     pub content: String,
+    // when we got it from the server, for .ping
+    pub received: Instant,
 }
 impl Msg {
-    fn new(current_nick: String, source: String, target: String, content: String) -> Msg {
+    fn new(
+        network: usize,
+        current_nick: String,
+        source: String,
+        hostmask: Option<String>,
+        target: String,
+        content: String,
+    ) -> Msg {
         Msg {
+            network,
             current_nick,
             source,
+            hostmask,
             target,
             content,
+            received: Instant::now(),
         }
     }
+
+    // where replies to this message should go
+    pub fn origin(&self) -> Origin {
+        Origin::new(self.network, &self.target)
+    }
 }
 
-pub async fn process_message(current_nick: &str, message: &Message, tx: mpsc::Sender<Bot>) {
+pub async fn process_message(
+    network: usize,
+    current_nick: &str,
+    message: &Message,
+    tx: mpsc::Sender<Bot>,
+) {
     let source = message.source_nickname();
     let target = message.response_target();
     let nick = current_nick.to_string();
+    let hostmask = hostmask(message);
 
     match &message.command {
         Command::PRIVMSG(_target, message) => {
             privmsg(
                 Msg::new(
+                    network,
                     nick,
                     source.unwrap().to_string(),
+                    hostmask.clone(),
                     target.unwrap().to_string(),
                     message.to_string(),
                 ),
@@ -49,8 +81,10 @@ pub async fn process_message(current_nick: &str, message: &Message, tx: mpsc::Se
         Command::KICK(channel, user, _text) => {
             kick(
                 Msg::new(
+                    network,
                     nick,
                     source.unwrap().to_string(),
+                    hostmask.clone(),
                     user.to_string(),
                     channel.to_string(),
                 ),
@@ -60,8 +94,10 @@ pub async fn process_message(current_nick: &str, message: &Message, tx: mpsc::Se
         }
         Command::INVITE(user, channel) => {
             invite(Msg::new(
+                network,
                 nick,
                 source.unwrap().to_string(),
+                hostmask.clone(),
                 user.to_string(),
                 channel.to_string(),
             ))
@@ -70,8 +106,10 @@ pub async fn process_message(current_nick: &str, message: &Message, tx: mpsc::Se
         Command::QUIT(message) => {
             quit(
                 Msg::new(
+                    network,
                     nick,
                     source.unwrap().to_string(),
+                    hostmask.clone(),
                     target.unwrap().to_string(),
                     "".to_string(),
                 ),
@@ -80,6 +118,59 @@ pub async fn process_message(current_nick: &str, message: &Message, tx: mpsc::Se
             )
             .await
         }
+        Command::JOIN(channel, _, _) => {
+            join(
+                Msg::new(
+                    network,
+                    nick,
+                    source.unwrap().to_string(),
+                    hostmask.clone(),
+                    channel.to_string(),
+                    "".to_string(),
+                ),
+                tx.clone(),
+            )
+            .await
+        }
+        Command::PART(channel, _) => {
+            part(
+                Msg::new(
+                    network,
+                    nick,
+                    source.unwrap().to_string(),
+                    hostmask.clone(),
+                    channel.to_string(),
+                    "".to_string(),
+                ),
+                tx.clone(),
+            )
+            .await
+        }
+        Command::NICK(new_nick) => {
+            nick_change(
+                Msg::new(
+                    network,
+                    nick,
+                    source.unwrap().to_string(),
+                    hostmask.clone(),
+                    new_nick.to_string(),
+                    "".to_string(),
+                ),
+                tx.clone(),
+            )
+            .await
+        }
+        Command::TOPIC(channel, Some(topic)) => {
+            tx.send(Bot::UpdateTopic(channel.to_string(), topic.to_string()))
+                .await
+                .unwrap();
+        }
+        // sent when we join a channel
+        Command::Response(Response::RPL_TOPIC, args) if args.len() > 2 => {
+            tx.send(Bot::UpdateTopic(args[1].to_string(), args[2].to_string()))
+                .await
+                .unwrap();
+        }
         // should handle this at some point but for now I don't care
         //Command::SAQUIT(nick, message) => saquit(nick, message, tx.clone()).await,
         //Command::KILL(nick, message) => kill(nick, message, tx.clone()).await,
@@ -87,48 +178,169 @@ pub async fn process_message(current_nick: &str, message: &Message, tx: mpsc::Se
     };
 }
 
-async fn privmsg(msg: Msg, tx: mpsc::Sender<Bot>) {
+async fn privmsg(mut msg: Msg, tx: mpsc::Sender<Bot>) {
+    // for private messages the target is whoever sent it, so replies go back
+    // to them, links and seen are only for channels
     if !msg.target.starts_with('#') {
+        // CTCP (i.e., VERSION or ACTION) isn't meant for us
+        if msg.content.starts_with('\x01') {
+            return;
+        }
+        msg.content = strip_formatting(&msg.content);
+        tx.send(Bot::Message(msg)).await.unwrap();
         return;
     }
 
-    let mut finder = LinkFinder::new();
-    finder.kinds(&[LinkKind::Url]);
-    let links: Vec<_> = finder.links(&msg.content).collect();
-    let urls: Vec<(_, _)> = links
-        .into_iter()
-        .map(|x| (msg.target.to_string(), x.as_str().to_string()))
-        .collect();
-    tx.send(Bot::Links(urls)).await.unwrap();
+    // seen keeps the message as it was sent, everything else gets it without
+    // any colours or formatting so that i.e., a coloured location still works
+    let stripped = strip_formatting(&msg.content);
+    let raw = std::mem::replace(&mut msg.content, stripped);
+
+    // .title fetches the title itself, don't fetch it twice, and links we posted
+    // ourselves (i.e., with echo-message) would only have us talking to ourselves
+    let title_command = matches!(
+        msg.content.split_whitespace().next(),
+        Some(".title" | "!title")
+    );
+    let own_message = msg.source.eq_ignore_ascii_case(&msg.current_nick);
+    if !title_command && !own_message {
+        let mut finder = LinkFinder::new();
+        finder.kinds(&[LinkKind::Url]);
+        let links: Vec<_> = finder.links(&msg.content).collect();
+        let urls: Vec<(_, _)> = links
+            .into_iter()
+            .map(|x| (msg.target.to_string(), x.as_str().to_string()))
+            .collect();
+        // titles are nice to have, nothing's lost if a few are skipped
+        if !urls.is_empty() {
+            send_or_drop(&tx, Bot::Links(msg.source.to_string(), urls));
+        }
+    }
 
     if msg.content.contains('🥾') || msg.content.contains('👢') {
         let y: f64 = random::<f64>();
         if y > 0.975 {
             let response = "https://www.youtube.com/watch?v=tfMcxmOBmpk".to_string();
-            let target = msg.target.to_string();
-            tx.send(Bot::Privmsg(target, response)).await.unwrap();
+            let source = msg.source.to_string();
+            send_or_drop(&tx, Bot::EasterEgg(source, msg.origin(), response));
         }
     }
 
     let entry = Seen {
         username: msg.source.to_string(),
-        message: format!("saying: {}", &msg.content),
+        message: raw,
         time: Utc::now().to_rfc3339(),
+        kind: SeenKind::Message,
+        channel: Some(msg.target.to_string()),
+        hostmask: msg.hostmask.clone(),
     };
     tx.send(Bot::UpdateSeen(entry)).await.unwrap();
 
     tx.send(Bot::Message(msg)).await.unwrap();
 }
 
+// removes mirc colour codes and formatting characters
+pub fn strip_formatting(message: &str) -> String {
+    let bytes = message.as_bytes();
+    let mut stripped = String::with_capacity(message.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\x03' => i += colour_code_len(&bytes[i..]),
+            // hex colour, \x04 followed by RRGGBB
+            b'\x04' => {
+                let hex = bytes[i + 1..]
+                    .iter()
+                    .take(6)
+                    .take_while(|c| c.is_ascii_hexdigit())
+                    .count();
+                i += 1 + if hex == 6 { 6 } else { 0 };
+            }
+            // bold, monospace, reset, reverse, italic, strikethrough, underline
+            b'\x02' | b'\x11' | b'\x0f' | b'\x16' | b'\x1d' | b'\x1e' | b'\x1f' => i += 1,
+            _ => {
+                let c = message[i..].chars().next().unwrap();
+                stripped.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    stripped
+}
+
 async fn kick(msg: Msg, tx: mpsc::Sender<Bot>) {
+    let entry = Seen {
+        username: msg.target.to_string(),
+        message: msg.content.to_string(),
+        time: Utc::now().to_rfc3339(),
+        kind: SeenKind::Kick,
+        channel: Some(msg.content.to_string()),
+        hostmask: None,
+    };
+    tx.send(Bot::UpdateSeen(entry)).await.unwrap();
+}
+
+async fn join(msg: Msg, tx: mpsc::Sender<Bot>) {
+    let entry = Seen {
+        username: msg.source.to_string(),
+        message: msg.target.to_string(),
+        time: Utc::now().to_rfc3339(),
+        kind: SeenKind::Join,
+        channel: Some(msg.target.to_string()),
+        hostmask: msg.hostmask.clone(),
+    };
+    tx.send(Bot::UpdateSeen(entry)).await.unwrap();
+
+    // no point greeting ourselves but there might be something to do on joining
+    match msg.source.eq_ignore_ascii_case(&msg.current_nick) {
+        true => send_or_drop(&tx, Bot::Joined(msg.target)),
+        false => send_or_drop(&tx, Bot::Join(msg.target, msg.source)),
+    }
+}
+
+async fn part(msg: Msg, tx: mpsc::Sender<Bot>) {
     let entry = Seen {
         username: msg.source.to_string(),
-        message: format!("being kicked from {}", &msg.target),
+        message: msg.target.to_string(),
         time: Utc::now().to_rfc3339(),
+        kind: SeenKind::Part,
+        channel: Some(msg.target.to_string()),
+        hostmask: msg.hostmask.clone(),
     };
     tx.send(Bot::UpdateSeen(entry)).await.unwrap();
 }
 
+async fn nick_change(msg: Msg, tx: mpsc::Sender<Bot>) {
+    let entry = Seen {
+        username: msg.source.to_string(),
+        message: msg.target.to_string(),
+        time: Utc::now().to_rfc3339(),
+        kind: SeenKind::Nick,
+        channel: None,
+        hostmask: msg.hostmask.clone(),
+    };
+    tx.send(Bot::UpdateSeen(entry)).await.unwrap();
+
+    // without a user@host there's nothing to be sure they're the same person by
+    if let Some(hostmask) = msg.hostmask {
+        tx.send(Bot::NickAlias(msg.source, msg.target, hostmask))
+            .await
+            .unwrap();
+    }
+}
+
+// user@host from the message prefix, if the server sent one
+fn hostmask(message: &Message) -> Option<String> {
+    match &message.prefix {
+        Some(Prefix::Nickname(_, user, host)) if !user.is_empty() && !host.is_empty() => {
+            Some(format!("{}@{}", user, host))
+        }
+        _ => None,
+    }
+}
+
 async fn invite(_msg: Msg) {}
 
 async fn quit(msg: Msg, quit_message: &Option<String>, tx: mpsc::Sender<Bot>) {
@@ -136,5 +348,14 @@ async fn quit(msg: Msg, quit_message: &Option<String>, tx: mpsc::Sender<Bot>) {
         Some(m) => m.to_string(),
         None => "".to_string(),
     };
+    let entry = Seen {
+        username: msg.source.to_string(),
+        message: quit_message.to_string(),
+        time: Utc::now().to_rfc3339(),
+        kind: SeenKind::Quit,
+        channel: None,
+        hostmask: msg.hostmask.clone(),
+    };
+    tx.send(Bot::UpdateSeen(entry)).await.unwrap();
     tx.send(Bot::Quit(msg.source, quit_message)).await.unwrap();
 }
@@ -0,0 +1,58 @@
+// groups the digits before the decimal point in threes, 44192.8 is 44,192.8,
+// anything after the decimal point is left as is
+pub fn thousands(number: &str) -> String {
+    let (sign, number) = match number.strip_prefix('-') {
+        Some(n) => ("-", n),
+        None => ("", number),
+    };
+    let (whole, fraction) = match number.find('.') {
+        Some(i) => number.split_at(i),
+        None => (number, ""),
+    };
+
+    let mut grouped = String::with_capacity(whole.len() + whole.len() / 3);
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    format!("{sign}{grouped}{fraction}")
+}
+
+pub fn count(n: impl ToString) -> String {
+    thousands(&n.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_in_threes() {
+        assert_eq!(thousands("1"), "1");
+        assert_eq!(thousands("999"), "999");
+        assert_eq!(thousands("1000"), "1,000");
+        assert_eq!(thousands("44192.8"), "44,192.8");
+        assert_eq!(thousands("123456"), "123,456");
+        assert_eq!(thousands("1234567.891"), "1,234,567.891");
+    }
+
+    #[test]
+    fn signs_and_fractions() {
+        assert_eq!(thousands("-1234"), "-1,234");
+        assert_eq!(thousands("-123"), "-123");
+        // only the whole part is grouped
+        assert_eq!(thousands("0.00001234"), "0.00001234");
+        assert_eq!(thousands("1000.123456"), "1,000.123456");
+        assert_eq!(thousands(""), "");
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(count(0), "0");
+        assert_eq!(count(1_000_000u64), "1,000,000");
+        assert_eq!(count(-4500i64), "-4,500");
+    }
+}
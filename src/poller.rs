@@ -0,0 +1,56 @@
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+
+// how far apart the first polls of each registered poller are
+const STAGGER: Duration = Duration::from_secs(5);
+
+// hands out pollers for the background tasks so they don't all go off at the
+// same moment, each one starts a little after the last and every poll after
+// that lands somewhere within `jitter` either side of its interval
+#[derive(Default)]
+pub struct Pollers {
+    registered: u32,
+}
+
+impl Pollers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, interval: Duration, jitter: Duration) -> Poller {
+        let first = (STAGGER * self.registered).min(interval);
+        self.registered += 1;
+
+        Poller {
+            interval,
+            // any more and polls could come back to back
+            jitter: jitter.min(interval / 2),
+            first: Some(first),
+        }
+    }
+}
+
+pub struct Poller {
+    interval: Duration,
+    jitter: Duration,
+    // the stagger before the first poll, taken once it's been waited out
+    first: Option<Duration>,
+}
+
+impl Poller {
+    // waits until it's time to poll again
+    pub async fn tick(&mut self) {
+        let wait = match self.first.take() {
+            Some(first) => first + self.random(self.jitter),
+            None => self.interval - self.jitter + self.random(self.jitter * 2),
+        };
+        tokio::time::sleep(wait).await;
+    }
+
+    fn random(&self, up_to: Duration) -> Duration {
+        match up_to.as_millis() as u64 {
+            0 => Duration::ZERO,
+            ms => Duration::from_millis(thread_rng().gen_range(0..=ms)),
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use crate::bot;
+use crate::http::Req;
+use crate::poller::Poller;
+use crate::sqlite::{CoinAlert, Database};
+use crate::{Bot, Origin};
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration as STDDuration;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::Sender;
+
+// the most recurring reminders one nick can have at once
+pub const MAX_REMINDERS: u32 = 5;
+
+// the most coin alerts one nick can have at once
+pub const MAX_COIN_ALERTS: u32 = 5;
+
+// how often reminders are checked for any that are due, and give or take how much
+pub const CHECK_INTERVAL: STDDuration = STDDuration::from_secs(30);
+pub const CHECK_JITTER: STDDuration = STDDuration::from_secs(5);
+
+// kraken only lets us make so many calls before it starts turning us away so
+// coin alerts are checked a lot less often
+pub const COIN_ALERT_INTERVAL: STDDuration = STDDuration::from_secs(120);
+pub const COIN_ALERT_JITTER: STDDuration = STDDuration::from_secs(15);
+
+// which days a recurring reminder goes off on, in the reminder's own timezone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    Daily,
+    Weekdays,
+    Weekly(Weekday),
+}
+
+impl Schedule {
+    // daily, weekdays, or a day of the week, i.e., mon or monday
+    pub fn parse(s: &str) -> Option<Schedule> {
+        match s.to_lowercase().as_ref() {
+            "daily" | "day" | "everyday" => Some(Schedule::Daily),
+            "weekdays" | "weekday" => Some(Schedule::Weekdays),
+            day => day.parse::<Weekday>().ok().map(Schedule::Weekly),
+        }
+    }
+
+    // how it's stored, parse reads this back
+    pub fn as_str(&self) -> String {
+        match self {
+            Schedule::Daily => "daily".to_string(),
+            Schedule::Weekdays => "weekdays".to_string(),
+            Schedule::Weekly(day) => day.to_string().to_lowercase(),
+        }
+    }
+
+    fn includes(&self, day: Weekday) -> bool {
+        match self {
+            Schedule::Daily => true,
+            Schedule::Weekdays => !matches!(day, Weekday::Sat | Weekday::Sun),
+            Schedule::Weekly(d) => *d == day,
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Daily => write!(f, "every day"),
+            Schedule::Weekdays => write!(f, "every weekday"),
+            Schedule::Weekly(day) => write!(f, "every {}", day),
+        }
+    }
+}
+
+// HH:MM
+pub fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M").ok()
+}
+
+// utc, gmt, or an Area/City name like Europe/London. plain names like Japan
+// are timezones too but they're much more likely to be the start of a message
+pub fn parse_timezone(s: &str) -> Option<Tz> {
+    if s.eq_ignore_ascii_case("utc") || s.eq_ignore_ascii_case("gmt") {
+        return Some(Tz::UTC);
+    }
+    s.contains('/').then(|| s.parse().ok()).flatten()
+}
+
+// splits anything that looks like it's meant to be a timezone off the front of
+// a reminder's message, whether it's one we know or not
+pub fn split_timezone(s: &str) -> (Option<&str>, &str) {
+    let (first, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    let looks_like = first.eq_ignore_ascii_case("utc")
+        || first.eq_ignore_ascii_case("gmt")
+        || (first.contains('/') && !first.contains("://"));
+    match looks_like {
+        true => (Some(first), rest.trim()),
+        false => (None, s),
+    }
+}
+
+// the first time strictly after `after` that the reminder should go off, this
+// is at most a week away so there's no need to be any cleverer than checking
+// each day in turn. a time the clocks skip goes off an hour later, and a time
+// they repeat goes off the first time round
+pub fn next_fire(
+    schedule: Schedule,
+    time: NaiveTime,
+    timezone: Tz,
+    after: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let mut day = after.with_timezone(&timezone).date_naive();
+    loop {
+        let local = day.and_time(time);
+        let candidate = match timezone.from_local_datetime(&local) {
+            LocalResult::None => timezone
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest(),
+            repeated => repeated.earliest(),
+        };
+        if let Some(candidate) = candidate.map(|c| c.with_timezone(&Utc)) {
+            if candidate > after && schedule.includes(day.weekday()) {
+                return candidate;
+            }
+        }
+        day += Duration::days(1);
+    }
+}
+
+// sends anything that's due then hands the next time back to the main loop, if
+// we were down when something was due it goes off once when we're back
+pub async fn poll_reminders(network: usize, db: Database, tx: Sender<Bot>, mut poller: Poller) {
+    loop {
+        poller.tick().await;
+
+        let now = Utc::now();
+        let due = match db.due_reminders(now.timestamp()) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("SQL error checking reminders: {}", err);
+                continue;
+            }
+        };
+
+        for r in due {
+            let (Some(schedule), Some(time), Some(timezone)) = (
+                Schedule::parse(&r.schedule),
+                parse_time(&r.time),
+                parse_timezone(&r.timezone),
+            ) else {
+                println!(
+                    "Skipping reminder {} with a bad schedule: {} {} {}",
+                    r.id, r.schedule, r.time, r.timezone
+                );
+                continue;
+            };
+
+            // nothing's listening once the network's gone
+            let response = format!("{}: {}", r.nick, r.message);
+            let origin = Origin::new(network, &r.channel);
+            if tx.send(Bot::Privmsg(origin, response)).await.is_err() {
+                return;
+            }
+            let next = next_fire(schedule, time, timezone, now);
+            if tx
+                .send(Bot::UpdateReminder(r.id, next.timestamp()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+pub async fn poll_coin_alerts(
+    network: usize,
+    db: Database,
+    req: Req,
+    tx: Sender<Bot>,
+    mut poller: Poller,
+) {
+    loop {
+        poller.tick().await;
+        // nothing's listening once the network's gone
+        if check_coin_alerts(network, &db, &req, &tx).await.is_err() {
+            return;
+        }
+    }
+}
+
+// one ticker call per pair no matter how many alerts are waiting on it
+async fn check_coin_alerts(
+    network: usize,
+    db: &Database,
+    req: &Req,
+    tx: &Sender<Bot>,
+) -> Result<(), SendError<Bot>> {
+    let alerts = match db.network_coin_alerts() {
+        Ok(a) => a,
+        Err(err) => {
+            println!("SQL error checking coin alerts: {}", err);
+            return Ok(());
+        }
+    };
+
+    let mut by_pair: HashMap<&str, Vec<CoinAlert>> = HashMap::new();
+    for alert in alerts {
+        by_pair
+            .entry(bot::kraken_pair(&alert.coin))
+            .or_default()
+            .push(alert);
+    }
+
+    for (pair, alerts) in by_pair {
+        let spot = match bot::get_spot(pair, req).await {
+            Ok((spot, _)) => spot,
+            Err(err) => {
+                println!("Error checking {} for coin alerts: {}", pair, err);
+                continue;
+            }
+        };
+
+        for alert in alerts.iter().filter(|a| crossed(a, spot)) {
+            let response = format!(
+                "{}: {} is {} {} ({}) [#{}]",
+                alert.nick,
+                alert.coin.to_uppercase(),
+                if alert.above { "above" } else { "below" },
+                bot::coin_price(pair, alert.target),
+                bot::coin_price(pair, spot),
+                alert.id
+            );
+            tx.send(Bot::Privmsg(Origin::new(network, &alert.channel), response))
+                .await?;
+            tx.send(Bot::ClearCoinAlert(alert.id)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn crossed(alert: &CoinAlert, spot: f32) -> bool {
+    if alert.above {
+        spot >= alert.target
+    } else {
+        spot <= alert.target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn fire(schedule: &str, time: &str, timezone: &str, after: &str) -> DateTime<Utc> {
+        next_fire(
+            Schedule::parse(schedule).unwrap(),
+            parse_time(time).unwrap(),
+            parse_timezone(timezone).unwrap(),
+            utc(after),
+        )
+    }
+
+    #[test]
+    fn utc_reminders() {
+        assert_eq!(
+            fire("daily", "09:00", "UTC", "2024-01-10T08:00:00Z"),
+            utc("2024-01-10T09:00:00Z")
+        );
+        // strictly after, so one that's just gone off is tomorrow's
+        assert_eq!(
+            fire("daily", "09:00", "UTC", "2024-01-10T09:00:00Z"),
+            utc("2024-01-11T09:00:00Z")
+        );
+        // friday evening to monday morning
+        assert_eq!(
+            fire("weekdays", "09:00", "utc", "2024-01-12T18:00:00Z"),
+            utc("2024-01-15T09:00:00Z")
+        );
+        assert_eq!(
+            fire("wed", "09:00", "UTC", "2024-01-10T10:00:00Z"),
+            utc("2024-01-17T09:00:00Z")
+        );
+    }
+
+    #[test]
+    fn local_time_across_daylight_saving() {
+        // 09:00 in london is 09:00 utc in winter and 08:00 utc in summer, the
+        // clocks went forward on 2024-03-31 and back on 2024-10-27
+        assert_eq!(
+            fire("daily", "09:00", "Europe/London", "2024-03-30T10:00:00Z"),
+            utc("2024-03-31T08:00:00Z")
+        );
+        assert_eq!(
+            fire("daily", "09:00", "Europe/London", "2024-10-26T10:00:00Z"),
+            utc("2024-10-27T09:00:00Z")
+        );
+        // the day is london's, not utc's
+        assert_eq!(
+            fire("sat", "00:30", "Europe/London", "2024-06-07T12:00:00Z"),
+            utc("2024-06-07T23:30:00Z")
+        );
+        assert_eq!(
+            fire("daily", "09:00", "America/New_York", "2024-03-09T15:00:00Z"),
+            utc("2024-03-10T13:00:00Z")
+        );
+    }
+
+    #[test]
+    fn skipped_and_repeated_times() {
+        // 01:30 never happens when the clocks go forward, it's 02:30 instead
+        assert_eq!(
+            fire("daily", "01:30", "Europe/London", "2024-03-30T12:00:00Z"),
+            utc("2024-03-31T01:30:00Z")
+        );
+        // and happens twice when they go back, the first one counts
+        assert_eq!(
+            fire("daily", "01:30", "Europe/London", "2024-10-26T12:00:00Z"),
+            utc("2024-10-27T00:30:00Z")
+        );
+        // without going off again the second time round
+        assert_eq!(
+            fire("daily", "01:30", "Europe/London", "2024-10-27T00:30:00Z"),
+            utc("2024-10-28T01:30:00Z")
+        );
+    }
+
+    #[test]
+    fn timezones() {
+        assert_eq!(parse_timezone("Europe/London"), Some(Tz::Europe__London));
+        assert_eq!(parse_timezone("gmt"), Some(Tz::UTC));
+        assert_eq!(parse_timezone("Europe/Nowhere"), None);
+        assert_eq!(parse_timezone("Japan"), None);
+
+        assert_eq!(split_timezone("UTC stretch"), (Some("UTC"), "stretch"));
+        assert_eq!(
+            split_timezone("Europe/London stand up"),
+            (Some("Europe/London"), "stand up")
+        );
+        assert_eq!(split_timezone("Japan trip"), (None, "Japan trip"));
+        assert_eq!(
+            split_timezone("https://example.com"),
+            (None, "https://example.com")
+        );
+        assert_eq!(split_timezone("Europe/London"), (Some("Europe/London"), ""));
+    }
+}
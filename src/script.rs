@@ -0,0 +1,121 @@
+use crate::bot::split_args;
+use crate::messages::Msg;
+use crate::settings::ScriptConfig;
+use failure::{bail, Error};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+// plenty for a few lines of irc, anything past this is thrown away
+const MAX_OUTPUT: u64 = 4096;
+
+// runs `path <command> <args>...` for a command the bot doesn't know about and
+// returns the first few lines it prints, args are split like split_args does
+// and the unsplit text is in BOOT_ARGS, who asked and where are in BOOT_NICK,
+// BOOT_TARGET and BOOT_NETWORK, there's no shell in between so nothing in the
+// message gets interpreted. it gets PATH and any BOOT_* variables of ours but
+// nothing else from our environment, anyone on irc can run it with whatever
+// args they like so it should be a wrapper that drops to an unprivileged user,
+// i.e., `sudo -u boot-scripts`, rather than the scripts themselves
+pub async fn run(
+    script: &ScriptConfig,
+    msg: &Msg,
+    command: &str,
+    args: &str,
+) -> Result<Vec<String>, Error> {
+    let mut child = Command::new(&script.path)
+        .arg(command)
+        .args(split_args(args))
+        .env_clear()
+        .envs(std::env::vars().filter(|(k, _)| k == "PATH" || k.starts_with("BOOT_")))
+        .env("BOOT_ARGS", args)
+        .env("BOOT_NICK", &msg.source)
+        .env("BOOT_TARGET", &msg.target)
+        .env("BOOT_NETWORK", msg.network.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // so a script that's timed out doesn't keep running
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // always there, it's piped above
+    let stdout = child.stdout.take().unwrap();
+    let mut output = Vec::new();
+    let finished = async {
+        stdout.take(MAX_OUTPUT).read_to_end(&mut output).await?;
+        // once it's said more than we'll read whatever it was going to say next
+        // doesn't matter, it's stopped here rather than left to die writing to
+        // a closed pipe and what it said so far is used however it exits
+        if output.len() as u64 >= MAX_OUTPUT {
+            let _ = child.start_kill();
+            child.wait().await?;
+            return Ok(None);
+        }
+        child.wait().await.map(Some)
+    };
+
+    let status = match tokio::time::timeout(Duration::from_secs(script.timeout), finished).await {
+        Ok(status) => status?,
+        Err(_) => bail!("{} took longer than {}s", script.path, script.timeout),
+    };
+    if let Some(status) = status.filter(|s| !s.success()) {
+        bail!("{} exited with {}", script.path, status);
+    }
+
+    let lines = String::from_utf8_lossy(&output)
+        .lines()
+        .map(|l| l.replace(['\r', '\0'], "").trim_end().to_string())
+        .filter(|l| !l.trim().is_empty())
+        .take(script.max_lines)
+        .collect();
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `sh -c <script> sh`, the trailing sh is $0
+    async fn sh(script: &str) -> Result<Vec<String>, Error> {
+        let config = ScriptConfig {
+            path: "/bin/sh".to_string(),
+            timeout: 5,
+            max_lines: 3,
+        };
+        let msg = Msg {
+            network: 0,
+            current_nick: "boot".to_string(),
+            source: "niall".to_string(),
+            target: "#boot".to_string(),
+            hostmask: None,
+            content: String::new(),
+            received: std::time::Instant::now(),
+        };
+        run(&config, &msg, "-c", &format!("\"{}\" sh", script)).await
+    }
+
+    #[tokio::test]
+    async fn endless_output() {
+        let lines = sh("while :; do echo boot; done").await.unwrap();
+        assert_eq!(lines, ["boot", "boot", "boot"]);
+    }
+
+    #[tokio::test]
+    async fn environment() {
+        std::env::set_var("BOOT_TEST_PASSED", "passed");
+        std::env::set_var("TEST_SECRET", "secret");
+        let lines = sh("echo $BOOT_NICK $BOOT_TARGET $BOOT_TEST_PASSED; echo ${TEST_SECRET:-cleared}; echo ${PATH:+path}")
+            .await
+            .unwrap();
+        assert_eq!(lines, ["niall #boot passed", "cleared", "path"]);
+    }
+
+    #[tokio::test]
+    async fn failures() {
+        let err = sh("echo boot; exit 3").await.unwrap_err();
+        assert_eq!(err.to_string(), "/bin/sh exited with exit status: 3");
+    }
+}
@@ -1,40 +1,1027 @@
-use failure::Error;
+use crate::bot::{is_builtin, COINS, COIN_TIME_FRAMES};
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use chrono_tz::Tz;
+use failure::{bail, Error};
 use irc::client::data::Config as IRCConfig;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::Path;
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct BotConfig {
     pub db: Option<String>,
     pub weather_api: Option<String>,
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+    // seconds without receiving anything from the server before we start to
+    // worry about the connection, 0 disables the watchdog
+    #[serde(default = "default_watchdog")]
+    pub watchdog: u64,
+    #[serde(default)]
+    pub flood: FloodConfig,
+    // seconds a user has to wait before using the same (expensive) command again
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+    // nick!user@host masks allowed to use admin commands on every network, * and
+    // ? are wildcards. cloaks only mean something on the network that gave
+    // them out, list those as `owners` in that network's table instead
+    #[serde(default)]
+    pub admins: Vec<String>,
+    // channel -> names of commands that are ignored in that channel
+    #[serde(default)]
+    pub disabled: HashMap<String, Vec<String>>,
+    // prefix weather with an emoji for the conditions
+    #[serde(default = "default_true")]
+    pub weather_icons: bool,
+    #[serde(default)]
+    pub hangman: HangmanConfig,
+    // channels where urban dictionary lookups are allowed, it's off by default
+    // as a lot of what's on there is nsfw
+    #[serde(default)]
+    pub allow_ud: Vec<String>,
+    #[serde(default)]
+    pub stocks: StockConfig,
+    // channel -> message sent when someone joins, {nick} is replaced with their nick
+    #[serde(default)]
+    pub greetings: HashMap<String, String>,
+    // the most notifications a user can leave for someone before they're delivered
+    #[serde(default = "default_max_tells")]
+    pub max_tells: u32,
+    // the longest message that can be left with .tell, in characters
+    #[serde(default = "default_max_tell_length")]
+    pub max_tell_length: usize,
+    // somewhere to paste a backlog of messages to instead of sending them all
+    // to the channel, the text is POSTed as is and the response should be the
+    // link, i.e., https://paste.rs/
+    pub paste_url: Option<String>,
+    // OurAirports' airports.csv (https://ourairports.com/data/) so locations
+    // like EGLL or LHR are looked up as airports
+    pub airports: Option<String>,
+    // what to do when someone says our nick without a command
+    #[serde(default)]
+    pub highlight: Highlight,
+    // only say which channel someone was seen in if whoever's asking is in it too
+    #[serde(default = "default_true")]
+    pub seen_private: bool,
+    // colour temperatures, prices and titles
+    #[serde(default)]
+    pub use_colors: bool,
+    // sent when shutting down on SIGINT/SIGTERM
+    #[serde(default = "default_quit_message")]
+    pub quit_message: String,
+    // coins shown by .coins
+    #[serde(default = "default_overview_coins")]
+    pub overview_coins: Vec<String>,
+    // language for weather descriptions, see https://openweathermap.org/current#multi
+    #[serde(default = "default_weather_lang")]
+    pub weather_lang: String,
+    // channels where titles aren't fetched for links
+    #[serde(default)]
+    pub no_titles: Vec<String>,
+    #[serde(default)]
+    pub spam: SpamConfig,
+    // most http requests (titles, feeds, weather etc.) allowed in flight at once
+    #[serde(default = "default_max_requests")]
+    pub max_requests: usize,
+    // address outbound http requests are sent from
+    pub local_address: Option<IpAddr>,
+    // "v4" or "v6", only used when there's no local_address
+    pub ip_version: Option<IpVersion>,
+    // lines per channel kept in memory for .last and .grep, 0 disables them
+    #[serde(default = "default_history")]
+    pub history: usize,
+    // log every channel message to the database for .search
+    #[serde(default)]
+    pub log_messages: bool,
+    // days logged messages are kept for, 0 keeps them forever
+    #[serde(default = "default_log_retention")]
+    pub log_retention: u64,
+    // accept invalid or self-signed certificates, i.e., for internal hosts
+    #[serde(default)]
+    pub allow_insecure: bool,
+    // used by .syn, see https://www.datamuse.com/api/
+    #[serde(default = "default_thesaurus_url")]
+    pub thesaurus_url: String,
+    // events (messages, links, database updates etc.) waiting for the main loop
+    #[serde(default = "default_channel_capacity")]
+    pub channel_capacity: usize,
+    // how many messages' links can have their titles fetched at once
+    #[serde(default = "default_title_workers")]
+    pub title_workers: usize,
+    // when titles, easter eggs and greetings aren't sent, commands still work
+    pub quiet_hours: Option<QuietHours>,
+    // shown before link titles, coloured grey when colours are on
+    #[serde(default = "default_title_prefix")]
+    pub title_prefix: String,
+    // how titles are sent, {prefix} and {title} are replaced
+    #[serde(default = "default_title_format")]
+    pub title_format: String,
+    // channel -> what to do once we've joined it, i.e., ask ChanServ for op
+    #[serde(default)]
+    pub on_join: HashMap<String, JoinActions>,
+    // reply when a command needs an api key that isn't set, otherwise ignore it
+    #[serde(default = "default_true")]
+    pub explain_unconfigured: bool,
+    // whether .seen mentions other nicks someone has changed to or from
+    #[serde(default = "default_true")]
+    pub seen_aliases: bool,
+    // deliver .tell messages by user@host as well as nick, so they still reach
+    // someone who's changed nick, off by default as shared hosts (i.e., web
+    // gateways) could hand them to the wrong person
+    #[serde(default)]
+    pub match_hostmask: bool,
+    // plain N/NE/... and C/F in weather instead of arrows, emoji and degree
+    // signs, for clients that can't show them
+    #[serde(default)]
+    pub weather_ascii: bool,
+    // extra names for coins, i.e., xbt = "btc"
+    #[serde(default)]
+    pub coin_aliases: HashMap<String, String>,
+    // extra names for coin time frames on top of the built-in ones, i.e.,
+    // q = "90d", see bot::COIN_TIME_FRAMES for what they can point to
+    #[serde(default)]
+    pub coin_times: HashMap<String, String>,
+    // something to run for commands the bot doesn't have, off unless it's set
+    pub script: Option<ScriptConfig>,
+}
+
+// used when there's no [bot] section at all, this should match the serde defaults
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            db: None,
+            weather_api: None,
+            feeds: Vec::new(),
+            watchdog: default_watchdog(),
+            flood: FloodConfig::default(),
+            cooldown: default_cooldown(),
+            admins: Vec::new(),
+            disabled: HashMap::new(),
+            weather_icons: default_true(),
+            hangman: HangmanConfig::default(),
+            allow_ud: Vec::new(),
+            stocks: StockConfig::default(),
+            greetings: HashMap::new(),
+            max_tells: default_max_tells(),
+            max_tell_length: default_max_tell_length(),
+            paste_url: None,
+            airports: None,
+            highlight: Highlight::default(),
+            seen_private: default_true(),
+            use_colors: false,
+            quit_message: default_quit_message(),
+            overview_coins: default_overview_coins(),
+            weather_lang: default_weather_lang(),
+            no_titles: Vec::new(),
+            spam: SpamConfig::default(),
+            max_requests: default_max_requests(),
+            local_address: None,
+            ip_version: None,
+            history: default_history(),
+            log_messages: false,
+            log_retention: default_log_retention(),
+            allow_insecure: false,
+            thesaurus_url: default_thesaurus_url(),
+            channel_capacity: default_channel_capacity(),
+            title_workers: default_title_workers(),
+            quiet_hours: None,
+            title_prefix: default_title_prefix(),
+            title_format: default_title_format(),
+            on_join: HashMap::new(),
+            explain_unconfigured: default_true(),
+            seen_aliases: default_true(),
+            match_hostmask: false,
+            weather_ascii: false,
+            coin_aliases: HashMap::new(),
+            coin_times: HashMap::new(),
+            script: None,
+        }
+    }
+}
+
+fn default_overview_coins() -> Vec<String> {
+    ["btc", "eth", "ltc", "xmr", "doge"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+fn default_weather_lang() -> String {
+    "en".to_string()
+}
+
+fn default_quit_message() -> String {
+    "Shutting down".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl BotConfig {
+    pub fn is_disabled(&self, channel: &str, command: &str) -> bool {
+        self.disabled
+            .iter()
+            .filter(|(c, _)| c.eq_ignore_ascii_case(channel))
+            .any(|(_, commands)| commands.iter().any(|c| c.eq_ignore_ascii_case(command)))
+    }
+
+    pub fn greeting(&self, channel: &str) -> Option<&str> {
+        self.greetings
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(channel))
+            .map(|(_, g)| g.as_str())
+    }
+
+    pub fn join_actions(&self, channel: &str) -> Option<&JoinActions> {
+        self.on_join
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(channel))
+            .map(|(_, a)| a)
+    }
+
+    pub fn titles_enabled(&self, channel: &str) -> bool {
+        !self
+            .no_titles
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(channel))
+    }
+
+    pub fn http_address(&self) -> Option<IpAddr> {
+        match (self.local_address, &self.ip_version) {
+            (Some(address), _) => Some(address),
+            (None, Some(IpVersion::V4)) => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            (None, Some(IpVersion::V6)) => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            (None, None) => None,
+        }
+    }
+
+    // whether it's currently quiet hours, anything that isn't a reply to a
+    // command should check this first
+    pub fn is_quiet(&self) -> bool {
+        self.quiet_hours.as_ref().is_some_and(QuietHours::is_quiet)
+    }
+
+    pub fn allows_ud(&self, channel: &str) -> bool {
+        self.allow_ud
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(channel))
+    }
+}
+
+fn default_title_prefix() -> String {
+    "↳ ".to_string()
+}
+
+fn default_title_format() -> String {
+    "{prefix}{title}".to_string()
+}
+
+fn default_title_workers() -> usize {
+    4
+}
+
+fn default_channel_capacity() -> usize {
+    32
+}
+
+fn default_thesaurus_url() -> String {
+    "https://api.datamuse.com/words".to_string()
+}
+
+fn default_log_retention() -> u64 {
+    30
+}
+
+fn default_history() -> usize {
+    100
+}
+
+fn default_max_requests() -> usize {
+    8
+}
+
+fn default_max_tells() -> u32 {
+    5
+}
+
+fn default_max_tell_length() -> usize {
+    300
+}
+
+fn default_cooldown() -> u64 {
+    5
+}
+
+// chanserv = ["OP {channel}"], modes = ["+nt"], {channel} is replaced with the
+// channel, modes are set a few seconds after joining so there's time to be opped
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct JoinActions {
+    #[serde(default)]
+    pub chanserv: Vec<String>,
+    #[serde(default)]
+    pub modes: Vec<String>,
+}
+
+// at most `lines` messages to a target every `seconds`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FloodConfig {
+    pub lines: u32,
+    pub seconds: u64,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        Self {
+            lines: 4,
+            seconds: 8,
+        }
+    }
+}
+
+// highlight = "help", highlight = "ignore" or highlight = { message = "..." }
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Highlight {
+    #[default]
+    Help,
+    Ignore,
+    Message(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+// word lengths for hangman, short words are at most `short` letters long, long
+// words are at least `long` letters long and medium is everything in between
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HangmanConfig {
+    pub short: usize,
+    pub long: usize,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+impl Default for HangmanConfig {
+    fn default() -> Self {
+        Self {
+            short: 5,
+            long: 9,
+            difficulty: Difficulty::default(),
+        }
+    }
+}
+
+// what short, medium and long mean for hangman, either word length alone or
+// also how rare the letters in the word are, in which case short is the
+// easiest third of the short words and long the hardest third of the long ones
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    #[default]
+    Length,
+    Frequency,
+}
+
+// quotes are fetched from `url`?symbol=<symbol>&token=<api_key>, anything that
+// returns the same fields as finnhub's quote endpoint will do
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StockConfig {
+    pub api_key: Option<String>,
+    #[serde(default = "default_stock_url")]
+    pub url: String,
+}
+
+impl Default for StockConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            url: default_stock_url(),
+        }
+    }
+}
+
+fn default_stock_url() -> String {
+    "https://finnhub.io/api/v1/quote".to_string()
+}
+
+// anyone sending more than `lines` messages within `seconds` is ignored for
+// `mute` seconds, 0 lines disables this
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpamConfig {
+    pub lines: usize,
+    pub seconds: u64,
+    pub mute: u64,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            lines: 6,
+            seconds: 5,
+            mute: 60,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+// start = "23:00", end = "07:00", timezone = "Europe/London", the end can be
+// before the start for hours that run past midnight, the timezone can also be
+// a fixed offset like "+01:00" but that won't follow daylight saving
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_timezone() -> String {
+    "+00:00".to_string()
+}
+
+impl QuietHours {
+    // start and end, or None if either of them or the timezone are invalid
+    fn parse(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let start = NaiveTime::parse_from_str(&self.start, "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(&self.end, "%H:%M").ok()?;
+        local_time(&self.timezone, Utc::now())?;
+        Some((start, end))
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.is_quiet_at(Utc::now())
+    }
+
+    fn is_quiet_at(&self, now: DateTime<Utc>) -> bool {
+        let (Some((start, end)), Some(now)) = (self.parse(), local_time(&self.timezone, now))
+        else {
+            return false;
+        };
+
+        match start <= end {
+            true => start <= now && now < end,
+            false => now >= start || now < end,
+        }
+    }
+}
+
+// the time of day in a tz database zone or at a fixed offset
+fn local_time(timezone: &str, now: DateTime<Utc>) -> Option<NaiveTime> {
+    match timezone.parse::<Tz>() {
+        Ok(tz) => Some(now.with_timezone(&tz).time()),
+        Err(_) => {
+            let offset = timezone.parse::<FixedOffset>().ok()?;
+            Some(now.with_timezone(&offset).time())
+        }
+    }
+}
+
+// path = "/usr/local/bin/boot-commands", it's run as `path <command> <args>`
+// for any .command that isn't built in, see script::run. it runs as whoever
+// the bot runs as so point it at a wrapper that drops to a user that can't
+// read the config or the database
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScriptConfig {
+    pub path: String,
+    // seconds before it's killed
+    #[serde(default = "default_script_timeout")]
+    pub timeout: u64,
+    // lines of output sent to the channel, the rest is ignored
+    #[serde(default = "default_script_lines")]
+    pub max_lines: usize,
+}
+
+fn default_script_timeout() -> u64 {
+    5
+}
+
+fn default_script_lines() -> usize {
+    3
+}
+
+fn default_watchdog() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FeedConfig {
+    pub url: String,
+    pub channel: String,
+    // seconds between polls
+    #[serde(default = "default_feed_interval")]
+    pub interval: u64,
+}
+
+fn default_feed_interval() -> u64 {
+    900
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Settings {
     #[serde(default)]
     pub bot: BotConfig,
     pub irc: IRCConfig,
+    // any other networks to connect to as well as [irc], each one gets its own
+    // connection and games but they all share [bot] and the database
+    #[serde(default)]
+    pub networks: Vec<IRCConfig>,
+}
+
+// pushes the name of every field that differs between two configs
+macro_rules! diff {
+    ($old:expr, $new:expr, $into:expr, $($field:ident),+) => {
+        $(if $old.$field != $new.$field {
+            $into.push(stringify!($field));
+        })+
+    };
+}
+
+// swaps the fields back so that the new config keeps whatever's in use
+macro_rules! keep {
+    ($old:expr, $new:expr, $($field:ident),+) => {
+        $(std::mem::swap(&mut $old.$field, &mut $new.$field);)+
+    };
+}
+
+#[derive(Debug, Default)]
+pub struct Reload {
+    // applied straight away
+    pub changed: Vec<&'static str>,
+    // changed in the file but ignored until the bot is restarted
+    pub restart: Vec<&'static str>,
 }
 
 impl Settings {
+    // takes everything from `new` that can change while we're connected,
+    // anything that's only read on startup is left alone
+    pub fn reload(&mut self, mut new: Settings) -> Reload {
+        let mut reload = Reload::default();
+        let (old, bot) = (&mut self.bot, &mut new.bot);
+
+        // owners are picked up as admins, see Settings::network
+        let without_owners = |irc: &IRCConfig| IRCConfig {
+            owners: Vec::new(),
+            ..irc.clone()
+        };
+        if without_owners(&self.irc) != without_owners(&new.irc) {
+            reload.restart.push("irc");
+        }
+        if self.networks != new.networks {
+            reload.restart.push("networks");
+        }
+        diff!(
+            old,
+            bot,
+            reload.restart,
+            db,
+            feeds,
+            watchdog,
+            flood,
+            max_requests,
+            local_address,
+            ip_version,
+            allow_insecure,
+            channel_capacity,
+            title_workers
+        );
+        keep!(
+            old,
+            bot,
+            db,
+            feeds,
+            watchdog,
+            flood,
+            max_requests,
+            local_address,
+            ip_version,
+            allow_insecure,
+            channel_capacity,
+            title_workers
+        );
+        diff!(
+            old,
+            bot,
+            reload.changed,
+            weather_api,
+            cooldown,
+            admins,
+            disabled,
+            weather_icons,
+            hangman,
+            allow_ud,
+            stocks,
+            greetings,
+            max_tells,
+            max_tell_length,
+            paste_url,
+            airports,
+            highlight,
+            seen_private,
+            use_colors,
+            quit_message,
+            overview_coins,
+            weather_lang,
+            no_titles,
+            spam,
+            history,
+            log_messages,
+            log_retention,
+            thesaurus_url,
+            quiet_hours,
+            title_prefix,
+            title_format,
+            on_join,
+            explain_unconfigured,
+            seen_aliases,
+            match_hostmask,
+            weather_ascii,
+            coin_aliases,
+            coin_times,
+            script
+        );
+
+        self.bot = new.bot;
+        reload
+    }
+
+    // how many networks there are to connect to, [irc] included
+    pub fn network_count(&self) -> usize {
+        1 + self.networks.len()
+    }
+
+    // the settings for a single network, [irc] is network 0 and [[networks]]
+    // follow on from it, feeds are only posted on the first network
+    pub fn network(&self, i: usize) -> Option<Settings> {
+        let irc = match i {
+            0 => self.irc.clone(),
+            i => self.networks.get(i - 1)?.clone(),
+        };
+        let mut bot = self.bot.clone();
+        if i > 0 {
+            bot.feeds.clear();
+        }
+        bot.admins.extend(irc.owners.iter().cloned());
+
+        Some(Settings {
+            bot,
+            irc,
+            networks: Vec::new(),
+        })
+    }
+
+    // what a network's rows in the database are kept under, its server unless
+    // it's given a name with `options = { network = "..." }`, so moving the
+    // networks around in the config doesn't move anyone's reminders
+    pub fn network_name(&self, i: usize) -> Option<String> {
+        let irc = match i {
+            0 => &self.irc,
+            i => self.networks.get(i - 1)?,
+        };
+        let name = irc
+            .get_option("network")
+            .or(irc.server.as_deref())
+            .unwrap_or_default();
+        Some(name.to_lowercase())
+    }
+
+    pub fn network_names(&self) -> Vec<String> {
+        (0..self.network_count())
+            .filter_map(|i| self.network_name(i))
+            .collect()
+    }
+
     pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
         let conf = fs::read_to_string(path)?;
         let settings: Settings = toml::de::from_str(&conf)?;
         Ok(settings)
     }
+
+    // catches anything serde is happy with but that won't work, every problem
+    // is listed at once so fixing the config isn't a game of whack-a-mole
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+        let bot = &self.bot;
+        let is_channel = |c: &str| c.starts_with('#') || c.starts_with('&');
+        let is_hostmask = |m: &str| m.split_once('!').is_some_and(|(_, h)| h.contains('@'));
+
+        let networks = std::iter::once(("[irc]".to_string(), &self.irc)).chain(
+            self.networks
+                .iter()
+                .enumerate()
+                .map(|(i, n)| (format!("[[networks]] #{}", i + 1), n)),
+        );
+        for (name, irc) in networks {
+            if irc.server.as_deref().unwrap_or_default().is_empty() {
+                problems.push(format!("{} server is missing", name));
+            }
+            if irc.nickname.as_deref().unwrap_or_default().is_empty() {
+                problems.push(format!("{} nickname is missing", name));
+            }
+            if irc.channels.is_empty() {
+                problems.push(format!(
+                    "{} channels is empty, there's nowhere to join",
+                    name
+                ));
+            }
+            for channel in irc.channels.iter().filter(|c| !is_channel(c)) {
+                problems.push(format!("{} {} in channels isn't a channel", name, channel));
+            }
+            for owner in irc.owners.iter().filter(|o| !is_hostmask(o)) {
+                problems.push(format!(
+                    "{} owner {} isn't a nick!user@host mask",
+                    name, owner
+                ));
+            }
+        }
+        for admin in bot.admins.iter().filter(|a| !is_hostmask(a)) {
+            problems.push(format!(
+                "admin {} isn't a nick!user@host mask, i.e., *!*@user/{}",
+                admin, admin
+            ));
+        }
+
+        let names = self.network_names();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) && !name.is_empty() {
+                problems.push(format!(
+                    "two networks are both called {}, give one a name with options = {{ network = \"...\" }}",
+                    name
+                ));
+            }
+        }
+
+        if bot.weather_api.as_deref() == Some("") {
+            problems.push("weather_api is empty, remove it to disable weather".to_string());
+        }
+        if bot.stocks.api_key.as_deref() == Some("") {
+            problems.push("[bot.stocks] api_key is empty, remove it to disable stocks".to_string());
+        }
+        if bot.flood.lines == 0 || bot.flood.seconds == 0 {
+            problems.push("[bot.flood] lines and seconds must both be above 0".to_string());
+        }
+        if bot.spam.lines > 0 && bot.spam.seconds == 0 {
+            problems
+                .push("[bot.spam] seconds must be above 0, set lines to 0 to disable".to_string());
+        }
+        // medium words are longer than short and shorter than long so there
+        // has to be room for at least one length in between
+        if bot.hangman.long < bot.hangman.short + 2 {
+            problems.push("[bot.hangman] long must be at least short + 2".to_string());
+        }
+        for (alias, coin) in &bot.coin_aliases {
+            if COINS.iter().any(|c| c.eq_ignore_ascii_case(alias)) {
+                problems.push(format!("coin_aliases {} is already a coin", alias));
+            } else if is_builtin(alias, &BotConfig::default()) {
+                // the command would win and the alias would never be used
+                problems.push(format!("coin_aliases {} is already a command", alias));
+            }
+            if !COINS.iter().any(|c| c.eq_ignore_ascii_case(coin)) {
+                problems.push(format!("coin_aliases {} = {} isn't a coin", alias, coin));
+            }
+        }
+        for (alias, time) in &bot.coin_times {
+            if !COIN_TIME_FRAMES
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(time))
+            {
+                problems.push(format!(
+                    "coin_times {} = {} should be one of {}",
+                    alias,
+                    time,
+                    COIN_TIME_FRAMES.join(", ")
+                ));
+            }
+        }
+        if !bot.title_format.contains("{title}") {
+            problems.push("title_format doesn't include {title}".to_string());
+        }
+        if bot.title_workers == 0 {
+            problems.push("title_workers must be above 0".to_string());
+        }
+        if bot.channel_capacity == 0 {
+            problems.push("channel_capacity must be above 0".to_string());
+        }
+        if bot.max_requests == 0 {
+            problems.push("max_requests must be above 0".to_string());
+        }
+        if let Some(url) = &bot.paste_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!("paste_url {} isn't an http(s) url", url));
+            }
+        }
+        if let Some(path) = &bot.airports {
+            if !Path::new(path).is_file() {
+                problems.push(format!("airports {} doesn't exist", path));
+            }
+        }
+        if bot.max_tell_length == 0 {
+            problems.push("max_tell_length must be above 0".to_string());
+        }
+        if let Highlight::Message(m) = &bot.highlight {
+            if m.trim().is_empty() {
+                problems.push("highlight message is empty, use \"ignore\" instead".to_string());
+            }
+        }
+        if let Some(quiet) = &bot.quiet_hours {
+            if quiet.parse().is_none() {
+                problems.push(
+                    "[bot.quiet_hours] start and end should be HH:MM and timezone like Europe/London or +01:00"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(script) = &bot.script {
+            if !Path::new(&script.path).is_file() {
+                problems.push(format!("[bot.script] {} doesn't exist", script.path));
+            }
+            if script.timeout == 0 {
+                problems.push("[bot.script] timeout must be above 0".to_string());
+            }
+            if script.max_lines == 0 {
+                problems.push("[bot.script] max_lines must be above 0".to_string());
+            }
+        }
+        match (bot.local_address, &bot.ip_version) {
+            (Some(IpAddr::V4(_)), Some(IpVersion::V6))
+            | (Some(IpAddr::V6(_)), Some(IpVersion::V4)) => {
+                problems.push("local_address doesn't match ip_version".to_string());
+            }
+            _ => (),
+        }
+
+        for feed in &bot.feeds {
+            if feed.url.is_empty() {
+                problems.push("[[bot.feeds]] url is empty".to_string());
+            }
+            if !is_channel(&feed.channel) {
+                problems.push(format!("[[bot.feeds]] {} isn't a channel", feed.channel));
+            }
+            if feed.interval == 0 {
+                problems.push(format!(
+                    "[[bot.feeds]] interval for {} must be above 0",
+                    feed.url
+                ));
+            }
+        }
+
+        let channels = bot
+            .disabled
+            .keys()
+            .chain(bot.greetings.keys())
+            .chain(bot.allow_ud.iter())
+            .chain(bot.no_titles.iter())
+            .chain(bot.on_join.keys());
+        for channel in channels.filter(|c| !is_channel(c)) {
+            problems.push(format!("{} isn't a channel", channel));
+        }
+
+        if !problems.is_empty() {
+            bail!("invalid config:\n  {}", problems.join("\n  "));
+        }
+        Ok(())
+    }
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            bot: BotConfig {
-                db: None,
-                weather_api: None,
+            bot: BotConfig::default(),
+            irc: IRCConfig {
+                ..IRCConfig::default()
             },
+            networks: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
             irc: IRCConfig {
+                server: Some("irc.example.org".to_string()),
+                nickname: Some("boot".to_string()),
+                channels: vec!["#boot".to_string()],
                 ..IRCConfig::default()
             },
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(settings().validate().is_ok());
+    }
+
+    #[test]
+    fn hangman_lengths_leave_room_for_medium() {
+        let mut s = settings();
+        for (short, long, valid) in [
+            (5, 9, true),
+            (5, 7, true),
+            (5, 6, false),
+            (5, 5, false),
+            (6, 5, false),
+        ] {
+            s.bot.hangman.short = short;
+            s.bot.hangman.long = long;
+            assert_eq!(s.validate().is_ok(), valid, "short {} long {}", short, long);
+        }
+    }
+
+    #[test]
+    fn coin_aliases_arent_commands() {
+        let mut s = settings();
+        for (alias, valid) in [
+            ("bitty", true),
+            ("btc", false),
+            ("np", false),
+            ("remind", false),
+            ("CoinAlert", false),
+            ("help", false),
+            ("fig", false),
+        ] {
+            s.bot.coin_aliases = HashMap::from([(alias.to_string(), "btc".to_string())]);
+            assert_eq!(s.validate().is_ok(), valid, "{}", alias);
+        }
+    }
+
+    #[test]
+    fn admins_are_hostmasks() {
+        let mut s = settings();
+        s.bot.admins = vec!["*!*@user/niall".to_string()];
+        s.irc.owners = vec!["niall!~n@example.org".to_string()];
+        assert!(s.validate().is_ok());
+        assert_eq!(s.network(0).unwrap().bot.admins.len(), 2);
+
+        s.bot.admins = vec!["niall".to_string()];
+        assert!(s.validate().is_err());
+        s.bot.admins.clear();
+        s.irc.owners = vec!["niall@example.org".to_string()];
+        assert!(s.validate().is_err());
+    }
+
+    fn quiet(start: &str, end: &str, timezone: &str) -> QuietHours {
+        QuietHours {
+            start: start.to_string(),
+            end: end.to_string(),
+            timezone: timezone.to_string(),
+        }
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_past_midnight() {
+        let q = quiet("23:00", "07:00", "+00:00");
+        assert!(q.is_quiet_at(utc("2024-01-10T23:00:00Z")));
+        assert!(q.is_quiet_at(utc("2024-01-10T03:00:00Z")));
+        assert!(!q.is_quiet_at(utc("2024-01-10T07:00:00Z")));
+        assert!(!q.is_quiet_at(utc("2024-01-10T12:00:00Z")));
+
+        let q = quiet("09:00", "17:00", "+00:00");
+        assert!(q.is_quiet_at(utc("2024-01-10T12:00:00Z")));
+        assert!(!q.is_quiet_at(utc("2024-01-10T20:00:00Z")));
+    }
+
+    #[test]
+    fn quiet_hours_follow_daylight_saving() {
+        let q = quiet("23:00", "07:00", "Europe/London");
+        // 22:30 GMT in winter, 23:30 BST in summer
+        assert!(!q.is_quiet_at(utc("2024-01-10T22:30:00Z")));
+        assert!(q.is_quiet_at(utc("2024-07-10T22:30:00Z")));
+        // 06:30 GMT, 07:30 BST
+        assert!(q.is_quiet_at(utc("2024-01-10T06:30:00Z")));
+        assert!(!q.is_quiet_at(utc("2024-07-10T06:30:00Z")));
+
+        // a fixed offset doesn't move
+        let q = quiet("23:00", "07:00", "+01:00");
+        assert!(q.is_quiet_at(utc("2024-01-10T22:30:00Z")));
+        assert!(q.is_quiet_at(utc("2024-07-10T22:30:00Z")));
+    }
+
+    #[test]
+    fn invalid_quiet_hours() {
+        let mut s = settings();
+        for (q, valid) in [
+            (quiet("23:00", "07:00", "Europe/London"), true),
+            (quiet("23:00", "07:00", "-05:00"), true),
+            (quiet("23:00", "07:00", "Mars/Olympus_Mons"), false),
+            (quiet("11pm", "07:00", "UTC"), false),
+        ] {
+            assert!(!q.is_quiet_at(utc("2024-01-10T23:30:00Z")) || valid);
+            s.bot.quiet_hours = Some(q);
+            assert_eq!(s.validate().is_ok(), valid, "{:?}", s.bot.quiet_hours);
         }
     }
 }
@@ -1,6 +1,6 @@
 use crate::bot::Coin;
 use failure::Error;
-use r2d2_sqlite::rusqlite::params;
+use r2d2_sqlite::rusqlite::{params, Connection};
 use r2d2_sqlite::SqliteConnectionManager;
 use serde::Deserialize;
 use std::path::Path;
@@ -8,30 +8,70 @@ use std::path::Path;
 #[derive(Clone)]
 pub struct Database {
     db: r2d2::Pool<SqliteConnectionManager>,
+    // nicks and channels only mean something on the network they're from, so
+    // anything keyed by them is kept per network, see Settings::network_name
+    network: String,
 }
 
 impl Database {
-    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+    // `networks` are the configured network names, the first one is [irc]
+    pub fn open(path: impl AsRef<Path>, networks: &[String]) -> Result<Self, Error> {
         let db = SqliteConnectionManager::file(path);
         let db = r2d2::Pool::new(db)?;
+        let network = networks.first().cloned().unwrap_or_default();
 
         let conn = db.get()?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS seen (
-            username    TEXT PRIMARY KEY,
+        let seen = "CREATE TABLE IF NOT EXISTS seen (
+            network     TEXT NOT NULL,
+            username    TEXT NOT NULL,
             message     TEXT NOT NULL,
-            time        TEXT NOT NULL)",
-            [],
-        )?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS notifications (
+            time        TEXT NOT NULL,
+            kind        TEXT NOT NULL DEFAULT 'message',
+            channel     TEXT,
+            hostmask    TEXT,
+            PRIMARY KEY (network, username))";
+        conn.execute(seen, [])?;
+        // older databases crammed the kind of event into the message
+        if !has_column(&conn, "seen", "kind")? {
+            conn.execute_batch(
+                "ALTER TABLE seen ADD COLUMN kind TEXT NOT NULL DEFAULT 'message';
+                UPDATE seen SET kind = 'kick', message = substr(message, 19)
+                WHERE message LIKE 'being kicked from %';
+                UPDATE seen SET message = substr(message, 9)
+                WHERE message LIKE 'saying: %';",
+            )?;
+        }
+        if !has_column(&conn, "seen", "channel")? {
+            conn.execute_batch(
+                "ALTER TABLE seen ADD COLUMN channel TEXT;
+                UPDATE seen SET channel = message
+                WHERE kind IN ('kick', 'join', 'part');",
+            )?;
+        }
+        // user@host of the last time they were seen, where we know it
+        if !has_column(&conn, "seen", "hostmask")? {
+            conn.execute("ALTER TABLE seen ADD COLUMN hostmask TEXT", [])?;
+        }
+        add_network(&conn, "seen", seen, networks)?;
+        let notifications = "CREATE TABLE IF NOT EXISTS notifications (
             id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            network     TEXT NOT NULL,
             recipient   TEXT NOT NULL,
             via         TEXT NOT NULL,
-            message     TEXT NOT NULL)",
-            [],
-        )?;
+            message     TEXT NOT NULL,
+            created     TEXT,
+            hostmask    TEXT)";
+        conn.execute(notifications, [])?;
+        // notifications left before this was added won't have a time
+        if !has_column(&conn, "notifications", "created")? {
+            conn.execute("ALTER TABLE notifications ADD COLUMN created TEXT", [])?;
+        }
+        // the recipient's user@host when the message was left, if we knew it
+        if !has_column(&conn, "notifications", "hostmask")? {
+            conn.execute("ALTER TABLE notifications ADD COLUMN hostmask TEXT", [])?;
+        }
+        add_network(&conn, "notifications", notifications, networks)?;
         conn.execute(
             "CREATE TABLE IF NOT EXISTS locations (
             loc         TEXT PRIMARY KEY,
@@ -41,13 +81,14 @@ impl Database {
             country     TEXT NOT NULL)",
             [],
         )?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS weather (
-            username    TEXT PRIMARY KEY,
+        let weather = "CREATE TABLE IF NOT EXISTS weather (
+            network     TEXT NOT NULL,
+            username    TEXT NOT NULL,
             lat         TEXT NOT NULL,
-            lon         TEXT NOT NULL)",
-            [],
-        )?;
+            lon         TEXT NOT NULL,
+            PRIMARY KEY (network, username))";
+        conn.execute(weather, [])?;
+        add_network(&conn, "weather", weather, networks)?;
         conn.execute(
             "CREATE TABLE IF NOT EXISTS coins (
             coin        TEXT PRIMARY KEY,
@@ -56,16 +97,156 @@ impl Database {
             data_1      TEXT NOT NULL)",
             [],
         )?;
-        Ok(Self { db })
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS feeds (
+            url         TEXT NOT NULL,
+            entry       TEXT NOT NULL,
+            PRIMARY KEY (url, entry))",
+            [],
+        )?;
+        let music = "CREATE TABLE IF NOT EXISTS music (
+            network     TEXT NOT NULL,
+            username    TEXT NOT NULL,
+            service     TEXT NOT NULL,
+            account     TEXT NOT NULL,
+            PRIMARY KEY (network, username))";
+        conn.execute(music, [])?;
+        add_network(&conn, "music", music, networks)?;
+        // scope is either a nick or a channel, both lowercase
+        let aliases = "CREATE TABLE IF NOT EXISTS aliases (
+            network     TEXT NOT NULL,
+            scope       TEXT NOT NULL,
+            name        TEXT NOT NULL,
+            expansion   TEXT NOT NULL,
+            PRIMARY KEY (network, scope, name))";
+        conn.execute(aliases, [])?;
+        add_network(&conn, "aliases", aliases, networks)?;
+        // how many times links to a host have been posted, both lowercase
+        let domains = "CREATE TABLE IF NOT EXISTS domains (
+            network     TEXT NOT NULL,
+            channel     TEXT NOT NULL,
+            host        TEXT NOT NULL,
+            count       INTEGER NOT NULL,
+            PRIMARY KEY (network, channel, host))";
+        conn.execute(domains, [])?;
+        add_network(&conn, "domains", domains, networks)?;
+        // how often each command is used in each channel, private messages are
+        // counted together under "private"
+        let command_stats = "CREATE TABLE IF NOT EXISTS command_stats (
+            network     TEXT NOT NULL,
+            channel     TEXT NOT NULL,
+            command     TEXT NOT NULL,
+            count       INTEGER NOT NULL,
+            last_used   TEXT NOT NULL,
+            PRIMARY KEY (network, channel, command))";
+        conn.execute(command_stats, [])?;
+        add_network(&conn, "command_stats", command_stats, networks)?;
+        // only written to when log_messages is enabled
+        let messages = "CREATE TABLE IF NOT EXISTS messages (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            network     TEXT NOT NULL,
+            channel     TEXT NOT NULL,
+            nick        TEXT NOT NULL,
+            content     TEXT NOT NULL,
+            time        TEXT NOT NULL)";
+        conn.execute(messages, [])?;
+        add_network(&conn, "messages", messages, networks)?;
+        // after the table's been rebuilt, the old index goes with the old table
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS messages_time ON messages (time)",
+            [],
+        )?;
+        // points won in games, i.e., trivia
+        let scores = "CREATE TABLE IF NOT EXISTS scores (
+            network     TEXT NOT NULL,
+            game        TEXT NOT NULL,
+            nick        TEXT NOT NULL COLLATE NOCASE,
+            points      INTEGER NOT NULL,
+            PRIMARY KEY (network, game, nick))";
+        conn.execute(scores, [])?;
+        add_network(&conn, "scores", scores, networks)?;
+        // nicks that don't want to show up in .seen
+        let optout = "CREATE TABLE IF NOT EXISTS optout (
+            network     TEXT NOT NULL,
+            username    TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (network, username))";
+        conn.execute(optout, [])?;
+        add_network(&conn, "optout", optout, networks)?;
+        // nicks that don't want titles or easter eggs in response to them
+        let muted = "CREATE TABLE IF NOT EXISTS muted (
+            network     TEXT NOT NULL,
+            username    TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (network, username))";
+        conn.execute(muted, [])?;
+        add_network(&conn, "muted", muted, networks)?;
+        // nicks seen changing to or from each other, linked by user@host, so
+        // .seen can say who else someone has been
+        let nick_hosts = "CREATE TABLE IF NOT EXISTS nick_hosts (
+            network     TEXT NOT NULL,
+            nick        TEXT NOT NULL COLLATE NOCASE,
+            hostmask    TEXT NOT NULL,
+            time        TEXT NOT NULL,
+            PRIMARY KEY (network, nick, hostmask))";
+        conn.execute(nick_hosts, [])?;
+        add_network(&conn, "nick_hosts", nick_hosts, networks)?;
+        // recurring reminders, `next` is when it next goes off (unix time)
+        let reminders = "CREATE TABLE IF NOT EXISTS reminders (
+            id          INTEGER PRIMARY KEY,
+            network     TEXT NOT NULL,
+            nick        TEXT NOT NULL COLLATE NOCASE,
+            channel     TEXT NOT NULL,
+            message     TEXT NOT NULL,
+            schedule    TEXT NOT NULL,
+            time        TEXT NOT NULL,
+            timezone    TEXT NOT NULL DEFAULT 'UTC',
+            next        INTEGER NOT NULL)";
+        conn.execute(reminders, [])?;
+        // reminders were all in utc before they each had a timezone
+        if !has_column(&conn, "reminders", "timezone")? {
+            conn.execute(
+                "ALTER TABLE reminders ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC'",
+                [],
+            )?;
+        }
+        add_network(&conn, "reminders", reminders, networks)?;
+        // one-off price alerts, removed once the price crosses the target
+        let coin_alerts = "CREATE TABLE IF NOT EXISTS coin_alerts (
+            id          INTEGER PRIMARY KEY,
+            network     TEXT NOT NULL,
+            nick        TEXT NOT NULL COLLATE NOCASE,
+            channel     TEXT NOT NULL,
+            coin        TEXT NOT NULL,
+            above       INTEGER NOT NULL,
+            target      REAL NOT NULL)";
+        conn.execute(coin_alerts, [])?;
+        add_network(&conn, "coin_alerts", coin_alerts, networks)?;
+        Ok(Self { db, network })
+    }
+
+    // the same database seen from another network
+    pub fn for_network(&self, network: &str) -> Database {
+        Database {
+            db: self.db.clone(),
+            network: network.to_string(),
+        }
     }
 
     pub fn add_seen(&self, entry: &Seen) -> Result<(), Error> {
         self.db.get()?.execute(
-            "INSERT INTO seen   (username, message, time)
-            VALUES              (:username, :message, :time)
-            ON CONFLICT (username) DO
-            UPDATE SET message=:message,time=:time",
-            params!(entry.username, entry.message, entry.time),
+            "INSERT INTO seen   (network, username, message, time, kind, channel, hostmask)
+            VALUES              (:network, :username, :message, :time, :kind, :channel, :hostmask)
+            ON CONFLICT (network, username) DO
+            UPDATE SET message=:message,time=:time,kind=:kind,channel=:channel,
+                hostmask=COALESCE(:hostmask, hostmask)",
+            params!(
+                self.network,
+                entry.username,
+                entry.message,
+                entry.time,
+                entry.kind.as_str(),
+                entry.channel,
+                entry.hostmask
+            ),
         )?;
 
         Ok(())
@@ -75,16 +256,20 @@ impl Database {
         let conn = self.db.get()?;
 
         let mut statement = conn.prepare(
-            "SELECT username, message, time
+            "SELECT username, message, time, kind, channel, hostmask
             FROM seen
-            WHERE username = :username
+            WHERE network = :network AND username = :username
             COLLATE NOCASE",
         )?;
-        let rows = statement.query_map(params![nick], |r| {
+        let rows = statement.query_map(params![self.network, nick], |r| {
+            let kind: String = r.get(3)?;
             Ok(Seen {
                 username: r.get(0)?,
                 message: r.get(1)?,
                 time: r.get(2)?,
+                kind: SeenKind::parse(&kind),
+                channel: r.get(4)?,
+                hostmask: r.get(5)?,
             })
         })?;
 
@@ -96,16 +281,150 @@ impl Database {
         Ok(results.pop())
     }
 
+    pub fn remove_seen(&self, nick: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "DELETE FROM seen
+            WHERE network = :network AND username = :username
+            COLLATE NOCASE",
+            params!(self.network, nick),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn add_optout(&self, nick: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT OR IGNORE INTO optout (network, username)
+            VALUES (:network, :username)",
+            params!(self.network, nick),
+        )?;
+
+        Ok(())
+    }
+
+    // returns false if they weren't opted out
+    pub fn remove_optout(&self, nick: &str) -> Result<bool, Error> {
+        let removed = self.db.get()?.execute(
+            "DELETE FROM optout
+            WHERE network = :network AND username = :username",
+            params!(self.network, nick),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn is_opted_out(&self, nick: &str) -> Result<bool, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT 1
+            FROM optout
+            WHERE network = :network AND username = :username",
+        )?;
+
+        Ok(statement.exists(params![self.network, nick])?)
+    }
+
+    pub fn add_nick_host(&self, nick: &str, hostmask: &str, time: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT INTO nick_hosts (network, nick, hostmask, time)
+            VALUES                  (:network, :nick, :hostmask, :time)
+            ON CONFLICT(network, nick, hostmask) DO UPDATE SET time = excluded.time",
+            params!(self.network, nick, hostmask, time),
+        )?;
+
+        Ok(())
+    }
+
+    // other nicks that have shared a user@host with this one, most recent first,
+    // leaving out anyone who's opted out
+    pub fn nick_aliases(&self, nick: &str, limit: u32) -> Result<Vec<String>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT b.nick
+            FROM nick_hosts a
+            JOIN nick_hosts b ON a.network = b.network AND a.hostmask = b.hostmask
+            WHERE a.network = :network AND a.nick = :nick AND b.nick != :nick
+                AND b.nick NOT IN (SELECT username FROM optout WHERE network = :network)
+            GROUP BY b.nick
+            ORDER BY MAX(b.time) DESC
+            LIMIT :limit",
+        )?;
+        let rows = statement.query_map(params![self.network, nick, limit], |r| r.get(0))?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+
+    pub fn add_mute(&self, nick: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT OR IGNORE INTO muted (network, username)
+            VALUES (:network, :username)",
+            params!(self.network, nick),
+        )?;
+
+        Ok(())
+    }
+
+    // returns false if they weren't muted
+    pub fn remove_mute(&self, nick: &str) -> Result<bool, Error> {
+        let removed = self.db.get()?.execute(
+            "DELETE FROM muted
+            WHERE network = :network AND username = :username",
+            params!(self.network, nick),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn is_muted(&self, nick: &str) -> Result<bool, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT 1
+            FROM muted
+            WHERE network = :network AND username = :username",
+        )?;
+
+        Ok(statement.exists(params![self.network, nick])?)
+    }
+
     pub fn add_notification(&self, entry: &Notification) -> Result<(), Error> {
         self.db.get()?.execute(
-            "INSERT INTO notifications  (recipient, via, message)
-            VALUES                      (:recipient, :via, :message)",
-            params!(entry.recipient, entry.via, entry.message),
+            "INSERT INTO notifications  (network, recipient, via, message, created, hostmask)
+            VALUES                      (:network, :recipient, :via, :message, :created, :hostmask)",
+            params!(
+                self.network,
+                entry.recipient,
+                entry.via,
+                entry.message,
+                entry.created,
+                entry.hostmask
+            ),
         )?;
 
         Ok(())
     }
 
+    // how many notifications `via` has waiting for `recipient`
+    pub fn count_notifications(&self, recipient: &str, via: &str) -> Result<u32, Error> {
+        let count = self.db.get()?.query_row(
+            "SELECT COUNT(*)
+            FROM notifications
+            WHERE network = :network
+            AND recipient = :recipient COLLATE NOCASE
+            AND via = :via COLLATE NOCASE",
+            params!(self.network, recipient, via),
+            |r| r.get(0),
+        )?;
+
+        Ok(count)
+    }
+
     pub fn remove_notification(&self, id: u32) -> Result<(), Error> {
         self.db.get()?.execute(
             "DELETE FROM notifications
@@ -116,21 +435,30 @@ impl Database {
         Ok(())
     }
 
-    pub fn check_notification(&self, nick: &str) -> Result<Vec<Notification>, Error> {
+    // with a hostmask this also finds messages left for whoever had it under
+    // another nick
+    pub fn check_notification(
+        &self,
+        nick: &str,
+        hostmask: Option<&str>,
+    ) -> Result<Vec<Notification>, Error> {
         let conn = self.db.get()?;
 
         let mut statement = conn.prepare(
-            "SELECT id, recipient, via, message
+            "SELECT id, recipient, via, message, created, hostmask
             FROM notifications
-            WHERE recipient = :nick
-            COLLATE NOCASE",
+            WHERE network = :network
+            AND (recipient = :nick COLLATE NOCASE
+                OR (:hostmask IS NOT NULL AND hostmask = :hostmask))",
         )?;
-        let rows = statement.query_map(params![nick], |r| {
+        let rows = statement.query_map(params![self.network, nick, hostmask], |r| {
             Ok(Notification {
                 id: r.get(0)?,
                 recipient: r.get(1)?,
                 via: r.get(2)?,
                 message: r.get(3)?,
+                created: r.get(4)?,
+                hostmask: r.get(5)?,
             })
         })?;
 
@@ -143,11 +471,13 @@ impl Database {
     }
 
     pub fn add_location(&self, loc: &str, entry: &Location) -> Result<(), Error> {
+        // two lookups for the same place can race each other, either will do
         self.db.get()?.execute(
             "INSERT INTO locations      (loc, lat, lon, city, country)
-            VALUES                      (:loc, :lat, :lon, :city, :country)",
+            VALUES                      (:loc, :lat, :lon, :city, :country)
+            ON CONFLICT (loc) DO NOTHING",
             params!(
-                loc,
+                location_key(loc),
                 entry.lat,
                 entry.lon,
                 entry.address.city,
@@ -167,13 +497,14 @@ impl Database {
             WHERE loc = :loc
             COLLATE NOCASE",
         )?;
-        let rows = statement.query_map(params![loc], |r| {
+        let rows = statement.query_map(params![location_key(loc)], |r| {
             Ok(Location {
                 lat: r.get(0)?,
                 lon: r.get(1)?,
                 address: Address {
                     city: r.get(2)?,
                     country: r.get(3)?,
+                    country_code: None,
                 },
             })
         })?;
@@ -188,11 +519,11 @@ impl Database {
 
     pub fn add_weather(&self, user: &str, lat: &str, lon: &str) -> Result<(), Error> {
         self.db.get()?.execute(
-            "INSERT INTO weather        (username, lat, lon)
-            VALUES                      (:user, :lat, :lon)
-            ON CONFLICT (username) DO
+            "INSERT INTO weather        (network, username, lat, lon)
+            VALUES                      (:network, :user, :lat, :lon)
+            ON CONFLICT (network, username) DO
             UPDATE SET lat=:lat,lon=:lon",
-            params!(user, lat, lon),
+            params!(self.network, user, lat, lon),
         )?;
 
         Ok(())
@@ -204,10 +535,44 @@ impl Database {
         let mut statement = conn.prepare(
             "SELECT lat, lon
             FROM weather
-            WHERE username = :user
+            WHERE network = :network AND username = :user
+            COLLATE NOCASE",
+        )?;
+        let rows =
+            statement.query_map(params![self.network, user], |r| Ok((r.get(0)?, r.get(1)?)))?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+
+        Ok(results.pop())
+    }
+
+    pub fn add_music_account(&self, user: &str, service: &str, account: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT INTO music      (network, username, service, account)
+            VALUES                  (:network, :user, :service, :account)
+            ON CONFLICT (network, username) DO
+            UPDATE SET service=:service,account=:account",
+            params!(self.network, user, service, account),
+        )?;
+
+        Ok(())
+    }
+
+    // (service, account)
+    pub fn check_music_account(&self, user: &str) -> Result<Option<(String, String)>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT service, account
+            FROM music
+            WHERE network = :network AND username = :user
             COLLATE NOCASE",
         )?;
-        let rows = statement.query_map(params![user], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        let rows =
+            statement.query_map(params![self.network, user], |r| Ok((r.get(0)?, r.get(1)?)))?;
 
         let mut results = Vec::new();
         for r in rows {
@@ -243,6 +608,221 @@ impl Database {
                 date: r.get(1)?,
                 data_0: r.get(2)?,
                 data_1: r.get(3)?,
+                chart: None,
+            })
+        })?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+
+        Ok(results.pop())
+    }
+
+    pub fn add_feed_entries(&self, url: &str, entries: &[String]) -> Result<(), Error> {
+        let mut conn = self.db.get()?;
+        let tx = conn.transaction()?;
+        for entry in entries {
+            tx.execute(
+                "INSERT OR IGNORE INTO feeds    (url, entry)
+                VALUES                          (:url, :entry)",
+                params!(url, entry),
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn check_feed_entries(&self, url: &str) -> Result<Vec<String>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT entry
+            FROM feeds
+            WHERE url = :url",
+        )?;
+        let rows = statement.query_map(params![url], |r| r.get(0))?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+
+        Ok(results)
+    }
+
+    pub fn add_message(&self, entry: &LoggedMessage) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT INTO messages   (network, channel, nick, content, time)
+            VALUES                  (:network, :channel, :nick, :content, :time)",
+            params!(
+                self.network,
+                entry.channel.to_lowercase(),
+                entry.nick,
+                entry.content,
+                entry.time
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // the most recent messages in `channel` containing `term`, newest first
+    pub fn search_messages(
+        &self,
+        channel: &str,
+        term: &str,
+        limit: u32,
+    ) -> Result<Vec<LoggedMessage>, Error> {
+        let conn = self.db.get()?;
+
+        // % and _ are wildcards for LIKE, search for them literally
+        let pattern = format!(
+            "%{}%",
+            term.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut statement = conn.prepare(
+            "SELECT channel, nick, content, time
+            FROM messages
+            WHERE network = :network AND channel = :channel
+                AND content LIKE :pattern ESCAPE '\\'
+            ORDER BY id DESC
+            LIMIT :limit",
+        )?;
+        let rows = statement.query_map(
+            params![self.network, channel.to_lowercase(), pattern, limit],
+            |r| {
+                Ok(LoggedMessage {
+                    channel: r.get(0)?,
+                    nick: r.get(1)?,
+                    content: r.get(2)?,
+                    time: r.get(3)?,
+                })
+            },
+        )?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+
+    // times are all rfc3339 in utc so they can be compared as strings
+    pub fn prune_messages(&self, before: &str) -> Result<usize, Error> {
+        let removed = self.db.get()?.execute(
+            "DELETE FROM messages
+            WHERE time < :before",
+            params!(before),
+        )?;
+
+        Ok(removed)
+    }
+
+    // returns their new total
+    pub fn add_point(&self, game: &str, nick: &str) -> Result<u32, Error> {
+        let conn = self.db.get()?;
+        conn.execute(
+            "INSERT INTO scores     (network, game, nick, points)
+            VALUES                  (:network, :game, :nick, 1)
+            ON CONFLICT (network, game, nick) DO
+            UPDATE SET points=points+1",
+            params!(self.network, game, nick),
+        )?;
+
+        let points = conn.query_row(
+            "SELECT points
+            FROM scores
+            WHERE network = :network AND game = :game AND nick = :nick",
+            params!(self.network, game, nick),
+            |r| r.get(0),
+        )?;
+        Ok(points)
+    }
+
+    pub fn top_scores(&self, game: &str, limit: u32) -> Result<Vec<(String, u32)>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT nick, points
+            FROM scores
+            WHERE network = :network AND game = :game
+            ORDER BY points DESC, nick
+            LIMIT :limit",
+        )?;
+        let rows = statement.query_map(params![self.network, game, limit], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+
+    // returns the new reminder's id
+    pub fn add_reminder(&self, entry: &Reminder) -> Result<u32, Error> {
+        let conn = self.db.get()?;
+        conn.execute(
+            "INSERT INTO reminders  (network, nick, channel, message, schedule, time, timezone, next)
+            VALUES                  (:network, :nick, :channel, :message, :schedule, :time, :timezone, :next)",
+            params!(
+                self.network,
+                entry.nick,
+                entry.channel,
+                entry.message,
+                entry.schedule,
+                entry.time,
+                entry.timezone,
+                entry.next
+            ),
+        )?;
+
+        Ok(conn.last_insert_rowid() as u32)
+    }
+
+    pub fn reminders_for(&self, nick: &str) -> Result<Vec<Reminder>, Error> {
+        self.query_reminders(
+            "SELECT id, nick, channel, message, schedule, time, timezone, next
+            FROM reminders
+            WHERE network = :network AND nick = :nick
+            ORDER BY next",
+            params![self.network, nick],
+        )
+    }
+
+    pub fn due_reminders(&self, now: i64) -> Result<Vec<Reminder>, Error> {
+        self.query_reminders(
+            "SELECT id, nick, channel, message, schedule, time, timezone, next
+            FROM reminders
+            WHERE network = :network AND next <= :now",
+            params![self.network, now],
+        )
+    }
+
+    fn query_reminders(
+        &self,
+        sql: &str,
+        params: impl r2d2_sqlite::rusqlite::Params,
+    ) -> Result<Vec<Reminder>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(params, |r| {
+            Ok(Reminder {
+                id: r.get(0)?,
+                nick: r.get(1)?,
+                channel: r.get(2)?,
+                message: r.get(3)?,
+                schedule: r.get(4)?,
+                time: r.get(5)?,
+                timezone: r.get(6)?,
+                next: r.get(7)?,
             })
         })?;
 
@@ -250,16 +830,408 @@ impl Database {
         for r in rows {
             results.push(r?);
         }
+        Ok(results)
+    }
+
+    pub fn set_reminder_next(&self, id: u32, next: i64) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "UPDATE reminders
+            SET next = :next
+            WHERE id = :id",
+            params!(next, id),
+        )?;
+
+        Ok(())
+    }
+
+    // only whoever set a reminder can remove it, returns false if there was
+    // nothing of theirs to remove
+    pub fn remove_reminder(&self, id: u32, nick: &str) -> Result<bool, Error> {
+        let removed = self.db.get()?.execute(
+            "DELETE FROM reminders
+            WHERE id = :id AND network = :network AND nick = :nick",
+            params!(id, self.network, nick),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    // returns the new alert's id
+    pub fn add_coin_alert(&self, entry: &CoinAlert) -> Result<u32, Error> {
+        let conn = self.db.get()?;
+        conn.execute(
+            "INSERT INTO coin_alerts    (network, nick, channel, coin, above, target)
+            VALUES                      (:network, :nick, :channel, :coin, :above, :target)",
+            params!(
+                self.network,
+                entry.nick,
+                entry.channel,
+                entry.coin,
+                entry.above,
+                entry.target
+            ),
+        )?;
+
+        Ok(conn.last_insert_rowid() as u32)
+    }
+
+    pub fn coin_alerts_for(&self, nick: &str) -> Result<Vec<CoinAlert>, Error> {
+        self.query_coin_alerts(
+            "SELECT id, nick, channel, coin, above, target
+            FROM coin_alerts
+            WHERE network = :network AND nick = :nick
+            ORDER BY id",
+            params![self.network, nick],
+        )
+    }
+
+    pub fn network_coin_alerts(&self) -> Result<Vec<CoinAlert>, Error> {
+        self.query_coin_alerts(
+            "SELECT id, nick, channel, coin, above, target
+            FROM coin_alerts
+            WHERE network = :network",
+            params![self.network],
+        )
+    }
+
+    fn query_coin_alerts(
+        &self,
+        sql: &str,
+        params: impl r2d2_sqlite::rusqlite::Params,
+    ) -> Result<Vec<CoinAlert>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(params, |r| {
+            Ok(CoinAlert {
+                id: r.get(0)?,
+                nick: r.get(1)?,
+                channel: r.get(2)?,
+                coin: r.get(3)?,
+                above: r.get(4)?,
+                target: r.get(5)?,
+            })
+        })?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+
+    // without a nick this is the poller clearing an alert that's gone off,
+    // otherwise only whoever set it can remove it
+    pub fn remove_coin_alert(&self, id: u32, nick: Option<&str>) -> Result<bool, Error> {
+        let conn = self.db.get()?;
+        let removed = match nick {
+            Some(nick) => conn.execute(
+                "DELETE FROM coin_alerts
+                WHERE id = :id AND network = :network AND nick = :nick",
+                params!(id, self.network, nick),
+            )?,
+            None => conn.execute(
+                "DELETE FROM coin_alerts
+                WHERE id = :id",
+                params!(id),
+            )?,
+        };
+
+        Ok(removed > 0)
+    }
+
+    pub fn add_domain(&self, channel: &str, host: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT INTO domains    (network, channel, host, count)
+            VALUES                  (:network, :channel, :host, 1)
+            ON CONFLICT (network, channel, host) DO
+            UPDATE SET count=count+1",
+            params!(self.network, channel.to_lowercase(), host.to_lowercase()),
+        )?;
+
+        Ok(())
+    }
+
+    // (channel, command, times used, when it was last used)
+    pub fn add_command_uses(&self, uses: &[(String, String, u32, String)]) -> Result<(), Error> {
+        let mut conn = self.db.get()?;
+        let tx = conn.transaction()?;
+        for (channel, command, count, last_used) in uses {
+            tx.execute(
+                "INSERT INTO command_stats  (network, channel, command, count, last_used)
+                VALUES                      (:network, :channel, :command, :count, :last_used)
+                ON CONFLICT (network, channel, command) DO
+                UPDATE SET count=count+:count,last_used=:last_used",
+                params!(
+                    self.network,
+                    channel.to_lowercase(),
+                    command,
+                    count,
+                    last_used
+                ),
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn top_commands(&self, channel: &str, limit: u32) -> Result<Vec<(String, u32)>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT command, count
+            FROM command_stats
+            WHERE network = :network AND channel = :channel
+            ORDER BY count DESC, command
+            LIMIT :limit",
+        )?;
+        let rows = statement
+            .query_map(params![self.network, channel.to_lowercase(), limit], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+
+    pub fn top_domains(&self, channel: &str, limit: u32) -> Result<Vec<(String, u32)>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT host, count
+            FROM domains
+            WHERE network = :network AND channel = :channel
+            ORDER BY count DESC, host
+            LIMIT :limit",
+        )?;
+        let rows = statement
+            .query_map(params![self.network, channel.to_lowercase(), limit], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
+        Ok(results)
+    }
+
+    pub fn add_alias(&self, scope: &str, name: &str, expansion: &str) -> Result<(), Error> {
+        self.db.get()?.execute(
+            "INSERT INTO aliases    (network, scope, name, expansion)
+            VALUES                  (:network, :scope, :name, :expansion)
+            ON CONFLICT (network, scope, name) DO
+            UPDATE SET expansion=:expansion",
+            params!(
+                self.network,
+                scope.to_lowercase(),
+                name.to_lowercase(),
+                expansion
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    // returns false if there was no such alias
+    pub fn remove_alias(&self, scope: &str, name: &str) -> Result<bool, Error> {
+        let removed = self.db.get()?.execute(
+            "DELETE FROM aliases
+            WHERE network = :network AND scope = :scope AND name = :name",
+            params!(self.network, scope.to_lowercase(), name.to_lowercase()),
+        )?;
+
+        Ok(removed > 0)
+    }
 
+    pub fn check_alias(&self, scope: &str, name: &str) -> Result<Option<String>, Error> {
+        let conn = self.db.get()?;
+
+        let mut statement = conn.prepare(
+            "SELECT expansion
+            FROM aliases
+            WHERE network = :network AND scope = :scope AND name = :name",
+        )?;
+        let rows = statement.query_map(
+            params![self.network, scope.to_lowercase(), name.to_lowercase()],
+            |r| r.get(0),
+        )?;
+
+        let mut results = Vec::new();
+        for r in rows {
+            results.push(r?);
+        }
         Ok(results.pop())
     }
 }
 
+// (name, type) of each column
+fn columns(conn: &Connection, table: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut statement = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let rows = statement.query_map([], |r| Ok((r.get(1)?, r.get(2)?)))?;
+
+    let mut results = Vec::new();
+    for r in rows {
+        results.push(r?);
+    }
+    Ok(results)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, Error> {
+    Ok(columns(conn, table)?.iter().any(|(name, _)| name == column))
+}
+
+// tables from before there were several networks are copied into a new one
+// made by `create`, sqlite can't add a column to a primary key in place. old
+// rows are all from the first network, except reminders and coin alerts which
+// stored the network's position in the config and are matched back up by it
+fn add_network(
+    conn: &Connection,
+    table: &str,
+    create: &str,
+    networks: &[String],
+) -> Result<(), Error> {
+    let existing = columns(conn, table)?;
+    let by_position = match existing.iter().find(|(name, _)| name == "network") {
+        Some((_, kind)) if kind == "TEXT" => return Ok(()),
+        Some(_) => true,
+        None => false,
+    };
+    let copied = existing
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|&name| name != "network")
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(&format!("ALTER TABLE {table} RENAME TO {table}_old"), [])?;
+    tx.execute(create, [])?;
+    if by_position {
+        tx.execute(
+            &format!(
+                "INSERT INTO {table} (network, {copied})
+                SELECT CAST(network AS TEXT), {copied} FROM {table}_old"
+            ),
+            [],
+        )?;
+        for (i, network) in networks.iter().enumerate() {
+            tx.execute(
+                &format!("UPDATE {table} SET network = :network WHERE network = :position"),
+                params!(network, i.to_string()),
+            )?;
+        }
+    } else {
+        let primary = networks.first().map(String::as_str).unwrap_or_default();
+        tx.execute(
+            &format!(
+                "INSERT INTO {table} (network, {copied})
+                SELECT :network, {copied} FROM {table}_old"
+            ),
+            params!(primary),
+        )?;
+    }
+    tx.execute(&format!("DROP TABLE {table}_old"), [])?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeenKind {
+    Message,
+    Kick,
+    Quit,
+    Join,
+    Part,
+    Nick,
+}
+
+impl SeenKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeenKind::Message => "message",
+            SeenKind::Kick => "kick",
+            SeenKind::Quit => "quit",
+            SeenKind::Join => "join",
+            SeenKind::Part => "part",
+            SeenKind::Nick => "nick",
+        }
+    }
+
+    fn parse(kind: &str) -> SeenKind {
+        match kind {
+            "kick" => SeenKind::Kick,
+            "quit" => SeenKind::Quit,
+            "join" => SeenKind::Join,
+            "part" => SeenKind::Part,
+            "nick" => SeenKind::Nick,
+            _ => SeenKind::Message,
+        }
+    }
+}
+
+// "New  York " and "new york" are the same place, older rows weren't normalised
+// but still match as long as the spacing is the same since lookups ignore case
+fn location_key(loc: &str) -> String {
+    loc.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[derive(Debug)]
+pub struct LoggedMessage {
+    pub channel: String,
+    pub nick: String,
+    pub content: String,
+    pub time: String,
+}
+
+#[derive(Debug)]
+pub struct Reminder {
+    pub id: u32,
+    pub nick: String,
+    pub channel: String,
+    pub message: String,
+    // see reminders::Schedule
+    pub schedule: String,
+    // HH:MM in `timezone`
+    pub time: String,
+    // a chrono-tz name, i.e., Europe/London or UTC
+    pub timezone: String,
+    // unix time
+    pub next: i64,
+}
+
+#[derive(Debug)]
+pub struct CoinAlert {
+    pub id: u32,
+    pub nick: String,
+    pub channel: String,
+    // as typed, i.e., btc or eth
+    pub coin: String,
+    // whether it goes off when the price is above the target or below it
+    pub above: bool,
+    pub target: f32,
+}
+
 #[derive(Debug)]
 pub struct Seen {
     pub username: String,
+    // what the user said for messages, the channel for kicks/joins/parts, the
+    // quit message for quits, and the new nick for nick changes
     pub message: String,
     pub time: String,
+    pub kind: SeenKind,
+    // where they were seen, quits and nick changes aren't tied to a channel
+    pub channel: Option<String>,
+    // user@host, this isn't known for someone who's been kicked
+    pub hostmask: Option<String>,
 }
 
 #[derive(Debug)]
@@ -268,12 +1240,19 @@ pub struct Notification {
     pub recipient: String,
     pub via: String,
     pub message: String,
+    // rfc3339
+    pub created: Option<String>,
+    // user@host of the recipient when it was left
+    pub hostmask: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Address {
     pub city: Option<String>,
     pub country: String,
+    // ISO 3166-1 alpha-2, lowercase, only used when looking a location up
+    #[serde(default)]
+    pub country_code: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -282,3 +1261,93 @@ pub struct Location {
     pub lon: String,
     pub address: Address,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh file per test, the pool hands out several connections so an
+    // in-memory database wouldn't be shared between them
+    fn temp_db(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("boot-{}-{}.sqlite", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn networks() -> Vec<String> {
+        vec!["irc.libera.chat".to_string(), "irc.oftc.net".to_string()]
+    }
+
+    #[test]
+    fn networks_are_kept_apart() {
+        let path = temp_db("networks");
+        let libera = Database::open(&path, &networks()).unwrap();
+        let oftc = libera.for_network("irc.oftc.net");
+
+        libera.add_weather("alice", "1", "2").unwrap();
+        libera.add_optout("alice").unwrap();
+        libera.add_point("trivia", "alice").unwrap();
+        assert_eq!(oftc.check_weather("alice").unwrap(), None);
+        assert!(!oftc.is_opted_out("alice").unwrap());
+        assert_eq!(oftc.add_point("trivia", "alice").unwrap(), 1);
+        assert_eq!(libera.add_point("trivia", "alice").unwrap(), 2);
+
+        let reminder = Reminder {
+            id: 0,
+            nick: "alice".to_string(),
+            channel: "#boot".to_string(),
+            message: "stretch".to_string(),
+            schedule: "daily".to_string(),
+            time: "09:00".to_string(),
+            timezone: "UTC".to_string(),
+            next: 0,
+        };
+        let id = libera.add_reminder(&reminder).unwrap();
+        assert!(oftc.due_reminders(1).unwrap().is_empty());
+        assert!(!oftc.remove_reminder(id, "alice").unwrap());
+        assert_eq!(libera.due_reminders(1).unwrap().len(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn old_rows_move_to_their_network() {
+        let path = temp_db("migrate");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE weather (
+                username TEXT PRIMARY KEY, lat TEXT NOT NULL, lon TEXT NOT NULL);
+            INSERT INTO weather VALUES ('alice', '1', '2');
+            CREATE TABLE reminders (
+                id INTEGER PRIMARY KEY, network INTEGER NOT NULL,
+                nick TEXT NOT NULL COLLATE NOCASE, channel TEXT NOT NULL,
+                message TEXT NOT NULL, schedule TEXT NOT NULL, time TEXT NOT NULL,
+                next INTEGER NOT NULL);
+            INSERT INTO reminders VALUES (1, 0, 'alice', '#a', 'one', 'daily', '09:00', 0);
+            INSERT INTO reminders VALUES (2, 1, 'bob', '#b', 'two', 'daily', '09:00', 0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let libera = Database::open(&path, &networks()).unwrap();
+        let oftc = libera.for_network("irc.oftc.net");
+        assert!(libera.check_weather("alice").unwrap().is_some());
+        assert_eq!(oftc.check_weather("alice").unwrap(), None);
+        let nicks = |db: &Database| -> Vec<String> {
+            db.due_reminders(1)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.nick)
+                .collect()
+        };
+        assert_eq!(nicks(&libera), ["alice"]);
+        assert_eq!(nicks(&oftc), ["bob"]);
+
+        // opening it again leaves everything where it is
+        let libera = Database::open(&path, &networks()).unwrap();
+        assert_eq!(nicks(&libera), ["alice"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
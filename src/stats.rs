@@ -0,0 +1,49 @@
+use crate::numbers::count;
+use chrono::Duration;
+use chrono_humanize::{Accuracy, HumanTime, Tense};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+// counters for things the bot has done since it started, these are shared
+// between the main loop and any spawned tasks so they're all atomics
+pub struct Stats {
+    started: Instant,
+    pub messages: AtomicUsize,
+    pub titles: AtomicUsize,
+    pub weather: AtomicUsize,
+    pub coins: AtomicUsize,
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats {
+            started: Instant::now(),
+            messages: AtomicUsize::new(0),
+            titles: AtomicUsize::new(0),
+            weather: AtomicUsize::new(0),
+            coins: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Stats {
+    pub fn incr(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn uptime(&self) -> String {
+        let uptime = Duration::seconds(self.started.elapsed().as_secs() as i64);
+        HumanTime::from(uptime).to_text_en(Accuracy::Precise, Tense::Present)
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Up for {} | messages: {} | titles: {} | weather: {} | coins: {}",
+            self.uptime(),
+            count(self.messages.load(Ordering::Relaxed)),
+            count(self.titles.load(Ordering::Relaxed)),
+            count(self.weather.load(Ordering::Relaxed)),
+            count(self.coins.load(Ordering::Relaxed)),
+        )
+    }
+}
@@ -0,0 +1,419 @@
+use irc::client::Sender;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+// wherever lines end up, this is the irc Sender everywhere except the tests
+pub trait Outgoing: Clone + Send + 'static {
+    fn privmsg(&self, target: &str, message: &str) -> Result<(), failure::Error>;
+}
+
+impl Outgoing for Sender {
+    fn privmsg(&self, target: &str, message: &str) -> Result<(), failure::Error> {
+        Ok(self.send_privmsg(target, message)?)
+    }
+}
+
+// a simple token bucket, each line sent costs a token and tokens refill
+// continuously up to `capacity` over `per`
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    // tokens per second
+    rate: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(lines: u32, per: Duration) -> Self {
+        let capacity = f64::from(lines.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            rate: capacity / per.as_secs_f64().max(0.001),
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+    }
+
+    async fn take(&mut self) {
+        self.refill();
+        if self.tokens < 1.0 {
+            let wait = (1.0 - self.tokens) / self.rate;
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            self.refill();
+        }
+        self.tokens -= 1.0;
+    }
+}
+
+// a target's queue is dropped after this long without messages, the bucket
+// will have refilled by then so starting over with a new one is no different
+const IDLE: Duration = Duration::from_secs(60);
+
+// paces outbound messages per target so a burst of titles or coin output
+// doesn't get the bot kicked for flooding, anything over the limit is queued
+pub struct Throttle<S = Sender> {
+    sender: S,
+    lines: u32,
+    per: Duration,
+    queues: HashMap<String, mpsc::UnboundedSender<String>>,
+}
+
+impl<S: Outgoing> Throttle<S> {
+    pub fn new(sender: S, lines: u32, seconds: u64) -> Self {
+        Self {
+            sender,
+            lines,
+            per: Duration::from_secs(seconds),
+            queues: HashMap::new(),
+        }
+    }
+
+    pub fn send(&mut self, target: String, message: String) {
+        // forget targets whose drain task has gone idle and finished
+        self.queues.retain(|_, queue| !queue.is_closed());
+
+        let key = target.to_lowercase();
+        let message = match self.queues.get(&key) {
+            Some(queue) => match queue.send(message) {
+                Ok(()) => return,
+                // the queue went idle since the retain above
+                Err(err) => err.0,
+            },
+            None => message,
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let bucket = TokenBucket::new(self.lines, self.per);
+        let idle = self.per.max(IDLE);
+        tokio::spawn(drain(self.sender.clone(), target.clone(), rx, bucket, idle));
+
+        if let Err(err) = tx.send(message) {
+            println!("Error queueing message for {}: {}", target, err);
+        }
+        self.queues.insert(key, tx);
+    }
+}
+
+async fn drain(
+    sender: impl Outgoing,
+    target: String,
+    mut rx: mpsc::UnboundedReceiver<String>,
+    mut bucket: TokenBucket,
+    idle: Duration,
+) {
+    loop {
+        let message = match tokio::time::timeout(idle, rx.recv()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(_) => {
+                // closing first means anything queued from now on fails and
+                // gets a fresh queue, then send whatever made it in before
+                rx.close();
+                match rx.try_recv() {
+                    Ok(message) => message,
+                    Err(_) => return,
+                }
+            }
+        };
+
+        bucket.take().await;
+        if let Err(err) = sender.privmsg(&target, &message) {
+            println!("Error sending message to {}: {}", target, err);
+        }
+    }
+}
+
+// lines are limited to 512 bytes including the trailing \r\n and the
+// ":nick!user@host PRIVMSG <target> :" the server prepends when relaying, we
+// don't know our own hostmask so leave a generous allowance for it
+const LINE_LIMIT: usize = 400;
+
+// splits a message into lines that fit within an IRC line, preferring to break
+// at spaces and never in the middle of a multibyte character or colour code.
+// spaces are kept as they are except the one a line is broken at, and a blank
+// line is sent as a lone space since irc has no way to send an empty one
+pub fn split_message(target: &str, message: &str) -> Vec<String> {
+    let limit = LINE_LIMIT.saturating_sub(target.len()).max(64);
+    let mut lines = Vec::new();
+
+    let message = message.trim_end_matches('\n');
+    if message.is_empty() {
+        return lines;
+    }
+
+    for part in message.split('\n') {
+        let mut line = String::new();
+        // an empty word is a leading or doubled space so it still counts
+        let mut started = false;
+        let before = lines.len();
+
+        for word in part.split(' ') {
+            let space = usize::from(started);
+            if line.len() + space + word.len() <= limit {
+                if started {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                started = true;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            started = true;
+
+            let mut word = word;
+            while word.len() > limit {
+                let at = split_point(word, limit);
+                lines.push(word[..at].to_string());
+                word = &word[at..];
+            }
+            line.push_str(word);
+        }
+
+        if !line.is_empty() {
+            lines.push(line);
+        } else if lines.len() == before {
+            lines.push(" ".to_string());
+        }
+    }
+
+    lines
+}
+
+// the largest index <= limit where it's safe to split a word
+fn split_point(word: &str, limit: usize) -> usize {
+    let bytes = word.as_bytes();
+    let mut at = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let end = match bytes[i] {
+            b'\x03' => i + colour_code_len(&bytes[i..]),
+            _ => i + word[i..].chars().next().map_or(1, char::len_utf8),
+        };
+        // always make progress even if the first token is somehow over the limit
+        if end > limit && at > 0 {
+            break;
+        }
+        at = end;
+        i = end;
+    }
+
+    at
+}
+
+// \x03 followed by up to two digits for the foreground colour and optionally
+// a comma and up to two digits for the background
+pub fn colour_code_len(code: &[u8]) -> usize {
+    let digits = |from: usize| {
+        code.iter()
+            .skip(from)
+            .take(2)
+            .take_while(|c| c.is_ascii_digit())
+            .count()
+    };
+
+    let mut len = 1;
+    let foreground = digits(len);
+    len += foreground;
+    if foreground > 0 && code.get(len) == Some(&b',') {
+        let background = digits(len + 1);
+        if background > 0 {
+            len += 1 + background;
+        }
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every line and how long after the start it went out
+    #[derive(Clone)]
+    struct Sent(mpsc::UnboundedSender<(String, String, Instant)>);
+
+    impl Outgoing for Sent {
+        fn privmsg(&self, target: &str, message: &str) -> Result<(), failure::Error> {
+            let sent = (target.to_string(), message.to_string(), Instant::now());
+            self.0.send(sent).map_err(|_| failure::err_msg("closed"))
+        }
+    }
+
+    fn throttle(lines: u32, seconds: u64) -> (Throttle<Sent>, Receiver) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let receiver = Receiver {
+            rx,
+            start: Instant::now(),
+        };
+        (Throttle::new(Sent(tx), lines, seconds), receiver)
+    }
+
+    struct Receiver {
+        rx: mpsc::UnboundedReceiver<(String, String, Instant)>,
+        start: Instant,
+    }
+
+    impl Receiver {
+        // the next line as target, message and whole seconds since the start
+        async fn next(&mut self) -> (String, String, u64) {
+            let (target, message, at) = self.rx.recv().await.unwrap();
+            (target, message, (at - self.start).as_secs())
+        }
+    }
+
+    fn line(target: &str, message: &str, at: u64) -> (String, String, u64) {
+        (target.to_string(), message.to_string(), at)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(3, Duration::from_secs(6));
+        for _ in 0..3 {
+            bucket.take().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // one token every two seconds once the burst is spent
+        bucket.take().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        bucket.take().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+
+        // never more than the capacity however long it's left
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        let rested = Instant::now();
+        for _ in 0..4 {
+            bucket.take().await;
+        }
+        assert_eq!(rested.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn burst_then_refill() {
+        let (mut throttle, mut rx) = throttle(3, 6);
+        for i in 1..=5 {
+            throttle.send("#boot".to_string(), i.to_string());
+        }
+
+        assert_eq!(rx.next().await, line("#boot", "1", 0));
+        assert_eq!(rx.next().await, line("#boot", "2", 0));
+        assert_eq!(rx.next().await, line("#boot", "3", 0));
+        assert_eq!(rx.next().await, line("#boot", "4", 2));
+        assert_eq!(rx.next().await, line("#boot", "5", 4));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn targets_are_separate() {
+        let (mut throttle, mut rx) = throttle(1, 10);
+        throttle.send("#boot".to_string(), "1".to_string());
+        throttle.send("#boot".to_string(), "2".to_string());
+        throttle.send("niall".to_string(), "hi".to_string());
+        // the same target whatever the case
+        throttle.send("#BOOT".to_string(), "3".to_string());
+
+        assert_eq!(rx.next().await, line("#boot", "1", 0));
+        assert_eq!(rx.next().await, line("niall", "hi", 0));
+        assert_eq!(rx.next().await, line("#boot", "2", 10));
+        assert_eq!(rx.next().await, line("#boot", "3", 20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_queues_are_dropped() {
+        let (mut throttle, mut rx) = throttle(1, 10);
+        throttle.send("#boot".to_string(), "1".to_string());
+        assert_eq!(rx.next().await, line("#boot", "1", 0));
+        assert!(!throttle.queues["#boot"].is_closed());
+
+        tokio::time::sleep(IDLE + Duration::from_secs(1)).await;
+        assert!(throttle.queues["#boot"].is_closed());
+
+        // and a new one takes its place, with a full bucket
+        throttle.send("#boot".to_string(), "2".to_string());
+        assert_eq!(throttle.queues.len(), 1);
+        assert!(!throttle.queues["#boot"].is_closed());
+        assert_eq!(rx.next().await, line("#boot", "2", 61));
+    }
+
+    // the limit for a line to #boot
+    const BOOT: usize = LINE_LIMIT - 5;
+
+    #[test]
+    fn splits_at_spaces() {
+        assert_eq!(split_message("#boot", "hello world"), ["hello world"]);
+        let words = "word ".repeat(100);
+        let lines = split_message("#boot", words.trim_end());
+        assert!(lines.iter().all(|l| l.len() <= BOOT && !l.ends_with(' ')));
+        assert_eq!(lines.join(" "), words.trim_end());
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        for text in ["日本語".repeat(100), "😀".repeat(200)] {
+            let lines = split_message("#boot", &text);
+            assert!(lines.len() > 1);
+            assert!(lines.iter().all(|l| l.len() <= BOOT));
+            assert_eq!(lines.concat(), text);
+        }
+        // 395 isn't a multiple of 3 or 4 so a naive split would land mid-char
+        assert_eq!(split_message("#boot", &"日".repeat(200))[0].len(), 393);
+        assert_eq!(split_message("#boot", &"😀".repeat(200))[0].len(), 392);
+    }
+
+    #[test]
+    fn colour_codes() {
+        assert_eq!(colour_code_len(b"\x03"), 1);
+        assert_eq!(colour_code_len(b"\x034hi"), 2);
+        assert_eq!(colour_code_len(b"\x0304,12hi"), 6);
+        assert_eq!(colour_code_len(b"\x0304,hi"), 3);
+        assert_eq!(colour_code_len(b"\x03123"), 3);
+
+        // the code would straddle the limit so it starts the next line
+        let word = format!("{}\x0304,12red", "a".repeat(BOOT - 3));
+        let lines = split_message("#boot", &word);
+        assert_eq!(lines, ["a".repeat(BOOT - 3), "\x0304,12red".to_string()]);
+    }
+
+    #[test]
+    fn oversized_words() {
+        let lines = split_message("#boot", &"x".repeat(1000));
+        let lengths: Vec<_> = lines.iter().map(String::len).collect();
+        assert_eq!(lengths, [BOOT, BOOT, 1000 - 2 * BOOT]);
+
+        let lines = split_message("#boot", &format!("short {}", "x".repeat(BOOT)));
+        assert_eq!(lines, ["short".to_string(), "x".repeat(BOOT)]);
+    }
+
+    #[test]
+    fn longer_targets_get_shorter_lines() {
+        let target = format!("#{}", "a".repeat(99));
+        let lines = split_message(&target, &"x".repeat(1000));
+        assert_eq!(lines[0].len(), LINE_LIMIT - 100);
+        // but never too short to be useful
+        let target = "#".repeat(500);
+        assert_eq!(split_message(&target, &"x".repeat(100))[0].len(), 64);
+    }
+
+    #[test]
+    fn newlines_and_spaces() {
+        assert_eq!(split_message("#boot", "one\ntwo"), ["one", "two"]);
+        assert_eq!(split_message("#boot", "one\n\ntwo"), ["one", " ", "two"]);
+        assert_eq!(split_message("#boot", "one\n"), ["one"]);
+        assert!(split_message("#boot", "").is_empty());
+        assert_eq!(split_message("#boot", "  indented"), ["  indented"]);
+        assert_eq!(split_message("#boot", "a  b"), ["a  b"]);
+    }
+}
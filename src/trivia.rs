@@ -0,0 +1,169 @@
+use crate::http::Req;
+use failure::{bail, Error};
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use std::collections::HashSet;
+use urlencoding::decode;
+
+// seconds before the answer is given away
+pub const TIMEOUT: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct Question {
+    pub category: String,
+    pub question: String,
+    pub answer: String,
+    // every possible answer, shuffled, including the right one
+    pub choices: Vec<String>,
+}
+
+impl Question {
+    pub fn ask(&self) -> String {
+        format!(
+            "Trivia ({}): {} [{}] (one guess each)",
+            self.category,
+            self.question,
+            self.choices.join(" / ")
+        )
+    }
+
+    pub fn is_answer(&self, guess: &str) -> bool {
+        normalise(guess) == normalise(&self.answer)
+    }
+
+    fn is_choice(&self, guess: &str) -> bool {
+        let guess = normalise(guess);
+        self.choices.iter().any(|c| normalise(c) == guess)
+    }
+}
+
+// a question that's been asked in a channel but not answered yet, the id is
+// used to tell whether a timeout is for this round or one that's long gone
+pub struct Round {
+    pub id: u64,
+    pub question: Question,
+    // lowercase nicks of everyone who's used their guess
+    guessed: HashSet<String>,
+}
+
+impl Round {
+    pub fn new(id: u64, question: Question) -> Round {
+        Round {
+            id,
+            question,
+            guessed: HashSet::new(),
+        }
+    }
+
+    // everyone gets one guess a round, otherwise saying every choice would
+    // always win, anything that isn't one of the choices is just chatter and
+    // doesn't count
+    pub fn guess(&mut self, nick: &str, guess: &str) -> bool {
+        if !self.question.is_choice(guess) {
+            return false;
+        }
+        self.guessed.insert(nick.to_lowercase()) && self.question.is_answer(guess)
+    }
+}
+
+// "The Beatles!" and "the  beatles" are the same answer
+fn normalise(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .join(" ")
+        .to_lowercase()
+}
+
+// https://opentdb.com/api_config.php
+#[derive(Deserialize)]
+struct OpenTdbResponse {
+    response_code: u8,
+    results: Vec<OpenTdbQuestion>,
+}
+
+#[derive(Deserialize)]
+struct OpenTdbQuestion {
+    category: String,
+    question: String,
+    correct_answer: String,
+    incorrect_answers: Vec<String>,
+}
+
+pub async fn fetch_question(req: &Req) -> Result<Question, Error> {
+    // url encoded responses save having to deal with html entities
+    let url = "https://opentdb.com/api.php?amount=1&encode=url3986";
+    let response: OpenTdbResponse = req.get(url).send().await?.json().await?;
+
+    if response.response_code != 0 {
+        bail!("open trivia db returned {}", response.response_code);
+    }
+    let Some(q) = response.results.into_iter().next() else {
+        bail!("open trivia db didn't return a question");
+    };
+
+    let decode = |s: &str| decode(s).map(|d| d.into_owned());
+    let answer = decode(&q.correct_answer)?;
+    let mut choices = q
+        .incorrect_answers
+        .iter()
+        .map(|a| decode(a))
+        .collect::<Result<Vec<_>, _>>()?;
+    choices.push(answer.to_string());
+    choices.shuffle(&mut rand::thread_rng());
+
+    Ok(Question {
+        category: decode(&q.category)?,
+        question: decode(&q.question)?,
+        answer,
+        choices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round() -> Round {
+        let question = Question {
+            category: "Music".to_string(),
+            question: "Who recorded Abbey Road?".to_string(),
+            answer: "The Beatles".to_string(),
+            choices: vec![
+                "The Rolling Stones".to_string(),
+                "The Beatles".to_string(),
+                "The Who".to_string(),
+                "The Kinks".to_string(),
+            ],
+        };
+        Round::new(1, question)
+    }
+
+    #[test]
+    fn answers_are_normalised() {
+        let round = round();
+        assert!(round.question.is_answer("the beatles!"));
+        assert!(round.question.is_answer("  The   Beatles "));
+        assert!(!round.question.is_answer("beatles"));
+    }
+
+    #[test]
+    fn one_guess_per_round() {
+        let mut round = round();
+        assert!(!round.guess("alice", "the who"));
+        // that was alice's one guess
+        assert!(!round.guess("alice", "the beatles"));
+        assert!(!round.guess("ALICE", "the beatles"));
+        assert!(round.guess("bob", "The Beatles"));
+    }
+
+    #[test]
+    fn chatter_isnt_a_guess() {
+        let mut round = round();
+        assert!(!round.guess("alice", "no idea, the stones maybe?"));
+        assert!(!round.guess("alice", "hmm"));
+        assert!(round.guess("alice", "the beatles"));
+    }
+}